@@ -0,0 +1,763 @@
+//! Local mirror of the chat sessions and messages of the Coco servers, so
+//! that past conversations open instantly and can be browsed offline.
+//!
+//! The hits returned by `/chat/_history` and `/chat/{id}/_history` are stored
+//! as is, in one file per server for the sessions and one file per session
+//! for its messages. Reads are served from the mirror, and the mirror is
+//! synced with the server in the background, see [`sync_sessions`] and
+//! [`sync_session_messages`]. Every [`FULL_SYNC_INTERVAL`], the whole session
+//! history is fetched to drop the sessions deleted on the server.
+
+use crate::common;
+use crate::common::error::CocoError;
+use crate::server::http_client::HttpClient;
+use crate::server::secrets::write_private_file;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::Manager;
+
+const MIRROR_DIR_NAME: &str = "chat_history";
+const SESSIONS_FILE_NAME: &str = "sessions.json";
+/// Number of hits fetched per request when syncing.
+const SYNC_PAGE_SIZE: usize = 100;
+/// How often the whole session history of a server is fetched, rather than
+/// only the sessions updated since the last sync, to find out about the
+/// sessions deleted on the server or by another client.
+const FULL_SYNC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoredSessions {
+    /// Most recently updated first.
+    hits: Vec<Value>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoredMessages {
+    /// The `updated` field of the session when its messages were synced.
+    session_updated: Option<String>,
+    /// Oldest first.
+    hits: Vec<Value>,
+}
+
+pub(crate) struct ChatMirror {
+    dir: PathBuf,
+    /// Held while reading then writing a file.
+    lock: Mutex<()>,
+    /// When the sessions of a server were last fully synced, by server ID.
+    last_full_syncs: Mutex<HashMap<String, Instant>>,
+    /// The servers being synced in the background.
+    syncing_servers: Mutex<HashSet<String>>,
+    /// The lowercased text of the messages of each session, by server ID then
+    /// session ID, so that searching does not read every message file. Loaded
+    /// on the first search of a server, then kept up to date.
+    message_texts: Mutex<HashMap<String, HashMap<String, String>>>,
+}
+
+/// A background sync of a server in progress, see [`ChatMirror::start_sync`].
+pub(crate) struct SyncGuard<'a> {
+    mirror: &'a ChatMirror,
+    server_id: String,
+}
+
+impl Drop for SyncGuard<'_> {
+    fn drop(&mut self) {
+        self.mirror
+            .syncing_servers
+            .lock()
+            .unwrap()
+            .remove(&self.server_id);
+    }
+}
+
+/// The changes made to the mirror by [`sync_sessions`].
+#[derive(Debug, Default)]
+pub(crate) struct SyncedSessions {
+    /// The sessions added or updated.
+    pub changed_ids: Vec<String>,
+    /// The sessions no longer on the server, removed from the mirror.
+    pub removed_ids: Vec<String>,
+}
+
+static CHAT_MIRROR: OnceLock<Option<ChatMirror>> = OnceLock::new();
+
+/// The chat mirror of the app, opened on first use, `None` if it cannot be.
+pub(crate) fn chat_mirror() -> Option<&'static ChatMirror> {
+    CHAT_MIRROR
+        .get_or_init(|| {
+            let app_handle = crate::GLOBAL_TAURI_APP_HANDLE
+                .get()
+                .expect("global tauri app handle not set");
+            let dir = match app_handle.path().app_data_dir() {
+                Ok(dir) => dir.join(MIRROR_DIR_NAME),
+                Err(e) => {
+                    log::error!("failed to get app data dir: {}", e);
+                    return None;
+                }
+            };
+
+            ChatMirror::open(&dir)
+                .map_err(|e| log::error!("failed to open chat mirror {:?}: {}", dir, e))
+                .ok()
+        })
+        .as_ref()
+}
+
+fn hex_digest(data: &str) -> String {
+    digest::digest(&digest::SHA256, data.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn hit_id(hit: &Value) -> Option<&str> {
+    hit.get("_id").and_then(Value::as_str)
+}
+
+fn hit_field<'a>(hit: &'a Value, field: &str) -> Option<&'a str> {
+    hit.get("_source")
+        .and_then(|source| source.get(field))
+        .and_then(Value::as_str)
+}
+
+/// Insert or replace `new_hits` in `hits` (by `_id`), then sort them by
+/// `sort_field`, ascending or not. Return the number of hits added or changed.
+fn merge_hits(
+    hits: &mut Vec<Value>,
+    new_hits: Vec<Value>,
+    sort_field: &str,
+    ascending: bool,
+) -> usize {
+    let mut changed = 0;
+    for new_hit in new_hits {
+        let Some(id) = hit_id(&new_hit) else {
+            continue;
+        };
+        match hits.iter_mut().find(|hit| hit_id(hit) == Some(id)) {
+            Some(hit) if *hit == new_hit => {}
+            Some(hit) => {
+                *hit = new_hit;
+                changed += 1;
+            }
+            None => {
+                hits.push(new_hit);
+                changed += 1;
+            }
+        }
+    }
+
+    // Timestamps are RFC 3339, they sort lexicographically
+    hits.sort_by(|a, b| {
+        let ordering = hit_field(a, sort_field).cmp(&hit_field(b, sort_field));
+        if ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+
+    changed
+}
+
+/// Whether all the `terms` appear in `texts`, both lowercased.
+fn matches_terms<'a>(terms: &[String], texts: impl Iterator<Item = &'a str> + Clone) -> bool {
+    terms
+        .iter()
+        .all(|term| texts.clone().any(|text| text.contains(term.as_str())))
+}
+
+/// The lowercased text of the message hits `hits`, one message per line.
+fn message_text(hits: &[Value]) -> String {
+    hits.iter()
+        .filter_map(|message| hit_field(message, "message"))
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A body shaped like the responses of the server, which is what the frontend
+/// expects.
+fn search_response_body(hits: &[Value], from: usize, size: usize) -> String {
+    let page: Vec<&Value> = hits.iter().skip(from).take(size).collect();
+    serde_json::json!({
+        "took": 0,
+        "timed_out": false,
+        "hits": {
+            "total": { "value": hits.len(), "relation": "eq" },
+            "max_score": null,
+            "hits": page,
+        }
+    })
+    .to_string()
+}
+
+pub(crate) fn parse_hits(body: &str) -> Result<Vec<Value>, CocoError> {
+    let response: Value = serde_json::from_str(body)?;
+    match response.pointer("/hits/hits") {
+        Some(Value::Array(hits)) => Ok(hits.clone()),
+        Some(_) => Err(CocoError::Parse("Invalid hits in chat history".to_string())),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl ChatMirror {
+    pub(crate) fn open(dir: &Path) -> Result<Self, CocoError> {
+        fs::create_dir_all(dir)
+            .map_err(|e| CocoError::Internal(format!("Failed to create {:?}: {}", dir, e)))?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            lock: Mutex::new(()),
+            last_full_syncs: Mutex::new(HashMap::new()),
+            syncing_servers: Mutex::new(HashSet::new()),
+            message_texts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn server_dir(&self, server_id: &str) -> PathBuf {
+        self.dir.join(&hex_digest(server_id)[..16])
+    }
+
+    fn sessions_path(&self, server_id: &str) -> PathBuf {
+        self.server_dir(server_id).join(SESSIONS_FILE_NAME)
+    }
+
+    fn messages_path(&self, server_id: &str, session_id: &str) -> PathBuf {
+        self.server_dir(server_id)
+            .join(format!("{}.json", hex_digest(session_id)))
+    }
+
+    /// The content of `path`, `None` if it has never been written.
+    fn read<T: for<'de> Deserialize<'de>>(path: &Path) -> Option<T> {
+        let data = fs::read(path).ok()?;
+        serde_json::from_slice(&data)
+            .map_err(|e| log::warn!("ignoring invalid chat mirror file {:?}: {}", path, e))
+            .ok()
+    }
+
+    fn write<T: Serialize>(path: &Path, value: &T) -> Result<(), CocoError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| CocoError::Internal(format!("Failed to create {:?}: {}", dir, e)))?;
+        }
+        write_private_file(path, &serde_json::to_vec(value)?)
+    }
+
+    /// Whether the sessions of server `server_id` have been mirrored.
+    pub(crate) fn has_sessions(&self, server_id: &str) -> bool {
+        self.sessions_path(server_id).exists()
+    }
+
+    /// Whether the messages of session `session_id` have been mirrored.
+    pub(crate) fn has_messages(&self, server_id: &str, session_id: &str) -> bool {
+        self.messages_path(server_id, session_id).exists()
+    }
+
+    fn sessions(&self, server_id: &str) -> Vec<Value> {
+        Self::read::<StoredSessions>(&self.sessions_path(server_id))
+            .map(|stored| stored.hits)
+            .unwrap_or_default()
+    }
+
+    fn messages(&self, server_id: &str, session_id: &str) -> StoredMessages {
+        Self::read(&self.messages_path(server_id, session_id)).unwrap_or_default()
+    }
+
+    /// Merge the session hits `hits` into the mirror, return the IDs of the
+    /// sessions added or updated.
+    fn merge_sessions(&self, server_id: &str, hits: Vec<Value>) -> Result<Vec<String>, CocoError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut stored = self.sessions(server_id);
+        let changed_ids: Vec<String> = hits
+            .iter()
+            .filter(|hit| !stored.contains(hit))
+            .filter_map(|hit| hit_id(hit).map(str::to_string))
+            .collect();
+        merge_hits(&mut stored, hits, "updated", false);
+
+        Self::write(
+            &self.sessions_path(server_id),
+            &StoredSessions { hits: stored },
+        )?;
+        Ok(changed_ids)
+    }
+
+    fn merge_messages(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        session_updated: Option<String>,
+        hits: Vec<Value>,
+    ) -> Result<(), CocoError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut stored = self.messages(server_id, session_id);
+        merge_hits(&mut stored.hits, hits, "created", true);
+        stored.session_updated = session_updated;
+
+        Self::write(&self.messages_path(server_id, session_id), &stored)?;
+        if let Some(texts) = self.message_texts.lock().unwrap().get_mut(server_id) {
+            texts.insert(session_id.to_string(), message_text(&stored.hits));
+        }
+        Ok(())
+    }
+
+    /// The hit of session `session_id`, as mirrored.
+    pub(crate) fn session(&self, server_id: &str, session_id: &str) -> Option<Value> {
+        self.sessions(server_id)
+            .into_iter()
+            .find(|hit| hit_id(hit) == Some(session_id))
+    }
+
+    /// The `updated` field of session `session_id`, as mirrored.
+    fn session_updated(&self, server_id: &str, session_id: &str) -> Option<String> {
+        self.session(server_id, session_id)
+            .as_ref()
+            .and_then(|hit| hit_field(hit, "updated"))
+            .map(str::to_string)
+    }
+
+    /// Whether the messages of session `session_id` were synced since the
+    /// mirrored session was last updated. Only as accurate as the mirrored
+    /// sessions.
+    fn are_messages_synced(&self, server_id: &str, session_id: &str) -> bool {
+        let session_updated = self.session_updated(server_id, session_id);
+        session_updated.is_some()
+            && self.has_messages(server_id, session_id)
+            && self.messages(server_id, session_id).session_updated == session_updated
+    }
+
+    /// The mirrored sessions of server `server_id` containing all the words
+    /// of `query` in their title, summary or messages, or all of them if
+    /// there is no query. Most recently updated first.
+    pub(crate) fn search_sessions(&self, server_id: &str, query: Option<&str>) -> Vec<Value> {
+        let sessions = self.sessions(server_id);
+        let terms: Vec<String> = query
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+        if terms.is_empty() {
+            return sessions;
+        }
+
+        // Held so that the messages are not merged while being loaded
+        let _guard = self.lock.lock().unwrap();
+        let mut message_texts = self.message_texts.lock().unwrap();
+        let texts = message_texts
+            .entry(server_id.to_string())
+            .or_insert_with(|| {
+                sessions
+                    .iter()
+                    .filter_map(hit_id)
+                    .map(|session_id| {
+                        let messages = self.messages(server_id, session_id).hits;
+                        (session_id.to_string(), message_text(&messages))
+                    })
+                    .collect()
+            });
+
+        sessions
+            .into_iter()
+            .filter(|session| {
+                let session_texts: Vec<String> = ["title", "summary"]
+                    .into_iter()
+                    .filter_map(|field| hit_field(session, field))
+                    .map(str::to_lowercase)
+                    .collect();
+                let messages_text = hit_id(session)
+                    .and_then(|session_id| texts.get(session_id))
+                    .map(String::as_str);
+                matches_terms(
+                    &terms,
+                    session_texts
+                        .iter()
+                        .map(String::as_str)
+                        .chain(messages_text),
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn chat_history(
+        &self,
+        server_id: &str,
+        from: usize,
+        size: usize,
+        query: Option<&str>,
+    ) -> String {
+        search_response_body(&self.search_sessions(server_id, query), from, size)
+    }
+
+    pub(crate) fn session_chat_history(
+        &self,
+        server_id: &str,
+        session_id: &str,
+        from: usize,
+        size: usize,
+    ) -> String {
+        search_response_body(&self.messages(server_id, session_id).hits, from, size)
+    }
+
+    /// The mirrored messages of session `session_id`, oldest first.
+    pub(crate) fn session_messages(&self, server_id: &str, session_id: &str) -> Vec<Value> {
+        self.messages(server_id, session_id).hits
+    }
+
+    fn remove_messages(&self, server_id: &str, session_id: &str) -> Result<(), CocoError> {
+        if let Some(texts) = self.message_texts.lock().unwrap().get_mut(server_id) {
+            texts.remove(session_id);
+        }
+
+        let path = self.messages_path(server_id, session_id);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(CocoError::Internal(format!(
+                "Failed to remove {:?}: {}",
+                path, e
+            ))),
+        }
+    }
+
+    /// Remove session `session_id` and its messages from the mirror.
+    pub(crate) fn remove_session(
+        &self,
+        server_id: &str,
+        session_id: &str,
+    ) -> Result<(), CocoError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut stored = self.sessions(server_id);
+        stored.retain(|hit| hit_id(hit) != Some(session_id));
+        Self::write(
+            &self.sessions_path(server_id),
+            &StoredSessions { hits: stored },
+        )?;
+
+        self.remove_messages(server_id, session_id)
+    }
+
+    /// Remove the sessions that are not in `session_ids`, and their messages,
+    /// from the mirror. Return the IDs of the sessions removed.
+    fn retain_sessions(
+        &self,
+        server_id: &str,
+        session_ids: &HashSet<String>,
+    ) -> Result<Vec<String>, CocoError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let (kept, removed): (Vec<Value>, Vec<Value>) = self
+            .sessions(server_id)
+            .into_iter()
+            .partition(|hit| hit_id(hit).is_some_and(|id| session_ids.contains(id)));
+        if removed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Self::write(
+            &self.sessions_path(server_id),
+            &StoredSessions { hits: kept },
+        )?;
+
+        let removed_ids: Vec<String> = removed
+            .iter()
+            .filter_map(|hit| hit_id(hit).map(str::to_string))
+            .collect();
+        for session_id in removed_ids.iter() {
+            self.remove_messages(server_id, session_id)?;
+        }
+        Ok(removed_ids)
+    }
+
+    /// Whether the sessions of server `server_id` have not been fully synced
+    /// for [`FULL_SYNC_INTERVAL`].
+    fn is_full_sync_due(&self, server_id: &str, now: Instant) -> bool {
+        self.last_full_syncs
+            .lock()
+            .unwrap()
+            .get(server_id)
+            .map_or(true, |last_full_sync| {
+                now.duration_since(*last_full_sync) >= FULL_SYNC_INTERVAL
+            })
+    }
+
+    /// Start a background sync of server `server_id`, it lasts until the
+    /// guard is dropped. `None` if one is already in progress.
+    pub(crate) fn start_sync(&self, server_id: &str) -> Option<SyncGuard<'_>> {
+        if !self
+            .syncing_servers
+            .lock()
+            .unwrap()
+            .insert(server_id.to_string())
+        {
+            return None;
+        }
+
+        Some(SyncGuard {
+            mirror: self,
+            server_id: server_id.to_string(),
+        })
+    }
+
+    /// Remove everything mirrored from server `server_id`.
+    pub(crate) fn purge(&self, server_id: &str) -> Result<(), CocoError> {
+        let _guard = self.lock.lock().unwrap();
+
+        self.last_full_syncs.lock().unwrap().remove(server_id);
+        self.message_texts.lock().unwrap().remove(server_id);
+        let dir = self.server_dir(server_id);
+        match fs::remove_dir_all(&dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(CocoError::Internal(format!(
+                "Failed to remove {:?}: {}",
+                dir, e
+            ))),
+        }
+    }
+}
+
+async fn fetch_hits(
+    server_id: &str,
+    path: &str,
+    from: usize,
+    size: usize,
+) -> Result<Vec<Value>, CocoError> {
+    let mut query_params: HashMap<String, Value> = HashMap::new();
+    if from > 0 {
+        query_params.insert("from".to_string(), from.into());
+    }
+    query_params.insert("size".to_string(), size.into());
+
+    let response = HttpClient::get(server_id, path, Some(query_params)).await?;
+    let body = common::http::get_response_body_text(response).await?;
+    parse_hits(&body)
+}
+
+/// Sync the sessions of server `server_id`.
+///
+/// The history is sorted by update time, so it is fetched until a page only
+/// contains sessions already mirrored. Unless a full sync is due, then it is
+/// fetched to the end, and the sessions missing from it are removed.
+pub(crate) async fn sync_sessions(
+    mirror: &ChatMirror,
+    server_id: &str,
+) -> Result<SyncedSessions, CocoError> {
+    let started_at = Instant::now();
+    let is_full_sync = mirror.is_full_sync_due(server_id, started_at);
+
+    let mut synced = SyncedSessions::default();
+    let mut listed_ids = HashSet::new();
+    let mut from = 0;
+    loop {
+        let hits = fetch_hits(server_id, "/chat/_history", from, SYNC_PAGE_SIZE).await?;
+        let fetched = hits.len();
+        listed_ids.extend(
+            hits.iter()
+                .filter_map(|hit| hit_id(hit).map(str::to_string)),
+        );
+
+        let changed_in_page = mirror.merge_sessions(server_id, hits)?;
+        let is_up_to_date = changed_in_page.is_empty();
+        synced.changed_ids.extend(changed_in_page);
+
+        if fetched < SYNC_PAGE_SIZE || (is_up_to_date && !is_full_sync) {
+            break;
+        }
+        from += fetched;
+    }
+
+    if is_full_sync {
+        synced.removed_ids = mirror.retain_sessions(server_id, &listed_ids)?;
+        mirror
+            .last_full_syncs
+            .lock()
+            .unwrap()
+            .insert(server_id.to_string(), started_at);
+    }
+
+    Ok(synced)
+}
+
+/// Sync the messages of session `session_id`, unless the session has not been
+/// updated since they were last synced. Return whether they were synced.
+pub(crate) async fn sync_session_messages(
+    mirror: &ChatMirror,
+    server_id: &str,
+    session_id: &str,
+) -> Result<bool, CocoError> {
+    if mirror.are_messages_synced(server_id, session_id) {
+        return Ok(false);
+    }
+    let session_updated = mirror.session_updated(server_id, session_id);
+
+    let path = format!("/chat/{}/_history", session_id);
+    let mut hits = Vec::new();
+    loop {
+        let page = fetch_hits(server_id, &path, hits.len(), SYNC_PAGE_SIZE).await?;
+        let fetched = page.len();
+        hits.extend(page);
+        if fetched < SYNC_PAGE_SIZE {
+            break;
+        }
+    }
+
+    mirror.merge_messages(server_id, session_id, session_updated, hits)?;
+    Ok(true)
+}
+
+#[test]
+fn test_merge_hits() {
+    let hit = |id: &str, updated: &str, title: &str| serde_json::json!({ "_id": id, "_source": { "updated": updated, "title": title } });
+
+    let mut hits = vec![hit("a", "2025-01-02T00:00:00Z", "A")];
+    let changed = merge_hits(
+        &mut hits,
+        vec![
+            hit("a", "2025-01-02T00:00:00Z", "A"),
+            hit("b", "2025-01-03T00:00:00Z", "B"),
+        ],
+        "updated",
+        false,
+    );
+    assert_eq!(changed, 1);
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hit_id(&hits[0]), Some("b"));
+
+    let changed = merge_hits(
+        &mut hits,
+        vec![hit("a", "2025-01-04T00:00:00Z", "Renamed")],
+        "updated",
+        false,
+    );
+    assert_eq!(changed, 1);
+    assert_eq!(hit_field(&hits[0], "title"), Some("Renamed"));
+}
+
+#[test]
+fn test_chat_mirror_search() {
+    let dir = std::env::temp_dir().join(format!(
+        "coco-chat-mirror-test-{}",
+        pizza_common::utils::uuid::Uuid::new()
+    ));
+    let mirror = ChatMirror::open(&dir).unwrap();
+
+    let session = |id: &str, title: &str| serde_json::json!({ "_id": id, "_source": { "title": title, "updated": "2025-01-01T00:00:00Z" } });
+    let message = |id: &str, text: &str| serde_json::json!({ "_id": id, "_source": { "message": text, "created": "2025-01-01T00:00:00Z" } });
+
+    mirror
+        .merge_sessions(
+            "server",
+            vec![session("s1", "Deploy plan"), session("s2", "Lunch")],
+        )
+        .unwrap();
+    mirror
+        .merge_messages(
+            "server",
+            "s2",
+            None,
+            vec![message("m1", "How do I rotate the TLS certificate?")],
+        )
+        .unwrap();
+
+    assert_eq!(mirror.search_sessions("server", None).len(), 2);
+    let ids = |hits: Vec<Value>| -> Vec<String> {
+        hits.iter()
+            .filter_map(|hit| hit_id(hit).map(str::to_string))
+            .collect()
+    };
+    assert_eq!(
+        ids(mirror.search_sessions("server", Some("deploy"))),
+        ["s1"]
+    );
+    assert_eq!(
+        ids(mirror.search_sessions("server", Some("TLS rotate"))),
+        ["s2"]
+    );
+    assert!(mirror
+        .search_sessions("server", Some("tls deploy"))
+        .is_empty());
+    // Messages merged after the first search are searchable
+    mirror
+        .merge_messages(
+            "server",
+            "s1",
+            None,
+            vec![message("m2", "Roll out the new Certificate")],
+        )
+        .unwrap();
+    assert_eq!(
+        ids(mirror.search_sessions("server", Some("certificate"))),
+        ["s1", "s2"]
+    );
+    assert!(mirror.search_sessions("another server", None).is_empty());
+
+    let body: Value =
+        serde_json::from_str(&mirror.session_chat_history("server", "s2", 0, 10)).unwrap();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+
+    assert!(mirror.is_full_sync_due("server", Instant::now()));
+    let listed_ids = HashSet::from(["s2".to_string()]);
+    assert_eq!(
+        mirror.retain_sessions("server", &listed_ids).unwrap(),
+        ["s1"]
+    );
+    assert!(mirror.session("server", "s1").is_none());
+    assert!(mirror.has_messages("server", "s2"));
+
+    let sync_guard = mirror.start_sync("server");
+    assert!(sync_guard.is_some());
+    assert!(mirror.start_sync("server").is_none());
+    assert!(mirror.start_sync("another server").is_some());
+    drop(sync_guard);
+    assert!(mirror.start_sync("server").is_some());
+
+    mirror.remove_session("server", "s2").unwrap();
+    assert!(!mirror.has_messages("server", "s2"));
+    mirror.purge("server").unwrap();
+    assert!(!mirror.has_sessions("server"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_messages_synced_against_stale_session() {
+    let dir = std::env::temp_dir().join(format!(
+        "coco-chat-mirror-test-{}",
+        pizza_common::utils::uuid::Uuid::new()
+    ));
+    let mirror = ChatMirror::open(&dir).unwrap();
+
+    let session = |updated: &str| serde_json::json!({ "_id": "s1", "_source": { "title": "Deploy plan", "updated": updated } });
+
+    assert!(!mirror.are_messages_synced("server", "s1"));
+    mirror
+        .merge_sessions("server", vec![session("2025-01-01T00:00:00Z")])
+        .unwrap();
+    mirror
+        .merge_messages(
+            "server",
+            "s1",
+            mirror.session_updated("server", "s1"),
+            Vec::new(),
+        )
+        .unwrap();
+    assert!(mirror.are_messages_synced("server", "s1"));
+
+    // The session was continued on another client: the messages look synced
+    // until the sessions are, which is why they are synced before exporting
+    mirror
+        .merge_sessions("server", vec![session("2025-01-02T00:00:00Z")])
+        .unwrap();
+    assert!(!mirror.are_messages_synced("server", "s1"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,467 @@
+//! Export of a chat session to a file, in one of the [`ExportFormat`]s.
+
+use super::chat_mirror::{
+    chat_mirror, parse_hits, sync_session_messages, sync_sessions, ChatMirror,
+};
+use crate::common::error::CocoError;
+use crate::server::attachment::{get_attachment, AttachmentSource};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt::Write;
+use tauri::{AppHandle, Runtime};
+
+/// Number of messages fetched per request.
+const EXPORT_PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Markdown,
+    /// The messages and attachments as returned by the server, nothing is
+    /// lost.
+    Json,
+    /// A standalone page, without external resources.
+    Html,
+}
+
+/// The conversation being exported.
+struct ExportedChat {
+    session_id: String,
+    title: Option<String>,
+    /// Message hits, oldest first.
+    messages: Vec<Value>,
+    attachments: Vec<AttachmentSource>,
+}
+
+/// A source cited by a reply of the assistant.
+struct Citation {
+    title: String,
+    url: String,
+}
+
+fn source_str<'a>(hit: &'a Value, field: &str) -> Option<&'a str> {
+    hit.pointer(&format!("/_source/{}", field))
+        .and_then(Value::as_str)
+}
+
+fn message_author(message: &Value) -> &'static str {
+    match source_str(message, "type") {
+        Some("assistant") => "Assistant",
+        _ => "You",
+    }
+}
+
+/// The sources fetched to answer `message`, without duplicates.
+fn message_citations(message: &Value) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+
+    let details = message
+        .pointer("/_source/details")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+    for detail in details {
+        if detail.get("type").and_then(Value::as_str) != Some("fetch_source") {
+            continue;
+        }
+        let sources = detail
+            .get("payload")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten();
+        for source in sources {
+            let Some(url) = source.get("url").and_then(Value::as_str) else {
+                continue;
+            };
+            if citations.iter().any(|citation| citation.url == url) {
+                continue;
+            }
+            let title = source.get("title").and_then(Value::as_str).unwrap_or(url);
+            citations.push(Citation {
+                title: title.to_string(),
+                url: url.to_string(),
+            });
+        }
+    }
+
+    citations
+}
+
+fn chat_title(chat: &ExportedChat) -> &str {
+    chat.title.as_deref().unwrap_or(&chat.session_id)
+}
+
+/// The text of the messages is Markdown already, code blocks included.
+fn render_markdown(chat: &ExportedChat) -> String {
+    let mut markdown = String::new();
+    let _ = writeln!(markdown, "# {}\n", chat_title(chat));
+
+    for message in &chat.messages {
+        let _ = write!(markdown, "## {}", message_author(message));
+        if let Some(created) = source_str(message, "created") {
+            let _ = write!(markdown, " ({})", created);
+        }
+        markdown.push_str("\n\n");
+
+        if let Some(text) = source_str(message, "message") {
+            let _ = writeln!(markdown, "{}\n", text.trim_end());
+        }
+
+        let citations = message_citations(message);
+        if !citations.is_empty() {
+            markdown.push_str("**Sources**\n\n");
+            for (i, citation) in citations.iter().enumerate() {
+                let _ = writeln!(
+                    markdown,
+                    "{}. [{}]({})",
+                    i + 1,
+                    citation.title,
+                    citation.url
+                );
+            }
+            markdown.push('\n');
+        }
+    }
+
+    if !chat.attachments.is_empty() {
+        markdown.push_str("## Attachments\n\n");
+        for attachment in &chat.attachments {
+            let _ = writeln!(
+                markdown,
+                "- [{}]({}) ({} bytes)",
+                attachment.name, attachment.url, attachment.size
+            );
+        }
+    }
+
+    markdown
+}
+
+fn render_json(chat: &ExportedChat) -> Result<String, CocoError> {
+    let json = serde_json::json!({
+        "session_id": chat.session_id,
+        "title": chat.title,
+        "exported_at": chrono::Utc::now().to_rfc3339(),
+        "messages": chat.messages,
+        "attachments": chat.attachments,
+    });
+
+    Ok(serde_json::to_string_pretty(&json)?)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Only the links with a safe scheme are kept, the others are rendered as text.
+fn is_safe_url(url: &str) -> bool {
+    let url = url.trim_start().to_lowercase();
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("mailto:")
+}
+
+fn html_link(title: &str, url: &str) -> String {
+    if is_safe_url(url) {
+        format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(url),
+            escape_html(title)
+        )
+    } else {
+        escape_html(title)
+    }
+}
+
+fn push_paragraph(html: &mut String, paragraph: &mut Vec<&str>) {
+    if !paragraph.is_empty() {
+        let lines: Vec<String> = paragraph.iter().map(|line| escape_html(line)).collect();
+        let _ = writeln!(html, "<p>{}</p>", lines.join("<br>\n"));
+        paragraph.clear();
+    }
+}
+
+fn push_code_block(html: &mut String, lines: &[&str]) {
+    let _ = writeln!(
+        html,
+        "<pre><code>{}</code></pre>",
+        escape_html(&lines.join("\n"))
+    );
+}
+
+/// Render the Markdown of a message: fenced code blocks are kept as is, the
+/// rest is split into paragraphs.
+fn markdown_to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code_block: Option<Vec<&str>> = None;
+
+    for line in markdown.lines() {
+        let is_fence = line.trim_start().starts_with("```");
+        if let Some(lines) = code_block.as_mut() {
+            if is_fence {
+                push_code_block(&mut html, lines);
+                code_block = None;
+            } else {
+                lines.push(line);
+            }
+        } else if is_fence {
+            push_paragraph(&mut html, &mut paragraph);
+            code_block = Some(Vec::new());
+        } else if line.trim().is_empty() {
+            push_paragraph(&mut html, &mut paragraph);
+        } else {
+            paragraph.push(line);
+        }
+    }
+
+    // An unterminated code block runs to the end of the message
+    if let Some(lines) = code_block {
+        push_code_block(&mut html, &lines);
+    }
+    push_paragraph(&mut html, &mut paragraph);
+
+    html
+}
+
+const HTML_STYLE: &str = "body{font-family:-apple-system,BlinkMacSystemFont,\"Segoe UI\",sans-serif;max-width:48rem;margin:2rem auto;padding:0 1rem;line-height:1.5;color:#1f2328}\
+.message{border-top:1px solid #d0d7de;padding:1rem 0}\
+.author{font-weight:600}\
+.created{color:#656d76;font-size:.875rem;margin-left:.5rem}\
+pre{background:#f6f8fa;padding:1rem;overflow-x:auto;border-radius:6px}\
+.sources{font-size:.875rem}";
+
+fn render_html(chat: &ExportedChat) -> String {
+    let title = escape_html(chat_title(chat));
+
+    let mut html = String::new();
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>",
+        title, HTML_STYLE, title
+    );
+
+    for message in &chat.messages {
+        html.push_str("<section class=\"message\">\n");
+        let _ = write!(
+            html,
+            "<div><span class=\"author\">{}</span>",
+            message_author(message)
+        );
+        if let Some(created) = source_str(message, "created") {
+            let _ = write!(
+                html,
+                "<span class=\"created\">{}</span>",
+                escape_html(created)
+            );
+        }
+        html.push_str("</div>\n");
+
+        if let Some(text) = source_str(message, "message") {
+            html.push_str(&markdown_to_html(text));
+        }
+
+        let citations = message_citations(message);
+        if !citations.is_empty() {
+            html.push_str("<ol class=\"sources\">\n");
+            for citation in &citations {
+                let _ = writeln!(
+                    html,
+                    "<li>{}</li>",
+                    html_link(&citation.title, &citation.url)
+                );
+            }
+            html.push_str("</ol>\n");
+        }
+        html.push_str("</section>\n");
+    }
+
+    if !chat.attachments.is_empty() {
+        html.push_str("<h2>Attachments</h2>\n<ul>\n");
+        for attachment in &chat.attachments {
+            let _ = writeln!(
+                html,
+                "<li>{} ({} bytes)</li>",
+                html_link(&attachment.name, &attachment.url),
+                attachment.size
+            );
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// All the messages of session `session_id`, fetched from the server.
+async fn fetch_messages(server_id: &str, session_id: &str) -> Result<Vec<Value>, CocoError> {
+    let mut messages = Vec::new();
+    loop {
+        let body = super::fetch_session_chat_history(
+            server_id,
+            session_id,
+            messages.len() as u32,
+            EXPORT_PAGE_SIZE,
+        )
+        .await?;
+        let page = parse_hits(&body)?;
+        let fetched = page.len();
+        messages.extend(page);
+        if fetched < EXPORT_PAGE_SIZE as usize {
+            break;
+        }
+    }
+    Ok(messages)
+}
+
+/// Sync the messages of session `session_id`. The sessions are synced first,
+/// as the mirrored `updated` field of the session is what tells whether its
+/// messages are stale, and it is itself stale if the session was continued
+/// since the history was last listed, e.g., on another client.
+async fn sync_exported_messages(
+    mirror: &ChatMirror,
+    server_id: &str,
+    session_id: &str,
+) -> Result<(), CocoError> {
+    sync_sessions(mirror, server_id).await?;
+    sync_session_messages(mirror, server_id, session_id).await?;
+    Ok(())
+}
+
+/// Export session `session_id` of server `server_id` to `path`, overwriting
+/// it. All its messages are exported, along with the metadata of its
+/// attachments.
+#[tauri::command]
+pub async fn export_session_chat<R: Runtime>(
+    _app_handle: AppHandle<R>,
+    server_id: String,
+    session_id: String,
+    format: ExportFormat,
+    path: String,
+) -> Result<(), CocoError> {
+    let messages = match chat_mirror() {
+        Some(mirror) => {
+            // The mirrored messages are exported if they cannot be synced,
+            // e.g., offline
+            if let Err(e) = sync_exported_messages(mirror, &server_id, &session_id).await {
+                if !mirror.has_messages(&server_id, &session_id) {
+                    return Err(e);
+                }
+                log::warn!(
+                    "failed to sync the messages of chat session [{}], exporting the mirrored ones: {}",
+                    session_id,
+                    e
+                );
+            }
+            mirror.session_messages(&server_id, &session_id)
+        }
+        None => fetch_messages(&server_id, &session_id).await?,
+    };
+
+    // The conversation is still worth exporting without them, e.g., offline
+    let attachments = match get_attachment(server_id.clone(), session_id.clone()).await {
+        Ok(response) => response
+            .hits
+            .hits
+            .unwrap_or_default()
+            .into_iter()
+            .map(|hit| hit._source)
+            .collect(),
+        Err(e) => {
+            log::warn!(
+                "failed to get the attachments of chat session [{}]: {}",
+                session_id,
+                e
+            );
+            Vec::new()
+        }
+    };
+
+    let title = chat_mirror()
+        .and_then(|mirror| mirror.session(&server_id, &session_id))
+        .and_then(|session| source_str(&session, "title").map(str::to_string));
+
+    let chat = ExportedChat {
+        session_id,
+        title,
+        messages,
+        attachments,
+    };
+    let content = match format {
+        ExportFormat::Markdown => render_markdown(&chat),
+        ExportFormat::Json => render_json(&chat)?,
+        ExportFormat::Html => render_html(&chat),
+    };
+
+    std::fs::write(&path, content)
+        .map_err(|e| CocoError::Internal(format!("Failed to write {}: {}", path, e)))
+}
+
+#[cfg(test)]
+fn test_chat() -> ExportedChat {
+    ExportedChat {
+        session_id: "session".to_string(),
+        title: Some("Rotate <certs>".to_string()),
+        messages: vec![
+            serde_json::json!({
+                "_id": "m1",
+                "_source": { "type": "user", "message": "How do I rotate certs?", "created": "2025-01-01T00:00:00Z" }
+            }),
+            serde_json::json!({
+                "_id": "m2",
+                "_source": {
+                    "type": "assistant",
+                    "message": "Run:\n\n```sh\ncoco certs rotate && echo <done>\n```\n\nThat's it.",
+                    "details": [{
+                        "type": "fetch_source",
+                        "payload": [
+                            { "title": "Runbook", "url": "https://wiki.example.com/runbook" },
+                            { "title": "Runbook again", "url": "https://wiki.example.com/runbook" },
+                            { "title": "Script", "url": "javascript:alert(1)" }
+                        ]
+                    }]
+                }
+            }),
+        ],
+        attachments: Vec::new(),
+    }
+}
+
+#[test]
+fn test_render_markdown() {
+    let markdown = render_markdown(&test_chat());
+
+    assert!(markdown.starts_with("# Rotate <certs>\n"));
+    assert!(markdown.contains("## You (2025-01-01T00:00:00Z)\n\nHow do I rotate certs?"));
+    assert!(markdown.contains("```sh\ncoco certs rotate && echo <done>\n```"));
+    assert!(markdown.contains("1. [Runbook](https://wiki.example.com/runbook)\n2. [Script]"));
+    assert!(!markdown.contains("Runbook again"));
+}
+
+#[test]
+fn test_render_html() {
+    let html = render_html(&test_chat());
+
+    assert!(html.contains("<title>Rotate &lt;certs&gt;</title>"));
+    assert!(html.contains("<pre><code>coco certs rotate &amp;&amp; echo &lt;done&gt;</code></pre>"));
+    assert!(html.contains("<p>That&#39;s it.</p>"));
+    assert!(html.contains("<a href=\"https://wiki.example.com/runbook\">Runbook</a>"));
+    assert!(!html.contains("javascript:"));
+}
+
+#[test]
+fn test_render_json() {
+    let json: Value = serde_json::from_str(&render_json(&test_chat()).unwrap()).unwrap();
+
+    assert_eq!(json["messages"].as_array().unwrap().len(), 2);
+    assert_eq!(json["messages"][1], test_chat().messages[1]);
+}
@@ -1,19 +1,55 @@
 use crate::common;
 use crate::common::assistant::ChatRequestMessage;
+use crate::common::error::CocoError;
 use crate::common::http::GetResponse;
 use crate::server::http_client::HttpClient;
+use serde::Serialize;
 use serde_json::Value;
 use std::collections::HashMap;
-use tauri::{AppHandle, Runtime};
+use tauri::{AppHandle, Emitter, Runtime};
 
+pub mod chat_mirror;
+pub mod export;
+
+/// The chat sessions of server `server_id`, served from the local mirror once
+/// it has been synced, see [`chat_mirror`](chat_mirror::chat_mirror).
+///
+/// The mirror is synced in the background, `chat-history-synced` is emitted
+/// with a [`ChatHistorySynced`] if anything changed.
 #[tauri::command]
 pub async fn chat_history<R: Runtime>(
-    _app_handle: AppHandle<R>,
+    app_handle: AppHandle<R>,
     server_id: String,
     from: u32,
     size: u32,
     query: Option<String>,
-) -> Result<String, String> {
+) -> Result<String, CocoError> {
+    let Some(mirror) = chat_mirror::chat_mirror() else {
+        return fetch_chat_history(&server_id, from, size, query).await;
+    };
+
+    if mirror.has_sessions(&server_id) {
+        spawn_chat_history_sync(app_handle, server_id.clone(), None);
+    } else {
+        let synced = chat_mirror::sync_sessions(mirror, &server_id).await?;
+        // Fetch the messages too, so that they can be searched offline
+        spawn_messages_sync(app_handle, server_id.clone(), synced.changed_ids);
+    }
+
+    // Searching may read every mirrored message file of the server
+    tauri::async_runtime::spawn_blocking(move || {
+        mirror.chat_history(&server_id, from as usize, size as usize, query.as_deref())
+    })
+    .await
+    .map_err(|e| CocoError::Internal(format!("Failed to search the chat history: {}", e)))
+}
+
+async fn fetch_chat_history(
+    server_id: &str,
+    from: u32,
+    size: u32,
+    query: Option<String>,
+) -> Result<String, CocoError> {
     let mut query_params: HashMap<String, Value> = HashMap::new();
     if from > 0 {
         query_params.insert("from".to_string(), from.into());
@@ -28,24 +64,40 @@ pub async fn chat_history<R: Runtime>(
         }
     }
 
-    let response = HttpClient::get(&server_id, "/chat/_history", Some(query_params))
-        .await
-        .map_err(|e| {
-            dbg!("Error get history: {}", &e);
-            format!("Error get history: {}", e)
-        })?;
+    let response = HttpClient::get(server_id, "/chat/_history", Some(query_params)).await?;
 
     common::http::get_response_body_text(response).await
 }
 
+/// The messages of session `session_id`, served from the local mirror once it
+/// has been synced, like [`chat_history`].
 #[tauri::command]
 pub async fn session_chat_history<R: Runtime>(
-    _app_handle: AppHandle<R>,
+    app_handle: AppHandle<R>,
     server_id: String,
     session_id: String,
     from: u32,
     size: u32,
-) -> Result<String, String> {
+) -> Result<String, CocoError> {
+    let Some(mirror) = chat_mirror::chat_mirror() else {
+        return fetch_session_chat_history(&server_id, &session_id, from, size).await;
+    };
+
+    if mirror.has_messages(&server_id, &session_id) {
+        spawn_chat_history_sync(app_handle, server_id.clone(), Some(session_id.clone()));
+    } else {
+        chat_mirror::sync_session_messages(mirror, &server_id, &session_id).await?;
+    }
+
+    Ok(mirror.session_chat_history(&server_id, &session_id, from as usize, size as usize))
+}
+
+async fn fetch_session_chat_history(
+    server_id: &str,
+    session_id: &str,
+    from: u32,
+    size: u32,
+) -> Result<String, CocoError> {
     let mut query_params: HashMap<String, Value> = HashMap::new();
     if from > 0 {
         query_params.insert("from".to_string(), from.into());
@@ -56,25 +108,125 @@ pub async fn session_chat_history<R: Runtime>(
 
     let path = format!("/chat/{}/_history", session_id);
 
-    let response = HttpClient::get(&server_id, path.as_str(), Some(query_params))
-        .await
-        .map_err(|e| format!("Error get session message: {}", e))?;
+    let response = HttpClient::get(server_id, path.as_str(), Some(query_params)).await?;
 
     common::http::get_response_body_text(response).await
 }
 
+/// Payload of the `chat-history-synced` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatHistorySynced {
+    pub server_id: String,
+    /// The sessions whose messages have been synced.
+    pub session_ids: Vec<String>,
+    /// The sessions deleted on the server, removed from the mirror.
+    pub removed_session_ids: Vec<String>,
+}
+
+/// Sync the sessions of server `server_id` (and the messages of session
+/// `session_id` if specified) in the background, unless it is being synced
+/// already. Failures are only logged, the mirror is served meanwhile.
+fn spawn_chat_history_sync<R: Runtime>(
+    app_handle: AppHandle<R>,
+    server_id: String,
+    session_id: Option<String>,
+) {
+    let Some(mirror) = chat_mirror::chat_mirror() else {
+        return;
+    };
+    // E.g., every page of the history being scrolled through asks for a sync
+    let Some(sync_guard) = mirror.start_sync(&server_id) else {
+        return;
+    };
+
+    tauri::async_runtime::spawn(async move {
+        let _sync_guard = sync_guard;
+
+        let synced = match chat_mirror::sync_sessions(mirror, &server_id).await {
+            Ok(synced) => synced,
+            Err(e) => {
+                log::warn!(
+                    "failed to sync the chat history of server [{}]: {}",
+                    server_id,
+                    e
+                );
+                return;
+            }
+        };
+        let mut session_ids = synced.changed_ids;
+        if let Some(session_id) = session_id {
+            if !session_ids.contains(&session_id) && !synced.removed_ids.contains(&session_id) {
+                session_ids.push(session_id);
+            }
+        }
+
+        sync_messages(app_handle, server_id, session_ids, synced.removed_ids).await;
+    });
+}
+
+/// Sync the messages of sessions `session_ids` in the background.
+fn spawn_messages_sync<R: Runtime>(
+    app_handle: AppHandle<R>,
+    server_id: String,
+    session_ids: Vec<String>,
+) {
+    tauri::async_runtime::spawn(sync_messages(
+        app_handle,
+        server_id,
+        session_ids,
+        Vec::new(),
+    ));
+}
+
+/// Sync the messages of sessions `session_ids`, then emit
+/// `chat-history-synced` if they changed or if sessions `removed_session_ids`
+/// have been removed from the mirror.
+async fn sync_messages<R: Runtime>(
+    app_handle: AppHandle<R>,
+    server_id: String,
+    session_ids: Vec<String>,
+    removed_session_ids: Vec<String>,
+) {
+    let Some(mirror) = chat_mirror::chat_mirror() else {
+        return;
+    };
+
+    let mut synced_ids = Vec::new();
+    for session_id in session_ids {
+        match chat_mirror::sync_session_messages(mirror, &server_id, &session_id).await {
+            Ok(true) => synced_ids.push(session_id),
+            Ok(false) => {}
+            Err(e) => log::warn!(
+                "failed to sync the messages of chat session [{}]: {}",
+                session_id,
+                e
+            ),
+        }
+    }
+
+    if synced_ids.is_empty() && removed_session_ids.is_empty() {
+        return;
+    }
+    let _ = app_handle.emit(
+        "chat-history-synced",
+        ChatHistorySynced {
+            server_id,
+            session_ids: synced_ids,
+            removed_session_ids,
+        },
+    );
+}
+
 #[tauri::command]
 pub async fn open_session_chat<R: Runtime>(
     _app_handle: AppHandle<R>,
     server_id: String,
     session_id: String,
-) -> Result<String, String> {
+) -> Result<String, CocoError> {
     let query_params = HashMap::new();
     let path = format!("/chat/{}/_open", session_id);
 
-    let response = HttpClient::post(&server_id, path.as_str(), Some(query_params), None)
-        .await
-        .map_err(|e| format!("Error open session: {}", e))?;
+    let response = HttpClient::post(&server_id, path.as_str(), Some(query_params), None).await?;
 
     common::http::get_response_body_text(response).await
 }
@@ -84,13 +236,11 @@ pub async fn close_session_chat<R: Runtime>(
     _app_handle: AppHandle<R>,
     server_id: String,
     session_id: String,
-) -> Result<String, String> {
+) -> Result<String, CocoError> {
     let query_params = HashMap::new();
     let path = format!("/chat/{}/_close", session_id);
 
-    let response = HttpClient::post(&server_id, path.as_str(), Some(query_params), None)
-        .await
-        .map_err(|e| format!("Error close session: {}", e))?;
+    let response = HttpClient::post(&server_id, path.as_str(), Some(query_params), None).await?;
 
     common::http::get_response_body_text(response).await
 }
@@ -99,13 +249,11 @@ pub async fn cancel_session_chat<R: Runtime>(
     _app_handle: AppHandle<R>,
     server_id: String,
     session_id: String,
-) -> Result<String, String> {
+) -> Result<String, CocoError> {
     let query_params = HashMap::new();
     let path = format!("/chat/{}/_cancel", session_id);
 
-    let response = HttpClient::post(&server_id, path.as_str(), Some(query_params), None)
-        .await
-        .map_err(|e| format!("Error cancel session: {}", e))?;
+    let response = HttpClient::post(&server_id, path.as_str(), Some(query_params), None).await?;
 
     common::http::get_response_body_text(response).await
 }
@@ -117,16 +265,12 @@ pub async fn new_chat<R: Runtime>(
     websocket_id: String,
     message: String,
     query_params: Option<HashMap<String, Value>>,
-) -> Result<GetResponse, String> {
+) -> Result<GetResponse, CocoError> {
     let body = if !message.is_empty() {
         let message = ChatRequestMessage {
             message: Some(message),
         };
-        Some(
-            serde_json::to_string(&message)
-                .map_err(|e| format!("Failed to serialize message: {}", e))?
-                .into(),
-        )
+        Some(serde_json::to_string(&message)?.into())
     } else {
         None
     };
@@ -136,16 +280,17 @@ pub async fn new_chat<R: Runtime>(
 
     let response =
         HttpClient::advanced_post(&server_id, "/chat/_new", Some(headers), query_params, body)
-            .await
-            .map_err(|e| format!("Error sending message: {}", e))?;
+            .await?;
 
     let body_text = common::http::get_response_body_text(response).await?;
 
-    let chat_response: GetResponse = serde_json::from_str(&body_text)
-        .map_err(|e| format!("Failed to parse response JSON: {}", e))?;
+    let chat_response: GetResponse = serde_json::from_str(&body_text)?;
 
     if chat_response.result != "created" {
-        return Err(format!("Unexpected result: {}", chat_response.result));
+        return Err(CocoError::Parse(format!(
+            "Unexpected result: {}",
+            chat_response.result
+        )));
     }
 
     Ok(chat_response)
@@ -159,7 +304,7 @@ pub async fn send_message<R: Runtime>(
     session_id: String,
     message: String,
     query_params: Option<HashMap<String, Value>>, //search,deep_thinking
-) -> Result<String, String> {
+) -> Result<String, CocoError> {
     let path = format!("/chat/{}/_send", session_id);
     let msg = ChatRequestMessage {
         message: Some(message),
@@ -176,22 +321,23 @@ pub async fn send_message<R: Runtime>(
         query_params,
         Some(body),
     )
-    .await
-    .map_err(|e| format!("Error cancel session: {}", e))?;
+    .await?;
 
     common::http::get_response_body_text(response).await
 }
 
 #[tauri::command]
-pub async fn delete_session_chat(server_id: String, session_id: String) -> Result<bool, String> {
+pub async fn delete_session_chat(server_id: String, session_id: String) -> Result<bool, CocoError> {
     let response =
         HttpClient::delete(&server_id, &format!("/chat/{}", session_id), None, None).await?;
 
-    if response.status().is_success() {
-        Ok(true)
-    } else {
-        Err(format!("Delete failed with status: {}", response.status()))
+    common::http::get_response_body_text(response).await?;
+
+    if let Some(mirror) = chat_mirror::chat_mirror() {
+        mirror.remove_session(&server_id, &session_id)?;
     }
+
+    Ok(true)
 }
 
 #[tauri::command]
@@ -200,7 +346,7 @@ pub async fn update_session_chat(
     session_id: String,
     title: Option<String>,
     context: Option<HashMap<String, Value>>,
-) -> Result<bool, String> {
+) -> Result<bool, CocoError> {
     let mut body = HashMap::new();
     if let Some(title) = title {
         body.insert("title".to_string(), Value::String(title));
@@ -219,8 +365,7 @@ pub async fn update_session_chat(
         None,
         Some(reqwest::Body::from(serde_json::to_string(&body).unwrap())),
     )
-    .await
-    .map_err(|e| format!("Error updating session: {}", e))?;
+    .await?;
 
     Ok(response.status().is_success())
 }
@@ -232,14 +377,14 @@ pub async fn assistant_search<R: Runtime>(
     from: u32,
     size: u32,
     query: Option<HashMap<String, Value>>,
-) -> Result<Value, String> {
+) -> Result<Value, CocoError> {
     let mut body = serde_json::json!({
         "from": from,
         "size": size,
     });
 
     if let Some(q) = query {
-        body["query"] = serde_json::to_value(q).map_err(|e| e.to_string())?;
+        body["query"] = serde_json::to_value(q)?;
     }
 
     let response = HttpClient::post(
@@ -248,11 +393,9 @@ pub async fn assistant_search<R: Runtime>(
         None,
         Some(reqwest::Body::from(body.to_string())),
     )
-    .await
-    .map_err(|e| format!("Error searching assistants: {}", e))?;
+    .await?;
+
+    let body_text = common::http::get_response_body_text(response).await?;
 
-    response
-        .json::<Value>()
-        .await
-        .map_err(|err| err.to_string())
+    Ok(serde_json::from_str(&body_text)?)
 }
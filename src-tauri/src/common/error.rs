@@ -20,9 +20,20 @@ pub enum SearchError {
     #[error("Invalid response format: {0}")]
     ParseError(String),
 
+    #[error("Server error ({status}): {reason}")]
+    ServerError { status: u16, reason: String },
+
+    /// The server could not be trusted, e.g., its certificate does not match
+    /// the pinned one.
+    #[error("TLS error: {0}")]
+    TlsError(String),
+
     #[error("Timeout occurred")]
     Timeout,
 
+    #[error("Query cancelled")]
+    Cancelled,
+
     #[error("Unknown error: {0}")]
     #[allow(dead_code)]
     Unknown(String),
@@ -43,3 +54,178 @@ impl From<reqwest::Error> for SearchError {
         }
     }
 }
+
+/// The error returned by the Tauri commands talking to Coco servers.
+///
+/// It is serialized as `{ "kind": "...", "message": "...", "status": ..., "reason": ... }`
+/// (`status` and `reason` are only present for `server_error`), so that the
+/// frontend can react to `kind` instead of matching error messages, e.g., ask
+/// the user to log in again on `auth_expired`.
+#[derive(Debug, Error, Clone)]
+pub enum CocoError {
+    #[error("Network error: {0}")]
+    Network(String),
+
+    #[error("TLS error: {0}")]
+    Tls(String),
+
+    #[error("Authentication expired, please log in again")]
+    AuthExpired,
+
+    #[error("Server error ({status}): {reason}")]
+    ServerError { status: u16, reason: String },
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid response format: {0}")]
+    Parse(String),
+
+    #[error("Timeout occurred")]
+    Timeout,
+
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl CocoError {
+    fn kind(&self) -> &'static str {
+        match self {
+            CocoError::Network(_) => "network",
+            CocoError::Tls(_) => "tls",
+            CocoError::AuthExpired => "auth_expired",
+            CocoError::ServerError { .. } => "server_error",
+            CocoError::NotFound(_) => "not_found",
+            CocoError::Parse(_) => "parse",
+            CocoError::Timeout => "timeout",
+            CocoError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl Serialize for CocoError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct SerializedCocoError<'a> {
+            kind: &'static str,
+            message: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            status: Option<u16>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            reason: Option<&'a str>,
+        }
+
+        let (status, reason) = match self {
+            CocoError::ServerError { status, reason } => (Some(*status), Some(reason.as_str())),
+            _ => (None, None),
+        };
+
+        SerializedCocoError {
+            kind: self.kind(),
+            message: self.to_string(),
+            status,
+            reason,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// `reqwest::Error`'s `Display` impl omits its sources, which is where the
+/// actual cause (connection refused, invalid certificate, ...) is.
+fn error_chain_message(err: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+/// Whether `err` is caused by a TLS failure, of native-tls or of rustls (used
+/// for the servers with a pinned certificate).
+fn is_tls_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err.is::<tokio_native_tls::native_tls::Error>() || err.is::<rustls::Error>() {
+            return true;
+        }
+        // The source of an `io::Error` is the one of the error it wraps, not
+        // that error, which is where rustls reports the failures of the
+        // handshake
+        if let Some(inner) = err
+            .downcast_ref::<std::io::Error>()
+            .and_then(std::io::Error::get_ref)
+        {
+            if is_tls_error(inner) {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+impl From<reqwest::Error> for CocoError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            CocoError::Timeout
+        } else if err.is_decode() {
+            CocoError::Parse(error_chain_message(&err))
+        } else if is_tls_error(&err) {
+            CocoError::Tls(error_chain_message(&err))
+        } else {
+            CocoError::Network(error_chain_message(&err))
+        }
+    }
+}
+
+impl From<serde_json::Error> for CocoError {
+    fn from(err: serde_json::Error) -> Self {
+        CocoError::Parse(err.to_string())
+    }
+}
+
+impl From<CocoError> for SearchError {
+    fn from(err: CocoError) -> Self {
+        match err {
+            CocoError::ServerError { status, reason } => {
+                SearchError::ServerError { status, reason }
+            }
+            CocoError::AuthExpired => SearchError::ServerError {
+                status: 401,
+                reason: err.to_string(),
+            },
+            CocoError::Parse(message) => SearchError::ParseError(message),
+            CocoError::Tls(message) => SearchError::TlsError(message),
+            CocoError::Timeout => SearchError::Timeout,
+            err => SearchError::HttpError(err.to_string()),
+        }
+    }
+}
+
+#[test]
+fn test_serialize_coco_error() {
+    let server_error = CocoError::ServerError {
+        status: 500,
+        reason: "index not found".to_string(),
+    };
+    assert_eq!(
+        serde_json::to_value(&server_error).unwrap(),
+        serde_json::json!({
+            "kind": "server_error",
+            "message": "Server error (500): index not found",
+            "status": 500,
+            "reason": "index not found",
+        })
+    );
+
+    assert_eq!(
+        serde_json::to_value(&CocoError::AuthExpired).unwrap(),
+        serde_json::json!({
+            "kind": "auth_expired",
+            "message": "Authentication expired, please log in again",
+        })
+    );
+}
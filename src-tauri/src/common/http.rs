@@ -1,5 +1,5 @@
-use crate::common;
-use reqwest::Response;
+use crate::common::error::{CocoError, ErrorResponse};
+use reqwest::{Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -19,34 +19,41 @@ pub struct Source {
     pub status: String,
 }
 
-pub async fn get_response_body_text(response: Response) -> Result<String, String> {
-    let status = response.status().as_u16();
-    let body = response
-        .text()
-        .await
-        .map_err(|e| format!("Failed to read response body: {}, code: {}", e, status))?;
+/// Read the body of `response`, unsuccessful responses are turned into a
+/// [`CocoError`], using the reason given by the server if there is one.
+pub async fn get_response_body_text(response: Response) -> Result<String, CocoError> {
+    let status = response.status();
+    let body = response.text().await.map_err(|e| {
+        CocoError::Network(format!(
+            "Failed to read response body: {}, code: {}",
+            e,
+            status.as_u16()
+        ))
+    })?;
 
-    log::debug!("Response status: {}, body: {}", status, &body);
+    log::debug!("Response status: {}, body: {}", status.as_u16(), &body);
 
-    if status < 200 || status >= 400 {
-        // Try to parse the error body
-        let fallback_error = "Failed to send message".to_string();
+    if status.is_success() || status.is_redirection() {
+        return Ok(body);
+    }
 
-        if body.trim().is_empty() {
-            return Err(fallback_error);
-        }
+    // Prefer the reason given by the server, then the body itself if it is
+    // not JSON, e.g., the error page of a reverse proxy
+    let reason = match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(parsed_error) => parsed_error.error.reason,
+        Err(_) if !body.trim().is_empty() => body.trim().to_string(),
+        Err(_) => status
+            .canonical_reason()
+            .unwrap_or("Unknown error")
+            .to_string(),
+    };
 
-        match serde_json::from_str::<common::error::ErrorResponse>(&body) {
-            Ok(parsed_error) => {
-                dbg!(&parsed_error);
-                Err(format!(
-                    "Server error ({}): {}",
-                    parsed_error.error.status, parsed_error.error.reason
-                ))
-            }
-            Err(_) => Err(fallback_error),
-        }
-    } else {
-        Ok(body)
-    }
+    Err(match status {
+        StatusCode::UNAUTHORIZED => CocoError::AuthExpired,
+        StatusCode::NOT_FOUND => CocoError::NotFound(reason),
+        _ => CocoError::ServerError {
+            status: status.as_u16(),
+            reason,
+        },
+    })
 }
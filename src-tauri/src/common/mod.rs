@@ -11,6 +11,7 @@ pub mod register;
 pub mod search;
 pub mod server;
 pub mod traits;
+pub mod websocket;
 
 pub static MAIN_WINDOW_LABEL: &str = "main";
 pub static SETTINGS_WINDOW_LABEL: &str = "settings";
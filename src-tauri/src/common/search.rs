@@ -1,9 +1,10 @@
 use crate::common::document::Document;
+use crate::common::error::CocoError;
 use crate::common::http::get_response_body_text;
 use reqwest::Response;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::error::Error;
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchResponse<T> {
@@ -42,9 +43,7 @@ pub struct SearchHit<T> {
     pub _score: Option<f64>,
     pub _source: T, // This will hold the type we pass in (e.g., DataSource)
 }
-pub async fn parse_search_response<T>(
-    response: Response,
-) -> Result<SearchResponse<T>, Box<dyn Error>>
+pub async fn parse_search_response<T>(response: Response) -> Result<SearchResponse<T>, CocoError>
 where
     T: for<'de> Deserialize<'de> + std::fmt::Debug,
 {
@@ -53,12 +52,12 @@ where
     // dbg!(&body_text);
 
     let search_response: SearchResponse<T> = serde_json::from_str(&body_text)
-        .map_err(|e| format!("Failed to deserialize search response: {}", e))?;
+        .map_err(|e| CocoError::Parse(format!("Failed to deserialize search response: {}", e)))?;
 
     Ok(search_response)
 }
 
-pub async fn parse_search_hits<T>(response: Response) -> Result<Vec<SearchHit<T>>, Box<dyn Error>>
+pub async fn parse_search_hits<T>(response: Response) -> Result<Vec<SearchHit<T>>, CocoError>
 where
     T: for<'de> Deserialize<'de> + std::fmt::Debug,
 {
@@ -67,7 +66,7 @@ where
     Ok(response.hits.hits)
 }
 
-pub async fn parse_search_results<T>(response: Response) -> Result<Vec<T>, Box<dyn Error>>
+pub async fn parse_search_results<T>(response: Response) -> Result<Vec<T>, CocoError>
 where
     T: for<'de> Deserialize<'de> + std::fmt::Debug,
 {
@@ -81,7 +80,7 @@ where
 #[allow(dead_code)]
 pub async fn parse_search_results_with_score<T>(
     response: Response,
-) -> Result<Vec<(T, Option<f64>)>, Box<dyn Error>>
+) -> Result<Vec<(T, Option<f64>)>, CocoError>
 where
     T: for<'de> Deserialize<'de> + std::fmt::Debug,
 {
@@ -97,6 +96,9 @@ pub struct SearchQuery {
     pub from: u64,
     pub size: u64,
     pub query_strings: HashMap<String, String>,
+    /// How long the source has to respond, it is dropped after that.
+    #[serde(skip)]
+    pub timeout: Option<Duration>,
 }
 
 impl SearchQuery {
@@ -105,11 +107,17 @@ impl SearchQuery {
             from,
             size,
             query_strings,
+            timeout: None,
         }
     }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuerySource {
     pub r#type: String, //coco-server/local/ etc.
     pub id: String,     //coco server's id
@@ -138,6 +146,14 @@ pub struct QueryResponse {
     pub total_hits: usize,
 }
 
+/// Hits of a single search source, sent while other sources are still being queried.
+#[derive(Debug, Clone, Serialize)]
+pub struct PartialQueryResponse {
+    pub source: QuerySource,
+    pub hits: Vec<QueryHits>,
+    pub total_hits: usize,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct MultiSourceQueryResponse {
     pub failed: Vec<FailedRequest>,
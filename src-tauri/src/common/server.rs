@@ -1,6 +1,7 @@
 use crate::common::health::Health;
 use crate::common::profile::UserProfile;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +35,95 @@ pub struct MinimalClientVersion {
     number: String,
 }
 
+/// TLS settings of a server, on top of the system trust store.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Extra root CA certificate (PEM) to trust, e.g., of a company CA.
+    pub ca_cert_pem: Option<String>,
+    /// Client certificate (PEM) for mutual TLS, along with `client_key_pem`.
+    pub client_cert_pem: Option<String>,
+    /// PKCS#8 private key (PEM) of `client_cert_pem`, it is kept in the
+    /// secrets backend, never stored along with the server nor sent back to the
+    /// frontend.
+    #[serde(skip_serializing)]
+    pub client_key_pem: Option<String>,
+    /// Hex-encoded SHA-256 fingerprint of the certificate the server must
+    /// present.
+    pub pinned_cert_sha256: Option<String>,
+}
+
+// Servers are logged, the private key must not be
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("ca_cert_pem", &self.ca_cert_pem)
+            .field("client_cert_pem", &self.client_cert_pem)
+            .field(
+                "client_key_pem",
+                &self.client_key_pem.as_ref().map(|_| "<redacted>"),
+            )
+            .field("pinned_cert_sha256", &self.pinned_cert_sha256)
+            .finish()
+    }
+}
+
+impl TlsConfig {
+    /// Whether these settings change nothing to the default TLS behavior.
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Proxy to connect to a server through.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ProxyConfig {
+    /// The proxy of the environment, `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY`
+    /// and `NO_PROXY`.
+    #[default]
+    System,
+    /// Connect directly, ignoring the environment.
+    None,
+    /// `url` is an `http://`, `https://`, `socks5://` or `socks5h://` URL,
+    /// optionally with credentials. `no_proxy` is a comma-separated list of
+    /// hosts to connect to directly, in the `NO_PROXY` format.
+    Custom {
+        url: String,
+        #[serde(default)]
+        no_proxy: Option<String>,
+    },
+}
+
+/// HTTP settings of a server, timeouts are in seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub connect_timeout: u64,
+    /// Maximum time without receiving any data from the server.
+    pub read_timeout: u64,
+    /// Maximum time of a whole request, attachment uploads excepted.
+    pub request_timeout: u64,
+    /// Maximum time of an attachment upload.
+    pub upload_timeout: u64,
+    pub proxy: ProxyConfig,
+    /// Headers sent with every request, e.g., required by a gateway in front of
+    /// the server.
+    pub default_headers: HashMap<String, String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 3,
+            read_timeout: 3,
+            request_timeout: 10,
+            upload_timeout: 300,
+            proxy: ProxyConfig::default(),
+            default_headers: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Server {
     #[serde(default = "default_empty_string")] // Custom default function for empty string
@@ -60,6 +150,15 @@ pub struct Server {
     pub auth_provider: AuthProvider,
     #[serde(default = "default_priority_type")]
     pub priority: u32,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// The WebSocket endpoint advertised by the server in `/provider/_info`,
+    /// it overrides the one derived from `endpoint`. Either absolute, or
+    /// relative to `endpoint`.
+    #[serde(default)]
+    pub websocket_endpoint: Option<String>,
 }
 
 impl PartialEq for Server {
@@ -84,6 +183,10 @@ pub struct ServerAccessToken {
     pub expired_at: u32, //unix timestamp in seconds
 }
 
+/// A token is considered expired this many seconds before it actually expires,
+/// so that it does not expire while a request is in flight.
+const TOKEN_EXPIRY_MARGIN_SECS: i64 = 60;
+
 impl ServerAccessToken {
     pub fn new(id: String, access_token: String, expired_at: u32) -> Self {
         Self {
@@ -92,6 +195,26 @@ impl ServerAccessToken {
             expired_at: expired_at,
         }
     }
+
+    /// A token expiring `expire_in` seconds from now.
+    pub fn expiring_in(id: String, access_token: String, expire_in: u32) -> Self {
+        let expired_at = chrono::Utc::now().timestamp() + i64::from(expire_in);
+        Self::new(
+            id,
+            access_token,
+            expired_at.clamp(0, u32::MAX.into()) as u32,
+        )
+    }
+
+    /// A token whose `expired_at` is 0 has an unknown lifetime, it is only
+    /// considered expired once the server rejects it.
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(chrono::Utc::now().timestamp())
+    }
+
+    fn is_expired_at(&self, now: i64) -> bool {
+        self.expired_at != 0 && now + TOKEN_EXPIRY_MARGIN_SECS >= i64::from(self.expired_at)
+    }
 }
 
 impl PartialEq for ServerAccessToken {
@@ -129,3 +252,37 @@ fn default_priority_type() -> u32 {
 fn default_user_profile_type() -> Option<UserProfile> {
     None
 }
+
+#[test]
+fn test_server_access_token_expiry() {
+    let token = ServerAccessToken::new("id".into(), "token".into(), 1_000_000);
+    assert!(!token.is_expired_at(1_000_000 - TOKEN_EXPIRY_MARGIN_SECS - 1));
+    assert!(token.is_expired_at(1_000_000 - TOKEN_EXPIRY_MARGIN_SECS));
+    assert!(token.is_expired_at(2_000_000));
+
+    let unknown_lifetime = ServerAccessToken::new("id".into(), "token".into(), 0);
+    assert!(!unknown_lifetime.is_expired_at(2_000_000));
+
+    assert!(!ServerAccessToken::expiring_in("id".into(), "token".into(), 3600).is_expired());
+}
+
+#[test]
+fn test_http_config_deserialize() {
+    // Servers saved before these settings existed
+    let config: HttpConfig = serde_json::from_str("{}").unwrap();
+    assert_eq!(config, HttpConfig::default());
+
+    let config: HttpConfig = serde_json::from_str(
+        r#"{"request_timeout": 60, "proxy": {"mode": "custom", "url": "socks5h://localhost:1080"}}"#,
+    )
+    .unwrap();
+    assert_eq!(config.request_timeout, 60);
+    assert_eq!(config.connect_timeout, 3);
+    assert_eq!(
+        config.proxy,
+        ProxyConfig::Custom {
+            url: "socks5h://localhost:1080".to_string(),
+            no_proxy: None,
+        }
+    );
+}
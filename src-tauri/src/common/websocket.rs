@@ -0,0 +1,103 @@
+//! The messages sent by the Coco servers over WebSocket connections.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::collections::HashMap;
+
+/// Prefix of the message assigning the session ID of a connection:
+/// `websocket-session-id: {id}`.
+const SESSION_ID_PREFIX: &str = "websocket-session-id:";
+/// Prefix of the messages streaming the reply of the assistant:
+/// `PRIVATE {chunk JSON}`.
+const CHAT_CHUNK_PREFIX: &str = "PRIVATE ";
+/// `chunk_type` of the chunks reporting the tools called by the assistant.
+const TOOL_CALL_CHUNK_TYPE: &str = "tools";
+
+/// A chunk of the reply to a chat message.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatChunk {
+    pub session_id: String,
+    pub message_id: String,
+    pub message_type: String,
+    pub reply_to_message: String,
+    pub chunk_sequence: u64,
+    pub chunk_type: String,
+    pub message_chunk: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Json>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WsEnvelope {
+    SessionId(String),
+    ChatChunk(ChatChunk),
+    ToolCall(ChatChunk),
+    /// `{"type": "notification", ...}`, the whole message.
+    Notification(Json),
+    /// `{"type": "heartbeat"}`
+    Heartbeat,
+    /// Anything else, as is.
+    Unknown(String),
+}
+
+impl WsEnvelope {
+    pub fn parse(text: &str) -> Self {
+        if let Some(session_id) = text.strip_prefix(SESSION_ID_PREFIX) {
+            return Self::SessionId(session_id.trim().to_string());
+        }
+
+        if let Some(chunk) = text.strip_prefix(CHAT_CHUNK_PREFIX) {
+            return match serde_json::from_str::<ChatChunk>(chunk) {
+                Ok(chunk) if chunk.chunk_type == TOOL_CALL_CHUNK_TYPE => Self::ToolCall(chunk),
+                Ok(chunk) => Self::ChatChunk(chunk),
+                Err(_) => Self::Unknown(text.to_string()),
+            };
+        }
+
+        let Ok(message) = serde_json::from_str::<Json>(text) else {
+            return Self::Unknown(text.to_string());
+        };
+        match message.get("type").and_then(Json::as_str) {
+            Some("notification") => Self::Notification(message),
+            Some("heartbeat") => Self::Heartbeat,
+            _ => Self::Unknown(text.to_string()),
+        }
+    }
+}
+
+#[test]
+fn test_parse_ws_envelope() {
+    assert_eq!(
+        WsEnvelope::parse("websocket-session-id: abc"),
+        WsEnvelope::SessionId("abc".to_string())
+    );
+
+    let chunk = WsEnvelope::parse(
+        r#"PRIVATE {"session_id":"s","reply_to_message":"m","chunk_sequence":2,"chunk_type":"response","message_chunk":"Hi","extra_field":1}"#,
+    );
+    let WsEnvelope::ChatChunk(chunk) = chunk else {
+        panic!("expected a chat chunk, got {:?}", chunk);
+    };
+    assert_eq!(chunk.reply_to_message, "m");
+    assert_eq!(chunk.chunk_sequence, 2);
+    assert_eq!(chunk.message_chunk, "Hi");
+    assert_eq!(chunk.extra["extra_field"], Json::from(1));
+
+    assert!(matches!(
+        WsEnvelope::parse(r#"PRIVATE {"chunk_type":"tools","message_chunk":"search"}"#),
+        WsEnvelope::ToolCall(_)
+    ));
+    assert!(matches!(
+        WsEnvelope::parse(r#"{"type":"notification","message":"Reindexed"}"#),
+        WsEnvelope::Notification(_)
+    ));
+    assert_eq!(
+        WsEnvelope::parse(r#"{"type":"heartbeat"}"#),
+        WsEnvelope::Heartbeat
+    );
+    assert_eq!(
+        WsEnvelope::parse("PRIVATE not json"),
+        WsEnvelope::Unknown("PRIVATE not json".to_string())
+    );
+}
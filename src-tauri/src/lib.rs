@@ -110,17 +110,25 @@ pub fn run() {
             server::servers::get_server_token,
             server::servers::add_coco_server,
             server::servers::remove_coco_server,
+            server::servers::set_server_tls_config,
+            server::servers::set_server_http_config,
+            server::search_cache::purge_search_cache,
             server::servers::list_coco_servers,
             server::servers::logout_coco_server,
             server::servers::refresh_coco_server_info,
             server::servers::enable_server,
             server::servers::disable_server,
+            server::auth::start_sso_login,
             server::auth::handle_sso_callback,
+            server::auth::login_with_token,
+            server::auth::login_with_password,
             server::profile::get_user_profiles,
             server::datasource::datasource_search,
             server::datasource::mcp_server_search,
             server::connector::get_connectors_by_server,
             search::query_coco_fusion,
+            search::query_coco_fusion_stream,
+            search::cancellation::cancel_query,
             assistant::chat_history,
             assistant::new_chat,
             assistant::send_message,
@@ -129,12 +137,14 @@ pub fn run() {
             assistant::close_session_chat,
             assistant::cancel_session_chat,
             assistant::delete_session_chat,
+            assistant::export::export_session_chat,
             assistant::update_session_chat,
             assistant::assistant_search,
             // server::get_coco_server_datasources,
             // server::get_coco_server_connectors,
             server::websocket::connect_to_server,
             server::websocket::disconnect,
+            server::websocket::ws_send,
             get_app_search_source,
             server::attachment::upload_attachment,
             server::attachment::get_attachment,
@@ -156,8 +166,22 @@ pub fn run() {
             local::application::enable_app_search,
             local::application::add_app_search_path,
             local::application::remove_app_search_path,
+            local::file_system::get_file_system_search_paths,
+            local::file_system::add_file_system_search_path,
+            local::file_system::remove_file_system_search_path,
+            local::search_history::add_search_history,
+            local::search_history::get_search_history,
+            local::search_history::delete_search_history,
+            local::search_history::clear_search_history,
+            local::search_history::get_search_history_incognito,
+            local::search_history::set_search_history_incognito,
+            local::pinned::pin_document,
+            local::pinned::unpin_document,
+            local::pinned::list_pinned,
             settings::set_allow_self_signature,
             settings::get_allow_self_signature,
+            settings::set_search_fusion_strategy,
+            settings::get_search_fusion_strategy,
         ])
         .setup(|app| {
             let app_handle = app.handle().clone();
@@ -174,6 +198,8 @@ pub fn run() {
                 init(app.handle()).await;
             });
 
+            server::health::start_health_checker(app.handle().clone());
+
             shortcut::enable_shortcut(app);
 
             enable_autostart(app);
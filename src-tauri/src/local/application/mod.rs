@@ -1,4 +1,25 @@
+//! Local application search.
+//!
+//! The store-backed settings (search paths, aliases, hotkeys, disabled apps) and
+//! the commands managing them are shared, how apps get indexed and searched
+//! depends on whether the `use_pizza_engine` feature is enabled.
+
+use crate::common::document::{DataSourceReference, Document};
+use crate::local::LOCAL_QUERY_SOURCE_TYPE;
+use crate::util::frecency::{self, LaunchHistory};
+use crate::util::open;
+use applications::{App, AppTrait};
+use log::warn;
 use serde::Serialize;
+use serde_json::Value as Json;
+use std::path::{Path, PathBuf};
+use tauri::{async_runtime, AppHandle, Runtime};
+use tauri_plugin_fs_pro::{icon, metadata, name, IconOptions};
+use tauri_plugin_global_shortcut::GlobalShortcutExt;
+use tauri_plugin_global_shortcut::Shortcut;
+use tauri_plugin_global_shortcut::ShortcutEvent;
+use tauri_plugin_global_shortcut::ShortcutState;
+use tauri_plugin_store::StoreExt;
 
 #[cfg(feature = "use_pizza_engine")]
 mod with_feature;
@@ -34,3 +55,646 @@ pub struct AppMetadata {
     modified: u128,
     last_opened: u128,
 }
+
+const TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH: &str = "disabled_app_list_and_search_path";
+const TAURI_STORE_APP_HOTKEY: &str = "app_hotkey";
+const TAURI_STORE_APP_ALIAS: &str = "app_alias";
+
+const TAURI_STORE_KEY_SEARCH_PATH: &str = "search_path";
+const TAURI_STORE_KEY_DISABLED_APP_LIST: &str = "disabled_app_list";
+
+/// We use this as:
+///
+/// 1. querysource ID
+/// 2. datasource ID
+/// 3. datasource name
+pub(crate) const QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME: &str = "Applications";
+
+pub fn get_default_search_paths() -> Vec<String> {
+    #[cfg(target_os = "macos")]
+    {
+        let home_dir =
+            PathBuf::from(std::env::var_os("HOME").expect("environment variable $HOME not found"));
+        return vec![
+            "/Applications".into(),
+            "/System/Applications".into(),
+            "/System/Library/CoreServices".into(),
+            home_dir
+                .join("Applications")
+                .into_os_string()
+                .into_string()
+                .expect("this path should be UTF-8 encoded"),
+        ];
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let paths = applications::get_default_search_paths();
+        let mut ret = Vec::with_capacity(paths.len());
+        for search_path in paths {
+            let path_string = search_path
+                .into_os_string()
+                .into_string()
+                .expect("path should be UTF-8 encoded");
+
+            ret.push(path_string);
+        }
+
+        ret
+    }
+}
+
+/// Helper function to return `app`'s path.
+///
+/// * Windows: return the path to application's exe
+/// * macOS: return the path to the `.app` bundle
+/// * Linux: return the path to the `.desktop` file
+fn get_app_path(app: &App) -> String {
+    let path = if cfg!(target_os = "windows") {
+        assert!(
+            app.icon_path.is_some(),
+            "we only accept Applications with icons"
+        );
+        app.app_path_exe
+            .as_ref()
+            .expect("icon is Some, exe path should be Some as well")
+            .to_path_buf()
+    } else {
+        app.app_desktop_path.clone()
+    };
+
+    path.into_os_string()
+        .into_string()
+        .expect("should be UTF-8 encoded")
+}
+
+/// Helper function to return `app`'s path.
+///
+/// * Windows/macOS: extract `app_path`'s file name and remove the file extension
+/// * Linux: return the name specified in `.desktop` file
+async fn get_app_name(app: &App) -> String {
+    if cfg!(target_os = "linux") {
+        app.name.clone()
+    } else {
+        let app_path = get_app_path(app);
+        name(app_path.into()).await
+    }
+}
+
+/// Helper function to return an absolute path to `app`'s icon.
+///
+/// On macOS/Windows, we cache icons in our data directory using the `icon()` function.
+async fn get_app_icon_path<R: Runtime>(
+    tauri_app_handle: &AppHandle<R>,
+    app: &App,
+) -> Result<String, String> {
+    let res_path = if cfg!(target_os = "linux") {
+        let icon_path = app
+            .icon_path
+            .as_ref()
+            .expect("We only accept applications with icons")
+            .to_path_buf();
+
+        Ok(icon_path)
+    } else {
+        let app_path = get_app_path(app);
+        let options = IconOptions {
+            size: Some(256),
+            save_path: None,
+        };
+
+        icon(tauri_app_handle.clone(), app_path.into(), Some(options))
+            .await
+            .map_err(|err| err.to_string())
+    };
+
+    let path = res_path?;
+
+    Ok(path
+        .into_os_string()
+        .into_string()
+        .expect("should be UTF-8 encoded"))
+}
+
+/// Return all the Apps found under `search_path`.
+///
+/// Note: apps with no icons will be filtered out.
+fn list_app_in(search_path: Vec<String>) -> Result<Vec<App>, String> {
+    let search_path = search_path
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+
+    let apps = applications::get_all_apps(&search_path).map_err(|err| err.to_string())?;
+
+    Ok(apps
+        .into_iter()
+        .filter(|app| app.icon_path.is_some())
+        .collect())
+}
+
+/// Create the stores used by app search and fill in the defaults, then
+/// register the app hotkeys.
+fn init_app_settings<R: Runtime>(app_handle: &AppHandle<R>) -> Result<(), String> {
+    app_handle
+        .store(TAURI_STORE_APP_HOTKEY)
+        .map_err(|e| e.to_string())?;
+    let disabled_app_list_and_search_path_store = app_handle
+        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
+        .map_err(|e| e.to_string())?;
+    if disabled_app_list_and_search_path_store
+        .get(TAURI_STORE_KEY_DISABLED_APP_LIST)
+        .is_none()
+    {
+        disabled_app_list_and_search_path_store
+            .set(TAURI_STORE_KEY_DISABLED_APP_LIST, Json::Array(Vec::new()));
+    }
+
+    if disabled_app_list_and_search_path_store
+        .get(TAURI_STORE_KEY_SEARCH_PATH)
+        .is_none()
+    {
+        let default_search_path = get_default_search_paths();
+        disabled_app_list_and_search_path_store
+            .set(TAURI_STORE_KEY_SEARCH_PATH, default_search_path);
+    }
+
+    register_app_hotkey_upon_start(app_handle.clone())
+}
+
+/// Build the document representing an app in search results.
+fn app_document(app_path: String, app_name: String, app_icon_path: String) -> Document {
+    Document {
+        source: Some(DataSourceReference {
+            r#type: Some(LOCAL_QUERY_SOURCE_TYPE.into()),
+            name: Some(QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME.into()),
+            id: Some(QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME.into()),
+            icon: None,
+        }),
+        id: app_path.clone(),
+        category: Some("Application".to_string()),
+        title: Some(app_name),
+        url: Some(app_path),
+        icon: Some(app_icon_path),
+
+        ..Default::default()
+    }
+}
+
+#[tauri::command]
+pub async fn set_app_alias<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    app_path: String,
+    alias: String,
+) {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_APP_ALIAS)
+        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_ALIAS));
+
+    store.set(app_path, alias);
+
+    // TODO: When pizza supports update, update index if this app's document exists there.
+    //
+    // NOTE: possible (depends on how we impl concurrency control in Pizza) TOCTOU: document gets
+    // deleted while updating it.
+}
+
+fn get_app_alias<R: Runtime>(tauri_app_handle: &AppHandle<R>, app_path: &str) -> Option<String> {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_APP_ALIAS)
+        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_ALIAS));
+
+    let json = store.get(app_path)?;
+
+    let string = match json {
+        Json::String(s) => s,
+        _ => unreachable!("app alias should be stored in a string"),
+    };
+
+    Some(string)
+}
+
+/// The handler that will be invoked when an application hotkey is pressed.
+///
+/// The `app_path` argument is for logging-only.
+fn app_hotkey_handler<R: Runtime>(
+    app_path: String,
+) -> impl Fn(&AppHandle<R>, &Shortcut, ShortcutEvent) + Send + Sync + 'static {
+    move |tauri_app_handle, _hot_key, event| {
+        if event.state() == ShortcutState::Pressed {
+            let app_path_clone = app_path.clone();
+            let tauri_app_handle_clone = tauri_app_handle.clone();
+            // This closure will be executed on the main thread, so we spawn to reduce the potential UI lag.
+            async_runtime::spawn(async move {
+                if let Err(e) = open(tauri_app_handle_clone, app_path_clone).await {
+                    warn!("failed to open app due to [{}]", e);
+                }
+            });
+        }
+    }
+}
+
+fn register_app_hotkey_upon_start<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+) -> Result<(), String> {
+    let app_hotkey_store = tauri_app_handle
+        .store(TAURI_STORE_APP_HOTKEY)
+        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
+
+    for (app_path, hotkey) in app_hotkey_store.entries() {
+        let hotkey = match hotkey {
+            Json::String(str) => str,
+            _ => unreachable!("hotkey should be stored in a string"),
+        };
+
+        tauri_app_handle
+            .global_shortcut()
+            .on_shortcut(hotkey.as_str(), app_hotkey_handler(app_path))
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn register_app_hotkey<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    app_path: String,
+    hotkey: String,
+) -> Result<(), String> {
+    let app_hotkey_store = tauri_app_handle
+        .store(TAURI_STORE_APP_HOTKEY)
+        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
+
+    app_hotkey_store.set(app_path.clone(), hotkey.as_str());
+
+    tauri_app_handle
+        .global_shortcut()
+        .on_shortcut(hotkey.as_str(), app_hotkey_handler(app_path))
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unregister_app_hotkey<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    app_path: String,
+) -> Result<(), String> {
+    let app_hotkey_store = tauri_app_handle
+        .store(TAURI_STORE_APP_HOTKEY)
+        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
+
+    let Some(hotkey) = app_hotkey_store.get(app_path.as_str()) else {
+        let error_msg = format!(
+            "unregister an Application hotkey that does not exist app: [{}]",
+            app_path,
+        );
+        warn!("{}", error_msg);
+        return Err(error_msg);
+    };
+
+    let hotkey = match hotkey {
+        Json::String(str) => str,
+        _ => unreachable!("hotkey should be stored in a string"),
+    };
+
+    let deleted = app_hotkey_store.delete(app_path.as_str());
+    if !deleted {
+        return Err("failed to delete application hotkey from store".into());
+    }
+
+    tauri_app_handle
+        .global_shortcut()
+        .unregister(hotkey.as_str())
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+fn get_disabled_app_list<R: Runtime>(tauri_app_handle: AppHandle<R>) -> Vec<String> {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
+        .unwrap_or_else(|_| {
+            panic!(
+                "tauri store [{}] not found/loaded",
+                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
+            )
+        });
+    let disabled_app_list_json = store
+        .get(TAURI_STORE_KEY_DISABLED_APP_LIST)
+        .unwrap_or_else(|| panic!("key [{}] not found", TAURI_STORE_KEY_DISABLED_APP_LIST));
+
+    let disabled_app_list: Vec<String> = match disabled_app_list_json {
+        Json::Array(a) => a
+            .into_iter()
+            .map(|json| match json {
+                Json::String(s) => s,
+                _ => unreachable!("app_path is stored in a string"),
+            })
+            .collect(),
+        _ => unreachable!("disabled app list is stored in an array"),
+    };
+
+    disabled_app_list
+}
+
+#[tauri::command]
+pub async fn disable_app_search<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    app_path: String,
+) -> Result<(), String> {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
+        .unwrap_or_else(|_| {
+            panic!(
+                "tauri store [{}] not found/loaded",
+                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
+            )
+        });
+
+    let mut disabled_app_list = get_disabled_app_list(tauri_app_handle);
+
+    if disabled_app_list.contains(&app_path) {
+        return Err(format!(
+            "trying to disable an app that is disabled [{}]",
+            app_path
+        ));
+    }
+
+    disabled_app_list.push(app_path);
+
+    store.set(TAURI_STORE_KEY_DISABLED_APP_LIST, disabled_app_list);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn enable_app_search<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    app_path: String,
+) -> Result<(), String> {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
+        .unwrap_or_else(|_| {
+            panic!(
+                "tauri store [{}] not found/loaded",
+                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
+            )
+        });
+
+    let mut disabled_app_list = get_disabled_app_list(tauri_app_handle);
+
+    match disabled_app_list
+        .iter()
+        .position(|app_path_str| app_path_str == &app_path)
+    {
+        Some(index) => {
+            disabled_app_list.remove(index);
+            store.set(TAURI_STORE_KEY_DISABLED_APP_LIST, disabled_app_list);
+
+            Ok(())
+        }
+        None => Err(format!(
+            "trying to enable an app that is not disabled [{}]",
+            app_path
+        )),
+    }
+}
+
+#[tauri::command]
+pub async fn add_app_search_path<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    search_path: String,
+) -> Result<(), String> {
+    let mut search_paths = get_app_search_path(tauri_app_handle.clone()).await;
+    if search_paths.contains(&search_path) {
+        return Ok(());
+    }
+
+    search_paths.push(search_path);
+
+    let store = tauri_app_handle
+        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
+        .unwrap_or_else(|_| {
+            panic!(
+                "store [{}] not found/loaded",
+                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
+            )
+        });
+
+    store.set(TAURI_STORE_KEY_SEARCH_PATH, search_paths.clone());
+    // The app list is synchronized with the search paths in the store
+    watch_app_search_paths(search_paths);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_app_search_path<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    search_path: String,
+) -> Result<(), String> {
+    let mut search_paths = get_app_search_path(tauri_app_handle.clone()).await;
+    let opt_index = search_paths.iter().position(|path| path == &search_path);
+    let Some(index) = opt_index else {
+        return Ok(());
+    };
+
+    search_paths.remove(index);
+
+    let store = tauri_app_handle
+        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
+        .unwrap_or_else(|_| {
+            panic!(
+                "store [{}] not found/loaded",
+                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
+            )
+        });
+
+    store.set(TAURI_STORE_KEY_SEARCH_PATH, search_paths.clone());
+    // The app list is synchronized with the search paths in the store
+    watch_app_search_paths(search_paths);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_app_search_path<R: Runtime>(tauri_app_handle: AppHandle<R>) -> Vec<String> {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
+        .unwrap_or_else(|_| {
+            panic!(
+                "store [{}] not found/loaded",
+                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
+            )
+        });
+
+    let search_path_json = store
+        .get(TAURI_STORE_KEY_SEARCH_PATH)
+        .unwrap_or_else(|| panic!("key [{}] not found", TAURI_STORE_KEY_SEARCH_PATH));
+
+    let search_path: Vec<String> = match search_path_json {
+        Json::Array(array) => array
+            .into_iter()
+            .map(|json| match json {
+                Json::String(str) => str,
+                _ => unreachable!("search path is stored in a string"),
+            })
+            .collect(),
+        _ => unreachable!("search path is stored in an array"),
+    };
+
+    search_path
+}
+
+/// Record the launch of `path` in the launch history if it is an application,
+/// i.e., it is in one of the app search paths.
+pub(crate) async fn record_app_launch<R: Runtime>(tauri_app_handle: &AppHandle<R>, path: &str) {
+    let search_paths = get_app_search_path(tauri_app_handle.clone()).await;
+    let is_app = search_paths
+        .iter()
+        .any(|search_path| Path::new(path).starts_with(search_path));
+    if is_app {
+        frecency::record_launch(tauri_app_handle, path);
+    }
+}
+
+#[tauri::command]
+pub async fn get_app_list<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+) -> Result<Vec<AppEntry>, String> {
+    let search_paths = get_app_search_path(tauri_app_handle.clone()).await;
+    let apps = list_app_in(search_paths)?;
+
+    let mut app_entries = Vec::with_capacity(apps.len());
+
+    for app in apps {
+        let name = get_app_name(&app).await;
+
+        // filter out Coco-AI
+        if name.eq(&tauri_app_handle.package_info().name) {
+            continue;
+        }
+
+        let path = get_app_path(&app);
+        let icon_path = get_app_icon_path(&tauri_app_handle, &app).await.unwrap();
+        let alias = {
+            let store = tauri_app_handle
+                .store(TAURI_STORE_APP_ALIAS)
+                .map_err(|e| e.to_string())?;
+            let opt_string = store.get(&path).map(|json| match json {
+                Json::String(s) => s,
+                _ => unreachable!("app alias should be stored in a string"),
+            });
+
+            opt_string.unwrap_or(String::new())
+        };
+        let hotkey = {
+            let store = tauri_app_handle
+                .store(TAURI_STORE_APP_HOTKEY)
+                .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
+            let opt_string = store.get(&path).map(|json| match json {
+                Json::String(s) => s,
+                _ => unreachable!("app hotkey should be stored in a string"),
+            });
+
+            opt_string.unwrap_or(String::new())
+        };
+        let is_disabled = {
+            let store = tauri_app_handle
+                .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
+                .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
+            let disabled_app_list_json = store
+                .get(TAURI_STORE_KEY_DISABLED_APP_LIST)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "store [{}] does not contain key [{}]",
+                        TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH,
+                        TAURI_STORE_KEY_DISABLED_APP_LIST
+                    )
+                });
+
+            let disabled_app_list = match disabled_app_list_json {
+                Json::Array(v) => v
+                    .into_iter()
+                    .map(|json| match json {
+                        Json::String(str) => str,
+                        _ => unreachable!("app path should be stored in a string"),
+                    })
+                    .collect::<Vec<String>>(),
+                _ => unreachable!("disabled app list should be stored in an array"),
+            };
+
+            disabled_app_list.contains(&path)
+        };
+
+        let app_entry = AppEntry {
+            path,
+            name,
+            icon_path,
+            alias,
+            hotkey,
+            is_disabled,
+        };
+
+        app_entries.push(app_entry);
+    }
+
+    Ok(app_entries)
+}
+
+#[tauri::command]
+pub async fn get_app_metadata<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    app_path: String,
+) -> Result<AppMetadata, String> {
+    let app =
+        App::from_path(std::path::Path::new(&app_path)).expect("frontend sends an invalid app");
+
+    let app_path = get_app_path(&app);
+    let app_name = get_app_name(&app).await;
+    let app_path_where = {
+        let app_path_borrowed_path = std::path::Path::new(app_path.as_str());
+        let app_path_where = app_path_borrowed_path
+            .parent()
+            .expect("every app file should live somewhere");
+
+        app_path_where
+            .to_str()
+            .expect("it is guaranteed to be UTF-8 encoded")
+            .to_string()
+    };
+    let icon = get_app_icon_path(&tauri_app_handle, &app).await?;
+
+    let raw_app_metadata = metadata(app_path.clone().into(), None).await?;
+
+    let last_opened = if cfg!(any(target_os = "macos", target_os = "windows")) {
+        let app_exe_path = app
+            .app_path_exe
+            .as_ref()
+            .expect("exe path should be Some")
+            .clone();
+        let raw_app_exe_metadata = metadata(app_exe_path, None).await?;
+        raw_app_exe_metadata.accessed_at
+    } else {
+        raw_app_metadata.accessed_at
+    };
+    // The access time is not updated by every file system, prefer our own
+    // record if it is more recent.
+    let last_opened = LaunchHistory::load(&tauri_app_handle)
+        .get(&app_path)
+        .and_then(|record| record.last_launched())
+        .map(|last_launched| last_opened.max(last_launched as u128))
+        .unwrap_or(last_opened);
+
+    Ok(AppMetadata {
+        name: app_name,
+        r#where: app_path_where,
+        size: raw_app_metadata.size,
+        icon,
+        created: raw_app_metadata.created_at,
+        modified: raw_app_metadata.modified_at,
+        last_opened,
+    })
+}
@@ -1,18 +1,22 @@
+use super::super::watcher::{watch, LocalIndexWatcher};
 use super::super::SearchSourceState;
 use super::super::Task;
 use super::super::RUNTIME_TX;
-use super::AppEntry;
-use super::AppMetadata;
-use crate::common::document::{DataSourceReference, Document};
+use super::{
+    app_document, get_app_alias, get_app_icon_path, get_app_list, get_app_name, get_app_path,
+    get_app_search_path, get_default_search_paths, get_disabled_app_list, init_app_settings,
+    list_app_in, QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME,
+};
+use crate::common::document::Document;
 use crate::common::error::SearchError;
 use crate::common::search::{QueryResponse, QuerySource, SearchQuery};
 use crate::common::traits::SearchSource;
 use crate::local::LOCAL_QUERY_SOURCE_TYPE;
-use crate::util::open;
+use crate::util::frecency::LaunchHistory;
 use crate::GLOBAL_TAURI_APP_HANDLE;
-use applications::{App, AppTrait};
 use async_trait::async_trait;
 use log::{debug, error, info, warn};
+use notify::RecursiveMode;
 use pizza_engine::document::FieldType;
 use pizza_engine::document::{
     Document as PizzaEngineDocument, DraftDoc as PizzaEngineDraftDoc, FieldValue,
@@ -23,162 +27,31 @@ use pizza_engine::search::{OriginalQuery, QueryContext, SearchResult, Searcher};
 use pizza_engine::store::{DiskStore, DiskStoreSnapshot};
 use pizza_engine::writer::Writer;
 use pizza_engine::{doc, Engine, EngineBuilder};
-use serde_json::Value as Json;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::PathBuf;
-use tauri::{async_runtime, AppHandle, Manager, Runtime};
-use tauri_plugin_fs_pro::{icon, metadata, name, IconOptions};
-use tauri_plugin_global_shortcut::GlobalShortcutExt;
-use tauri_plugin_global_shortcut::Shortcut;
-use tauri_plugin_global_shortcut::ShortcutEvent;
-use tauri_plugin_global_shortcut::ShortcutState;
-use tauri_plugin_store::StoreExt;
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Manager, Runtime};
 use tokio::sync::oneshot::Sender as OneshotSender;
 
 const FIELD_APP_NAME: &str = "app_name";
 const FIELD_ICON_PATH: &str = "icon_path";
 const FIELD_APP_ALIAS: &str = "app_alias";
 const APPLICATION_SEARCH_SOURCE_ID: &str = "application";
-
-const TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH: &str = "disabled_app_list_and_search_path";
-const TAURI_STORE_APP_HOTKEY: &str = "app_hotkey";
-const TAURI_STORE_APP_ALIAS: &str = "app_alias";
-
-const TAURI_STORE_KEY_SEARCH_PATH: &str = "search_path";
-const TAURI_STORE_KEY_DISABLED_APP_LIST: &str = "disabled_app_list";
+/// Number of hits fetched from the index, the launch history is applied to
+/// them before the page asked for is taken, so that a frequently launched app
+/// with a lower text relevance still makes it into the results.
+const APP_SEARCH_CANDIDATES: usize = 100;
 
 const THREAD_NAME_APP_SYNCHRONIZER: &str = "local app search - app list synchronizer";
+/// App list changes are reported by the watcher, this is only a fallback.
+const APP_LIST_SYNC_FALLBACK_INTERVAL: std::time::Duration =
+    std::time::Duration::from_secs(60 * 30);
 
-/// We use this as:
-///
-/// 1. querysource ID
-/// 2. datasource ID
-/// 3. datasource name
-pub(crate) const QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME: &str = "Applications";
-
-pub fn get_default_search_paths() -> Vec<String> {
-    #[cfg(target_os = "macos")]
-    {
-        let home_dir =
-            PathBuf::from(std::env::var_os("HOME").expect("environment variable $HOME not found"));
-        return vec![
-            "/Applications".into(),
-            "/System/Applications".into(),
-            "/System/Library/CoreServices".into(),
-            home_dir
-                .join("Applications")
-                .into_os_string()
-                .into_string()
-                .expect("this path should be UTF-8 encoded"),
-        ];
-    }
-
-    #[cfg(not(target_os = "macos"))]
-    {
-        let paths = applications::get_default_search_paths();
-        let mut ret = Vec::with_capacity(paths.len());
-        for search_path in paths {
-            let path_string = search_path
-                .into_os_string()
-                .into_string()
-                .expect("path should be UTF-8 encoded");
-
-            ret.push(path_string);
-        }
-
-        ret
-    }
-}
-
-/// Helper function to return `app`'s path.
-///
-/// * Windows: return the path to application's exe
-/// * macOS: return the path to the `.app` bundle
-/// * Linux: return the path to the `.desktop` file
-fn get_app_path(app: &App) -> String {
-    let path = if cfg!(target_os = "windows") {
-        assert!(
-            app.icon_path.is_some(),
-            "we only accept Applications with icons"
-        );
-        app.app_path_exe
-            .as_ref()
-            .expect("icon is Some, exe path should be Some as well")
-            .to_path_buf()
-    } else {
-        app.app_desktop_path.clone()
-    };
-
-    path.into_os_string()
-        .into_string()
-        .expect("should be UTF-8 encoded")
-}
-
-/// Helper function to return `app`'s path.
-///
-/// * Windows/macOS: extract `app_path`'s file name and remove the file extension
-/// * Linux: return the name specified in `.desktop` file
-async fn get_app_name(app: &App) -> String {
-    if cfg!(target_os = "linux") {
-        app.name.clone()
-    } else {
-        let app_path = get_app_path(app);
-        name(app_path.into()).await
-    }
-}
-
-/// Helper function to return an absolute path to `app`'s icon.
-///
-/// On macOS/Windows, we cache icons in our data directory using the `icon()` function.
-async fn get_app_icon_path<R: Runtime>(
-    tauri_app_handle: &AppHandle<R>,
-    app: &App,
-) -> Result<String, String> {
-    let res_path = if cfg!(target_os = "linux") {
-        let icon_path = app
-            .icon_path
-            .as_ref()
-            .expect("We only accept applications with icons")
-            .to_path_buf();
-
-        Ok(icon_path)
-    } else {
-        let app_path = get_app_path(app);
-        let options = IconOptions {
-            size: Some(256),
-            save_path: None,
-        };
-
-        icon(tauri_app_handle.clone(), app_path.into(), Some(options))
-            .await
-            .map_err(|err| err.to_string())
-    };
-
-    let path = res_path?;
-
-    Ok(path
-        .into_os_string()
-        .into_string()
-        .expect("should be UTF-8 encoded"))
-}
-
-/// Return all the Apps found under `search_path`.
-///
-/// Note: apps with no icons will be filtered out.
-fn list_app_in(search_path: Vec<String>) -> Result<Vec<App>, String> {
-    let search_path = search_path
-        .into_iter()
-        .map(PathBuf::from)
-        .collect::<Vec<_>>();
-
-    let apps = applications::get_all_apps(&search_path).map_err(|err| err.to_string())?;
-
-    Ok(apps
-        .into_iter()
-        .filter(|app| app.icon_path.is_some())
-        .collect())
-}
+/// Sending to it wakes up the app list synchronizer, set once the synchronizer starts.
+static APP_LIST_SYNC_TX: OnceLock<tokio::sync::mpsc::Sender<()>> = OnceLock::new();
+/// Watcher of the app search paths, replaced whenever they change.
+static APP_WATCHER: Mutex<Option<LocalIndexWatcher>> = Mutex::new(None);
 
 // A homemade version of `std::try!()` for use in the `Task::exec()` function.
 ///
@@ -326,11 +199,16 @@ impl<R: Runtime> Task for SearchApplicationsTask<R> {
 
     async fn exec(&mut self, state: &mut Option<Box<dyn SearchSourceState>>) {
         let callback = self.callback.take().unwrap();
+        // The query has been cancelled or has timed out while this task was queued
+        if callback.is_closed() {
+            debug!("local app search query cancelled, skip it");
+            return;
+        }
         let disabled_app_list = get_disabled_app_list(self.tauri_app_handle.clone());
 
         // TODO: search via alias, implement this when Pizza engine supports update
         let dsl = format!(
-            "{{ \"query\": {{ \"bool\": {{ \"should\": [ {{ \"match\": {{ \"{FIELD_APP_NAME}\": \"{}\" }} }}, {{ \"prefix\": {{ \"{FIELD_APP_NAME}\": \"{}\" }} }} ] }} }} }}", self.query_string, self.query_string);
+            "{{ \"size\": {APP_SEARCH_CANDIDATES}, \"query\": {{ \"bool\": {{ \"should\": [ {{ \"match\": {{ \"{FIELD_APP_NAME}\": \"{}\" }} }}, {{ \"prefix\": {{ \"{FIELD_APP_NAME}\": \"{}\" }} }} ] }} }} }}", self.query_string, self.query_string);
 
         let state = state
             .as_mut()
@@ -344,7 +222,8 @@ impl<R: Runtime> Task for SearchApplicationsTask<R> {
         let mut search_result = match state.searcher.parse_and_query(&query_ctx, &state.snapshot) {
             Ok(search_result) => search_result,
             Err(engine_err) => {
-                callback.send(Err(engine_err)).unwrap();
+                // The receiver may have been dropped if the query was cancelled
+                let _ = callback.send(Err(engine_err));
                 return;
             }
         };
@@ -369,17 +248,19 @@ impl<R: Runtime> Task for SearchApplicationsTask<R> {
 }
 
 /// When
-/// 1. App list watcher reports that there are some newly-installed applications
-/// 2. New search paths have been added by the user
+/// 1. App list watcher reports that some applications have been installed or uninstalled
+/// 2. Search paths have been added or removed by the user
 ///
-/// We use this task to index them.
-struct IndexNewApplicationsTask {
-    applications: Vec<PizzaEngineDraftDoc>,
+/// We use this task to index the new applications and delete the ones that are gone.
+struct SynchronizeApplicationsTask {
+    new_applications: Vec<PizzaEngineDraftDoc>,
+    /// Paths (document keys) of the applications that no longer exist.
+    removed_app_paths: Vec<String>,
     callback: Option<tokio::sync::oneshot::Sender<Result<(), String>>>,
 }
 
 #[async_trait(?Send)]
-impl Task for IndexNewApplicationsTask {
+impl Task for SynchronizeApplicationsTask {
     fn search_source_id(&self) -> &'static str {
         APPLICATION_SEARCH_SOURCE_ID
     }
@@ -398,7 +279,11 @@ impl Task for IndexNewApplicationsTask {
 
         let writer = &mut state.writer;
 
-        for app_document in std::mem::take(&mut self.applications) {
+        for app_path in std::mem::take(&mut self.removed_app_paths) {
+            task_exec_try!(writer.delete_document(&app_path).await, callback);
+        }
+
+        for app_document in std::mem::take(&mut self.new_applications) {
             task_exec_try!(writer.create_document(app_document).await, callback);
         }
 
@@ -410,6 +295,124 @@ impl Task for IndexNewApplicationsTask {
     }
 }
 
+/// Index the apps that exist in the search paths but not in `known_app_paths`,
+/// delete the ones in `known_app_paths` that no longer exist, then update
+/// `known_app_paths` to the current app list.
+async fn synchronize_app_list<R: Runtime>(
+    tauri_app_handle: &AppHandle<R>,
+    known_app_paths: &mut HashSet<String>,
+) {
+    let search_paths = get_app_search_path(tauri_app_handle.clone()).await;
+    let mut current_app_list = list_app_in(search_paths)
+        .unwrap_or_else(|e| panic!("failed to fetch app list due to error [{}]", e));
+    // filter out Coco-AI
+    current_app_list.retain(|app| app.name != tauri_app_handle.package_info().name);
+
+    let current_app_list_path_hash_index = {
+        let mut index = HashMap::new();
+        for (idx, app) in current_app_list.iter().enumerate() {
+            index.insert(get_app_path(app), idx);
+        }
+
+        index
+    };
+    let current_app_path_list: HashSet<String> =
+        current_app_list.iter().map(get_app_path).collect();
+
+    let new_apps: Vec<&String> = current_app_path_list.difference(known_app_paths).collect();
+    let removed_app_paths: Vec<String> = known_app_paths
+        .difference(&current_app_path_list)
+        .cloned()
+        .collect();
+    debug!(
+        "found new apps [{:?}], removed apps [{:?}]",
+        new_apps, removed_app_paths
+    );
+    if new_apps.is_empty() && removed_app_paths.is_empty() {
+        return;
+    }
+
+    // Synchronize the stored app list
+    let mut new_apps_pizza_engine_documents = Vec::new();
+
+    for new_app_path in new_apps {
+        let idx = *current_app_list_path_hash_index.get(new_app_path).unwrap();
+        let new_app = current_app_list.get(idx).unwrap();
+        let new_app_name = get_app_name(new_app).await;
+        let new_app_icon_path = get_app_icon_path(tauri_app_handle, new_app).await.unwrap();
+        let new_app_alias = get_app_alias(tauri_app_handle, new_app_path).unwrap_or(String::new());
+
+        let new_app_pizza_engine_document = doc!(new_app_path.clone(),  {
+            FIELD_APP_NAME => new_app_name,
+            FIELD_ICON_PATH => new_app_icon_path,
+            FIELD_APP_ALIAS => new_app_alias,
+          }
+        );
+
+        new_apps_pizza_engine_documents.push(new_app_pizza_engine_document);
+    }
+
+    let (callback, wait_for_complete) = tokio::sync::oneshot::channel();
+    let synchronize_apps_task = Box::new(SynchronizeApplicationsTask {
+        new_applications: new_apps_pizza_engine_documents,
+        removed_app_paths,
+        callback: Some(callback),
+    });
+    RUNTIME_TX
+        .get()
+        .unwrap()
+        .send(synchronize_apps_task)
+        .expect("rx dropped, pizza runtime could possibly be dead");
+    wait_for_complete
+        .await
+        .expect("tx dropped, pizza runtime could possibly be dead")
+        .unwrap_or_else(|e| panic!("failed to synchronize the app index due to error [{}]", e));
+
+    *known_app_paths = current_app_path_list;
+}
+
+/// Invoked when the user changes the search paths, once they are persisted:
+/// watch the new search paths and synchronize the app list with them.
+pub(super) fn watch_app_search_paths(search_paths: Vec<String>) {
+    start_watcher(search_paths);
+
+    if let Some(sync_tx) = APP_LIST_SYNC_TX.get() {
+        let _ = sync_tx.try_send(());
+    }
+}
+
+/// (Re)start watching `search_paths`, changes there wake up the app list synchronizer.
+fn start_watcher(search_paths: Vec<String>) {
+    let Some(sync_tx) = APP_LIST_SYNC_TX.get() else {
+        // The synchronizer is not running
+        return;
+    };
+    let sync_tx = sync_tx.clone();
+    let roots: Vec<PathBuf> = search_paths.into_iter().map(PathBuf::from).collect();
+
+    let mut watcher_guard = APP_WATCHER.lock().unwrap();
+    // Stop watching the old search paths
+    *watcher_guard = None;
+
+    // Both changes and rescans result in a full synchronization, and pending
+    // synchronization requests are coalesced since the channel has a capacity of 1.
+    let watch_result = watch(
+        QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME,
+        &roots,
+        RecursiveMode::NonRecursive,
+        move |_event| {
+            let _ = sync_tx.try_send(());
+        },
+    );
+    match watch_result {
+        Ok(watcher) => *watcher_guard = Some(watcher),
+        Err(e) => warn!(
+            "failed to watch app search paths, new apps will be found by polling, error [{}]",
+            e
+        ),
+    }
+}
+
 pub struct ApplicationSearchSource;
 
 impl ApplicationSearchSource {
@@ -434,37 +437,19 @@ impl ApplicationSearchSource {
             )
         }
 
-        app_handle
-            .store(TAURI_STORE_APP_HOTKEY)
-            .map_err(|e| e.to_string())?;
-        let disabled_app_list_and_search_path_store = app_handle
-            .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-            .map_err(|e| e.to_string())?;
-        if disabled_app_list_and_search_path_store
-            .get(TAURI_STORE_KEY_DISABLED_APP_LIST)
-            .is_none()
-        {
-            disabled_app_list_and_search_path_store
-                .set(TAURI_STORE_KEY_DISABLED_APP_LIST, Json::Array(Vec::new()));
-        }
-
-        if disabled_app_list_and_search_path_store
-            .get(TAURI_STORE_KEY_SEARCH_PATH)
-            .is_none()
-        {
-            let default_search_path = get_default_search_paths();
-            disabled_app_list_and_search_path_store
-                .set(TAURI_STORE_KEY_SEARCH_PATH, default_search_path);
-        }
-
-        register_app_hotkey_upon_start(app_handle.clone())?;
+        init_app_settings(&app_handle)?;
 
         if indexing_applications_result.is_err() {
             warn!(
                 "thread [{}] won't start because indexing applications failed",
                 THREAD_NAME_APP_SYNCHRONIZER
             )
-        } else {
+        } else if APP_LIST_SYNC_TX.get().is_none() {
+            let (sync_tx, mut sync_rx) = tokio::sync::mpsc::channel::<()>(1);
+            APP_LIST_SYNC_TX
+                .set(sync_tx)
+                .expect("the app list synchronizer should only be started once");
+
             let app_handle_clone = app_handle.clone();
             std::thread::Builder::new()
                 .name(THREAD_NAME_APP_SYNCHRONIZER.into())
@@ -476,104 +461,29 @@ impl ApplicationSearchSource {
 
                     tokio_rt.block_on(async move {
                         info!("thread [{}] started", THREAD_NAME_APP_SYNCHRONIZER);
-                        loop {
-                            tokio::time::sleep(std::time::Duration::from_secs(60 * 2)).await;
-                            debug!("app list synchronizer working");
-
-                            let stored_app_list = get_app_list(app_handle_clone.clone())
+                        let mut known_app_paths: HashSet<String> =
+                            get_app_list(app_handle_clone.clone())
                                 .await
-                                .expect("failed to fetch the stored app list");
-                            let store = app_handle_clone
-                                .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-                                .unwrap_or_else(|_e| {
-                                    panic!(
-                                        "store [{}] not found/loaded",
-                                        TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
-                                    )
-                                });
-                            let search_path_json =
-                                store.get(TAURI_STORE_KEY_SEARCH_PATH).unwrap_or_else(|| {
-                                    panic!("key [{}] not found", TAURI_STORE_KEY_SEARCH_PATH)
-                                });
-                            let search_paths: Vec<String> = match search_path_json {
-                                Json::Array(array) => array
-                                    .into_iter()
-                                    .map(|json| match json {
-                                        Json::String(str) => str,
-                                        _ => unreachable!("search path should be a string"),
-                                    })
-                                    .collect(),
-                                _ => unreachable!("search paths should be stored in an array"),
-                            };
-                            let mut current_app_list =
-                                list_app_in(search_paths).unwrap_or_else(|e| {
-                                    panic!("failed to fetch app list due to error [{}]", e)
-                                });
-                            // filter out Coco-AI
-                            current_app_list
-                                .retain(|app| app.name != app_handle.package_info().name);
-
-                            let current_app_list_path_hash_index = {
-                                let mut index = HashMap::new();
-                                for (idx, app) in current_app_list.iter().enumerate() {
-                                    index.insert(get_app_path(app), idx);
-                                }
-
-                                index
-                            };
-                            let current_app_path_list: HashSet<String> =
-                                current_app_list.iter().map(get_app_path).collect();
-                            let stored_app_path_list: HashSet<String> = stored_app_list
-                                .iter()
-                                .map(|app_entry| app_entry.path.clone())
+                                .expect("failed to fetch the stored app list")
+                                .into_iter()
+                                .map(|app_entry| app_entry.path)
                                 .collect();
-
-                            let new_apps = current_app_path_list.difference(&stored_app_path_list);
-                            debug!("found new apps [{:?}]", new_apps);
-
-                            // Synchronize the stored app list
-                            let mut new_apps_pizza_engine_documents = Vec::new();
-
-                            for new_app_path in new_apps {
-                                let idx =
-                                    *current_app_list_path_hash_index.get(new_app_path).unwrap();
-                                let new_app = current_app_list.get(idx).unwrap();
-                                let new_app_name = get_app_name(new_app).await;
-                                let new_app_icon_path =
-                                    get_app_icon_path(&app_handle_clone, new_app).await.unwrap();
-                                let new_app_alias = get_app_alias(&app_handle_clone, &new_app_path)
-                                    .unwrap_or(String::new());
-
-                                let new_app_pizza_engine_document = doc!(new_app_path.clone(),  {
-                                    FIELD_APP_NAME => new_app_name,
-                                    FIELD_ICON_PATH => new_app_icon_path,
-                                    FIELD_APP_ALIAS => new_app_alias,
-                                  }
-                                );
-
-                                new_apps_pizza_engine_documents.push(new_app_pizza_engine_document);
+                        loop {
+                            // Wake up when the watcher reports changes, or poll in
+                            // case some changes were not reported.
+                            tokio::select! {
+                                _ = sync_rx.recv() => {},
+                                _ = tokio::time::sleep(APP_LIST_SYNC_FALLBACK_INTERVAL) => {},
                             }
+                            debug!("app list synchronizer working");
 
-                            let (callback, wait_for_complete) = tokio::sync::oneshot::channel();
-                            let index_new_apps_task = Box::new(IndexNewApplicationsTask {
-                                applications: new_apps_pizza_engine_documents,
-                                callback: Some(callback),
-                            });
-                            RUNTIME_TX
-                                .get()
-                                .unwrap()
-                                .send(index_new_apps_task)
-                                .expect("rx dropped, pizza runtime could possibly be dead");
-                            wait_for_complete
-                                .await
-                                .expect("tx dropped, pizza runtime could possibly be dead")
-                                .unwrap_or_else(|e| {
-                                    panic!("failed to index new apps due to error [{}]", e)
-                                });
+                            synchronize_app_list(&app_handle_clone, &mut known_app_paths).await;
                         }
                     });
                 })
                 .unwrap();
+
+            start_watcher(get_app_search_path(app_handle.clone()).await);
         }
 
         Ok(())
@@ -608,12 +518,14 @@ impl SearchSource for ApplicationSearchSource {
             });
         }
 
+        let tauri_app_handle = GLOBAL_TAURI_APP_HANDLE
+            .get()
+            .expect("global tauri app handle not initialized");
+        let query_len = query_string.chars().count();
+
         let (tx, rx) = tokio::sync::oneshot::channel();
         let task = SearchApplicationsTask {
-            tauri_app_handle: GLOBAL_TAURI_APP_HANDLE
-                .get()
-                .expect("global tauri app handle not initialized")
-                .clone(),
+            tauri_app_handle: tauri_app_handle.clone(),
             query_string,
             callback: Some(tx),
         };
@@ -634,7 +546,18 @@ impl SearchSource for ApplicationSearchSource {
 
         let total_hits = search_result.total_hits;
         let source = self.get_type();
-        let hits = pizza_engine_hits_to_coco_hits(search_result.hits);
+        let mut hits = pizza_engine_hits_to_coco_hits(search_result.hits);
+
+        let launch_history = LaunchHistory::load(tauri_app_handle);
+        for (document, score) in hits.iter_mut() {
+            *score = launch_history.boost_score(&document.id, *score, query_len);
+        }
+        hits.sort_by(|(_, score_a), (_, score_b)| score_b.total_cmp(score_a));
+        let hits = hits
+            .into_iter()
+            .skip(query.from as usize)
+            .take(query.size as usize)
+            .collect();
 
         Ok(QueryResponse {
             source,
@@ -665,458 +588,10 @@ fn pizza_engine_hits_to_coco_hits(
             _ => unreachable!("field icon is of type Text"),
         };
 
-        let coco_document = Document {
-            source: Some(DataSourceReference {
-                r#type: Some(LOCAL_QUERY_SOURCE_TYPE.into()),
-                name: Some(QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME.into()),
-                id: Some(QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME.into()),
-                icon: None,
-            }),
-            id: app_path.clone(),
-            category: Some("Application".to_string()),
-            title: Some(app_name.clone()),
-            url: Some(app_path),
-            icon: Some(app_icon_path),
-
-            ..Default::default()
-        };
+        let coco_document = app_document(app_path, app_name, app_icon_path);
 
         coco_hits.push((coco_document, score));
     }
 
     coco_hits
 }
-
-#[tauri::command]
-pub async fn set_app_alias<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    app_path: String,
-    alias: String,
-) {
-    let store = tauri_app_handle
-        .store(TAURI_STORE_APP_ALIAS)
-        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_ALIAS));
-
-    store.set(app_path, alias);
-
-    // TODO: When pizza supports update, update index if this app's document exists there.
-    //
-    // NOTE: possible (depends on how we impl concurrency control in Pizza) TOCTOU: document gets
-    // deleted while updating it.
-}
-
-fn get_app_alias<R: Runtime>(tauri_app_handle: &AppHandle<R>, app_path: &str) -> Option<String> {
-    let store = tauri_app_handle
-        .store(TAURI_STORE_APP_ALIAS)
-        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_ALIAS));
-
-    let json = store.get(app_path)?;
-
-    let string = match json {
-        Json::String(s) => s,
-        _ => unreachable!("app alias should be stored in a string"),
-    };
-
-    Some(string)
-}
-
-/// The handler that will be invoked when an application hotkey is pressed.
-///
-/// The `app_path` argument is for logging-only.
-fn app_hotkey_handler<R: Runtime>(
-    app_path: String,
-) -> impl Fn(&AppHandle<R>, &Shortcut, ShortcutEvent) + Send + Sync + 'static {
-    move |tauri_app_handle, _hot_key, event| {
-        if event.state() == ShortcutState::Pressed {
-            let app_path_clone = app_path.clone();
-            let tauri_app_handle_clone = tauri_app_handle.clone();
-            // This closure will be executed on the main thread, so we spawn to reduce the potential UI lag.
-            async_runtime::spawn(async move {
-                if let Err(e) = open(tauri_app_handle_clone, app_path_clone).await {
-                    warn!("failed to open app due to [{}]", e);
-                }
-            });
-        }
-    }
-}
-
-fn register_app_hotkey_upon_start<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-) -> Result<(), String> {
-    let app_hotkey_store = tauri_app_handle
-        .store(TAURI_STORE_APP_HOTKEY)
-        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
-
-    for (app_path, hotkey) in app_hotkey_store.entries() {
-        let hotkey = match hotkey {
-            Json::String(str) => str,
-            _ => unreachable!("hotkey should be stored in a string"),
-        };
-
-        tauri_app_handle
-            .global_shortcut()
-            .on_shortcut(hotkey.as_str(), app_hotkey_handler(app_path))
-            .map_err(|e| e.to_string())?;
-    }
-
-    Ok(())
-}
-
-#[tauri::command]
-pub async fn register_app_hotkey<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    app_path: String,
-    hotkey: String,
-) -> Result<(), String> {
-    let app_hotkey_store = tauri_app_handle
-        .store(TAURI_STORE_APP_HOTKEY)
-        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
-
-    app_hotkey_store.set(app_path.clone(), hotkey.as_str());
-
-    tauri_app_handle
-        .global_shortcut()
-        .on_shortcut(hotkey.as_str(), app_hotkey_handler(app_path))
-        .map_err(|e| e.to_string())?;
-
-    Ok(())
-}
-
-#[tauri::command]
-pub async fn unregister_app_hotkey<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    app_path: String,
-) -> Result<(), String> {
-    let app_hotkey_store = tauri_app_handle
-        .store(TAURI_STORE_APP_HOTKEY)
-        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
-
-    let Some(hotkey) = app_hotkey_store.get(app_path.as_str()) else {
-        let error_msg = format!(
-            "unregister an Application hotkey that does not exist app: [{}]",
-            app_path,
-        );
-        warn!("{}", error_msg);
-        return Err(error_msg);
-    };
-
-    let hotkey = match hotkey {
-        Json::String(str) => str,
-        _ => unreachable!("hotkey should be stored in a string"),
-    };
-
-    let deleted = app_hotkey_store.delete(app_path.as_str());
-    if !deleted {
-        return Err("failed to delete application hotkey from store".into());
-    }
-
-    tauri_app_handle
-        .global_shortcut()
-        .unregister(hotkey.as_str())
-        .map_err(|e| e.to_string())?;
-
-    Ok(())
-}
-
-fn get_disabled_app_list<R: Runtime>(tauri_app_handle: AppHandle<R>) -> Vec<String> {
-    let store = tauri_app_handle
-        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-        .unwrap_or_else(|_| {
-            panic!(
-                "tauri store [{}] not found/loaded",
-                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
-            )
-        });
-    let disabled_app_list_json = store
-        .get(TAURI_STORE_KEY_DISABLED_APP_LIST)
-        .unwrap_or_else(|| panic!("key [{}] not found", TAURI_STORE_KEY_DISABLED_APP_LIST));
-
-    let disabled_app_list: Vec<String> = match disabled_app_list_json {
-        Json::Array(a) => a
-            .into_iter()
-            .map(|json| match json {
-                Json::String(s) => s,
-                _ => unreachable!("app_path is stored in a string"),
-            })
-            .collect(),
-        _ => unreachable!("disabled app list is stored in an array"),
-    };
-
-    disabled_app_list
-}
-
-#[tauri::command]
-pub async fn disable_app_search<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    app_path: String,
-) -> Result<(), String> {
-    let store = tauri_app_handle
-        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-        .unwrap_or_else(|_| {
-            panic!(
-                "tauri store [{}] not found/loaded",
-                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
-            )
-        });
-
-    let mut disabled_app_list = get_disabled_app_list(tauri_app_handle);
-
-    if disabled_app_list.contains(&app_path) {
-        return Err(format!(
-            "trying to disable an app that is disabled [{}]",
-            app_path
-        ));
-    }
-
-    disabled_app_list.push(app_path);
-
-    store.set(TAURI_STORE_KEY_DISABLED_APP_LIST, disabled_app_list);
-
-    Ok(())
-}
-
-#[tauri::command]
-pub async fn enable_app_search<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    app_path: String,
-) -> Result<(), String> {
-    let store = tauri_app_handle
-        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-        .unwrap_or_else(|_| {
-            panic!(
-                "tauri store [{}] not found/loaded",
-                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
-            )
-        });
-
-    let mut disabled_app_list = get_disabled_app_list(tauri_app_handle);
-
-    match disabled_app_list
-        .iter()
-        .position(|app_path_str| app_path_str == &app_path)
-    {
-        Some(index) => {
-            disabled_app_list.remove(index);
-            store.set(TAURI_STORE_KEY_DISABLED_APP_LIST, disabled_app_list);
-
-            Ok(())
-        }
-        None => Err(format!(
-            "trying to enable an app that is not disabled [{}]",
-            app_path
-        )),
-    }
-}
-
-#[tauri::command]
-pub async fn add_app_search_path<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    search_path: String,
-) -> Result<(), String> {
-    let mut search_paths = get_app_search_path(tauri_app_handle.clone()).await;
-    if search_paths.contains(&search_path) {
-        return Ok(());
-    }
-
-    search_paths.push(search_path);
-
-    let store = tauri_app_handle
-        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-        .unwrap_or_else(|_| {
-            panic!(
-                "store [{}] not found/loaded",
-                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
-            )
-        });
-
-    store.set(TAURI_STORE_KEY_SEARCH_PATH, search_paths);
-
-    Ok(())
-}
-
-#[tauri::command]
-pub async fn remove_app_search_path<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    search_path: String,
-) -> Result<(), String> {
-    let mut search_paths = get_app_search_path(tauri_app_handle.clone()).await;
-    let opt_index = search_paths.iter().position(|path| path == &search_path);
-    let Some(index) = opt_index else {
-        return Ok(());
-    };
-
-    search_paths.remove(index);
-    let store = tauri_app_handle
-        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-        .unwrap_or_else(|_| {
-            panic!(
-                "store [{}] not found/loaded",
-                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
-            )
-        });
-
-    store.set(TAURI_STORE_KEY_SEARCH_PATH, search_paths);
-
-    Ok(())
-}
-
-#[tauri::command]
-pub async fn get_app_search_path<R: Runtime>(tauri_app_handle: AppHandle<R>) -> Vec<String> {
-    let store = tauri_app_handle
-        .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-        .unwrap_or_else(|_| {
-            panic!(
-                "store [{}] not found/loaded",
-                TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH
-            )
-        });
-
-    let search_path_json = store
-        .get(TAURI_STORE_KEY_SEARCH_PATH)
-        .unwrap_or_else(|| panic!("key [{}] not found", TAURI_STORE_KEY_SEARCH_PATH));
-
-    let search_path: Vec<String> = match search_path_json {
-        Json::Array(array) => array
-            .into_iter()
-            .map(|json| match json {
-                Json::String(str) => str,
-                _ => unreachable!("search path is stored in a string"),
-            })
-            .collect(),
-        _ => unreachable!("search path is stored in an array"),
-    };
-
-    search_path
-}
-
-#[tauri::command]
-pub async fn get_app_list<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-) -> Result<Vec<AppEntry>, String> {
-    let search_paths = get_app_search_path(tauri_app_handle.clone()).await;
-    let apps = list_app_in(search_paths)?;
-
-    let mut app_entries = Vec::with_capacity(apps.len());
-
-    for app in apps {
-        let name = get_app_name(&app).await;
-
-        // filter out Coco-AI
-        if name.eq(&tauri_app_handle.package_info().name) {
-            continue;
-        }
-
-        let path = get_app_path(&app);
-        let icon_path = get_app_icon_path(&tauri_app_handle, &app).await.unwrap();
-        let alias = {
-            let store = tauri_app_handle
-                .store(TAURI_STORE_APP_ALIAS)
-                .map_err(|e| e.to_string())?;
-            let opt_string = store.get(&path).map(|json| match json {
-                Json::String(s) => s,
-                _ => unreachable!("app alias should be stored in a string"),
-            });
-
-            opt_string.unwrap_or(String::new())
-        };
-        let hotkey = {
-            let store = tauri_app_handle
-                .store(TAURI_STORE_APP_HOTKEY)
-                .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
-            let opt_string = store.get(&path).map(|json| match json {
-                Json::String(s) => s,
-                _ => unreachable!("app hotkey should be stored in a string"),
-            });
-
-            opt_string.unwrap_or(String::new())
-        };
-        let is_disabled = {
-            let store = tauri_app_handle
-                .store(TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH)
-                .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_APP_HOTKEY));
-            let disabled_app_list_json = store
-                .get(TAURI_STORE_KEY_DISABLED_APP_LIST)
-                .unwrap_or_else(|| {
-                    panic!(
-                        "store [{}] does not contain key [{}]",
-                        TAURI_STORE_DISABLED_APP_LIST_AND_SEARCH_PATH,
-                        TAURI_STORE_KEY_DISABLED_APP_LIST
-                    )
-                });
-
-            let disabled_app_list = match disabled_app_list_json {
-                Json::Array(v) => v
-                    .into_iter()
-                    .map(|json| match json {
-                        Json::String(str) => str,
-                        _ => unreachable!("app path should be stored in a string"),
-                    })
-                    .collect::<Vec<String>>(),
-                _ => unreachable!("disabled app list should be stored in an array"),
-            };
-
-            disabled_app_list.contains(&path)
-        };
-
-        let app_entry = AppEntry {
-            path,
-            name,
-            icon_path,
-            alias,
-            hotkey,
-            is_disabled,
-        };
-
-        app_entries.push(app_entry);
-    }
-
-    Ok(app_entries)
-}
-
-#[tauri::command]
-pub async fn get_app_metadata<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    app_path: String,
-) -> Result<AppMetadata, String> {
-    let app =
-        App::from_path(std::path::Path::new(&app_path)).expect("frontend sends an invalid app");
-
-    let app_path = get_app_path(&app);
-    let app_name = get_app_name(&app).await;
-    let app_path_where = {
-        let app_path_borrowed_path = std::path::Path::new(app_path.as_str());
-        let app_path_where = app_path_borrowed_path
-            .parent()
-            .expect("every app file should live somewhere");
-
-        app_path_where
-            .to_str()
-            .expect("it is guaranteed to be UTF-8 encoded")
-            .to_string()
-    };
-    let icon = get_app_icon_path(&tauri_app_handle, &app).await?;
-
-    let raw_app_metadata = metadata(app_path.into(), None).await?;
-
-    let last_opened = if cfg!(any(target_os = "macos", target_os = "windows")) {
-        let app_exe_path = app
-            .app_path_exe
-            .as_ref()
-            .expect("exe path should be Some")
-            .clone();
-        let raw_app_exe_metadata = metadata(app_exe_path, None).await?;
-        raw_app_exe_metadata.accessed_at
-    } else {
-        raw_app_metadata.accessed_at
-    };
-
-    Ok(AppMetadata {
-        name: app_name,
-        r#where: app_path_where,
-        size: raw_app_metadata.size,
-        icon,
-        created: raw_app_metadata.created_at,
-        modified: raw_app_metadata.modified_at,
-        last_opened,
-    })
-}
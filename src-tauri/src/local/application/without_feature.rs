@@ -1,22 +1,145 @@
-use super::AppEntry;
-use super::AppMetadata;
+use super::super::watcher::{watch, LocalIndexWatcher};
+use super::{
+    app_document, get_app_alias, get_app_icon_path, get_app_name, get_app_path,
+    get_app_search_path, get_disabled_app_list, init_app_settings, list_app_in,
+    QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME,
+};
 use crate::common::error::SearchError;
 use crate::common::search::{QueryResponse, QuerySource, SearchQuery};
 use crate::common::traits::SearchSource;
 use crate::local::LOCAL_QUERY_SOURCE_TYPE;
+use crate::util::frecency::LaunchHistory;
+use crate::util::fuzzy::fuzzy_score;
+use crate::GLOBAL_TAURI_APP_HANDLE;
 use async_trait::async_trait;
-use tauri::{AppHandle, Runtime};
+use log::{debug, warn};
+use notify::RecursiveMode;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use tauri::{async_runtime, AppHandle, Runtime};
 
-pub(crate) const QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME: &str = "Applications";
+/// An app found in the search paths, everything we need to search and display it.
+struct ListedApp {
+    path: String,
+    name: String,
+    icon_path: String,
+}
+
+lazy_static::lazy_static! {
+    /// Apps found in the search paths, refreshed when they change.
+    static ref APP_LIST: RwLock<Vec<ListedApp>> = RwLock::new(Vec::new());
+}
+
+/// Watcher of the app search paths, replaced whenever they change.
+static APP_WATCHER: Mutex<Option<LocalIndexWatcher>> = Mutex::new(None);
 
 pub struct ApplicationSearchSource;
 
 impl ApplicationSearchSource {
-    pub async fn init<R: Runtime>(_app_handle: AppHandle<R>) -> Result<(), String> {
+    pub async fn init<R: Runtime>(app_handle: AppHandle<R>) -> Result<(), String> {
+        init_app_settings(&app_handle)?;
+
+        let search_paths = get_app_search_path(app_handle.clone()).await;
+        if let Err(e) = refresh_app_list(&app_handle, search_paths.clone()).await {
+            warn!(
+                "listing local applications failed, app search won't work until the app search paths change, error [{}]",
+                e
+            );
+        }
+        start_watcher(&app_handle, search_paths);
+
         Ok(())
     }
 }
 
+/// List the apps in `search_paths` and replace [`APP_LIST`] with them.
+async fn refresh_app_list<R: Runtime>(
+    tauri_app_handle: &AppHandle<R>,
+    search_paths: Vec<String>,
+) -> Result<(), String> {
+    let apps = list_app_in(search_paths)?;
+
+    let mut app_list = Vec::with_capacity(apps.len());
+    for app in apps.iter() {
+        let name = get_app_name(app).await;
+        // filter out Coco-AI
+        if name.is_empty() || name.eq(&tauri_app_handle.package_info().name) {
+            continue;
+        }
+
+        let path = get_app_path(app);
+        let icon_path = match get_app_icon_path(tauri_app_handle, app).await {
+            Ok(icon_path) => icon_path,
+            Err(e) => {
+                warn!(
+                    "failed to get the icon of application [app name: '{}', app path: '{}'] due to error [{}]",
+                    name, path, e
+                );
+                continue;
+            }
+        };
+
+        app_list.push(ListedApp {
+            path,
+            name,
+            icon_path,
+        });
+    }
+
+    debug!("found [{}] local applications", app_list.len());
+    *APP_LIST.write().unwrap() = app_list;
+
+    Ok(())
+}
+
+/// (Re)start watching `search_paths`, any change there refreshes the app list.
+fn start_watcher<R: Runtime>(tauri_app_handle: &AppHandle<R>, search_paths: Vec<String>) {
+    let roots: Vec<PathBuf> = search_paths.iter().map(PathBuf::from).collect();
+
+    let mut watcher_guard = APP_WATCHER.lock().unwrap();
+    // Stop watching the old search paths
+    *watcher_guard = None;
+
+    let tauri_app_handle = tauri_app_handle.clone();
+    // Re-listing apps is cheap enough that we do it for both changes and rescans.
+    let watch_result = watch(
+        QUERYSOURCE_ID_DATASOURCE_ID_DATASOURCE_NAME,
+        &roots,
+        RecursiveMode::NonRecursive,
+        move |_event| {
+            let refresh_result =
+                async_runtime::block_on(refresh_app_list(&tauri_app_handle, search_paths.clone()));
+            if let Err(e) = refresh_result {
+                warn!("failed to refresh the app list due to error [{}]", e);
+            }
+        },
+    );
+    match watch_result {
+        Ok(watcher) => *watcher_guard = Some(watcher),
+        Err(e) => warn!(
+            "failed to watch app search paths, app list won't be refreshed, error [{}]",
+            e
+        ),
+    }
+}
+
+/// Invoked when the user changes the search paths, refresh the app list and
+/// watch the new search paths.
+pub(super) fn watch_app_search_paths(search_paths: Vec<String>) {
+    let tauri_app_handle = GLOBAL_TAURI_APP_HANDLE
+        .get()
+        .expect("global tauri app handle not initialized");
+
+    start_watcher(tauri_app_handle, search_paths.clone());
+
+    let tauri_app_handle = tauri_app_handle.clone();
+    async_runtime::spawn(async move {
+        if let Err(e) = refresh_app_list(&tauri_app_handle, search_paths).await {
+            warn!("failed to refresh the app list due to error [{}]", e);
+        }
+    });
+}
+
 #[async_trait]
 impl SearchSource for ApplicationSearchSource {
     fn get_type(&self) -> QuerySource {
@@ -30,91 +153,60 @@ impl SearchSource for ApplicationSearchSource {
         }
     }
 
-    async fn search(&self, _query: SearchQuery) -> Result<QueryResponse, SearchError> {
-        Ok(QueryResponse {
-            source: self.get_type(),
-            hits: Vec::new(),
-            total_hits: 0,
-        })
-    }
-}
-
-#[tauri::command]
-pub async fn set_app_alias(_app_path: String, _alias: String) -> Result<(), String> {
-    unreachable!("app list should be empty, there is no way this can be invoked")
-}
+    async fn search(&self, query: SearchQuery) -> Result<QueryResponse, SearchError> {
+        let query_string = query
+            .query_strings
+            .get("query")
+            .map(|query_string| query_string.trim())
+            .unwrap_or_default();
 
-#[tauri::command]
-pub async fn register_app_hotkey<R: Runtime>(
-    _tauri_app_handle: AppHandle<R>,
-    _app_path: String,
-    _hotkey: String,
-) -> Result<(), String> {
-    unreachable!("app list should be empty, there is no way this can be invoked")
-}
-
-#[tauri::command]
-pub async fn unregister_app_hotkey<R: Runtime>(
-    _tauri_app_handle: AppHandle<R>,
-    _app_path: String,
-) -> Result<(), String> {
-    unreachable!("app list should be empty, there is no way this can be invoked")
-}
-
-#[tauri::command]
-pub async fn disable_app_search<R: Runtime>(
-    _tauri_app_handle: AppHandle<R>,
-    _app_path: String,
-) -> Result<(), String> {
-    // no-op
-    Ok(())
-}
+        if query_string.is_empty() {
+            return Ok(QueryResponse {
+                source: self.get_type(),
+                hits: Vec::new(),
+                total_hits: 0,
+            });
+        }
 
-#[tauri::command]
-pub async fn enable_app_search<R: Runtime>(
-    _tauri_app_handle: AppHandle<R>,
-    _app_path: String,
-) -> Result<(), String> {
-    // no-op
-    Ok(())
-}
+        let tauri_app_handle = GLOBAL_TAURI_APP_HANDLE
+            .get()
+            .expect("global tauri app handle not initialized");
+        let disabled_app_list = get_disabled_app_list(tauri_app_handle.clone());
+        let launch_history = LaunchHistory::load(tauri_app_handle);
+        let query_len = query_string.chars().count();
 
-#[tauri::command]
-pub async fn add_app_search_path<R: Runtime>(
-    _tauri_app_handle: AppHandle<R>,
-    _search_path: String,
-) -> Result<(), String> {
-    // no-op
-    Ok(())
-}
+        let mut hits = Vec::new();
+        for app in APP_LIST.read().unwrap().iter() {
+            if disabled_app_list.contains(&app.path) {
+                continue;
+            }
 
-#[tauri::command]
-pub async fn remove_app_search_path<R: Runtime>(
-    _tauri_app_handle: AppHandle<R>,
-    _search_path: String,
-) -> Result<(), String> {
-    // no-op
-    Ok(())
-}
+            let name_score = fuzzy_score(query_string, &app.name);
+            let alias_score = get_app_alias(tauri_app_handle, &app.path)
+                .and_then(|alias| fuzzy_score(query_string, &alias));
+            let score = match (name_score, alias_score) {
+                (Some(name_score), Some(alias_score)) => name_score.max(alias_score),
+                (Some(score), None) | (None, Some(score)) => score,
+                (None, None) => continue,
+            };
+            let score = launch_history.boost_score(&app.path, score, query_len);
 
-#[tauri::command]
-pub async fn get_app_search_path<R: Runtime>(_tauri_app_handle: AppHandle<R>) -> Vec<String> {
-    // Return an empty list
-    Vec::new()
-}
+            let document = app_document(app.path.clone(), app.name.clone(), app.icon_path.clone());
+            hits.push((document, score));
+        }
 
-#[tauri::command]
-pub async fn get_app_list<R: Runtime>(
-    _tauri_app_handle: AppHandle<R>,
-) -> Result<Vec<AppEntry>, String> {
-    // Return an empty list
-    Ok(Vec::new())
-}
+        hits.sort_by(|(_, score_a), (_, score_b)| score_b.total_cmp(score_a));
+        let total_hits = hits.len();
+        let hits = hits
+            .into_iter()
+            .skip(query.from as usize)
+            .take(query.size as usize)
+            .collect();
 
-#[tauri::command]
-pub async fn get_app_metadata<R: Runtime>(
-    _tauri_app_handle: AppHandle<R>,
-    _app_path: String,
-) -> Result<AppMetadata, String> {
-    unreachable!("app list should be empty, there is no way this can be invoked")
+        Ok(QueryResponse {
+            source: self.get_type(),
+            hits,
+            total_hits,
+        })
+    }
 }
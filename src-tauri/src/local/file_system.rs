@@ -1 +1,528 @@
+//! Local file search.
+//!
+//! Files under the user-configured search paths are indexed in memory (name,
+//! path, extension, size and modification time), queries are matched against
+//! file names. The index is kept up to date by a file system watcher.
 
+use super::watcher::{watch, LocalIndexWatcher, WatchEvent};
+use super::LOCAL_QUERY_SOURCE_TYPE;
+use crate::common::document::{DataSourceReference, Document};
+use crate::common::error::SearchError;
+use crate::common::search::{QueryResponse, QuerySource, SearchQuery};
+use crate::common::traits::SearchSource;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use notify::RecursiveMode;
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::UNIX_EPOCH;
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_store::StoreExt;
+use walkdir::WalkDir;
+
+/// We use this as:
+///
+/// 1. querysource ID
+/// 2. datasource ID
+/// 3. datasource name
+pub(crate) const DATA_SOURCE_ID: &str = "Files";
+
+const TAURI_STORE_FILE_SYSTEM_SEARCH: &str = "file_system_search";
+const TAURI_STORE_KEY_SEARCH_PATH: &str = "search_path";
+
+/// Do not descend deeper than this when indexing a search path.
+const MAX_INDEX_DEPTH: usize = 8;
+/// Upper bound of the number of files kept in the index.
+const MAX_INDEXED_FILES: usize = 200_000;
+
+#[derive(Debug, Clone)]
+struct FileEntry {
+    name: String,
+    /// Lowercased `name`, cached so that we don't do this for every query.
+    name_lowercase: String,
+    path: String,
+    extension: Option<String>,
+    size: u64,
+    /// Modification time, in milliseconds since the Unix epoch.
+    modified: Option<i64>,
+}
+
+impl FileEntry {
+    /// Return `None` if `path` is not a regular file or is not UTF-8 encoded.
+    fn from_path(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+
+        let name = path.file_name()?.to_str()?.to_string();
+        let path_string = path.to_str()?.to_string();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_millis() as i64);
+
+        Some(Self {
+            name_lowercase: name.to_lowercase(),
+            name,
+            path: path_string,
+            extension,
+            size: metadata.len(),
+            modified,
+        })
+    }
+
+    fn to_document(&self) -> Document {
+        let mut metadata = HashMap::new();
+        if let Some(ref extension) = self.extension {
+            metadata.insert("extension".to_string(), Json::String(extension.clone()));
+        }
+        if let Some(modified) = self.modified {
+            metadata.insert("modified".to_string(), Json::from(modified));
+        }
+
+        Document {
+            source: Some(DataSourceReference {
+                r#type: Some(LOCAL_QUERY_SOURCE_TYPE.into()),
+                name: Some(DATA_SOURCE_ID.into()),
+                id: Some(DATA_SOURCE_ID.into()),
+                icon: None,
+            }),
+            id: self.path.clone(),
+            category: Some("File".to_string()),
+            subcategory: self.extension.clone(),
+            title: Some(self.name.clone()),
+            url: Some(self.path.clone()),
+            size: Some(self.size as i64),
+            updated: self
+                .modified
+                .and_then(chrono::DateTime::<chrono::Utc>::from_timestamp_millis)
+                .map(|datetime| datetime.to_rfc3339()),
+            metadata: Some(metadata),
+
+            ..Default::default()
+        }
+    }
+
+    /// Score this file against `query`, which should be lowercased. Return
+    /// `None` if it does not match.
+    fn score(&self, query: &str) -> Option<f64> {
+        let base = if self.name_lowercase == query {
+            100.0
+        } else if self.name_lowercase.starts_with(query) {
+            80.0
+        } else if self.name_lowercase.contains(query) {
+            50.0
+        } else {
+            return None;
+        };
+
+        // Prefer files whose name is mostly covered by the query.
+        let coverage = query.len() as f64 / self.name_lowercase.len() as f64;
+
+        Some(base + coverage * 10.0)
+    }
+}
+
+lazy_static! {
+    /// File path => indexed file
+    static ref FILE_INDEX: RwLock<HashMap<PathBuf, FileEntry>> = RwLock::new(HashMap::new());
+    /// Watcher of the current search paths, replaced whenever they change.
+    static ref FILE_WATCHER: Mutex<Option<LocalIndexWatcher>> = Mutex::new(None);
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.starts_with('.'))
+        .unwrap_or(false)
+}
+
+/// Return true if `path` lives in one of the `roots` and is not hidden.
+fn is_indexable(roots: &[PathBuf], path: &Path) -> bool {
+    roots.iter().any(|root| match path.strip_prefix(root) {
+        Ok(relative) => !relative.components().any(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .map(|str| str.starts_with('.'))
+                .unwrap_or(false)
+        }),
+        Err(_) => false,
+    })
+}
+
+/// Depth of `path` in the root it lives in, 0 for a root itself.
+fn depth_in_root(roots: &[PathBuf], path: &Path) -> Option<usize> {
+    roots.iter().find_map(|root| {
+        path.strip_prefix(root)
+            .ok()
+            .map(|relative| relative.components().count())
+    })
+}
+
+/// Walk through `dir`, `max_depth` levels deep at most, and add the files
+/// found there to `index` until it has `max_files` files. Return false once it
+/// has.
+fn index_files_under(
+    dir: &Path,
+    max_depth: usize,
+    max_files: usize,
+    index: &mut HashMap<PathBuf, FileEntry>,
+) -> bool {
+    let walker = WalkDir::new(dir)
+        .max_depth(max_depth)
+        .into_iter()
+        // depth 0 is the search path itself, we index it even if it is hidden
+        .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.path()));
+
+    for entry in walker {
+        if index.len() >= max_files {
+            warn!(
+                "local file index reaches its limit [{}], the remaining files won't be indexed",
+                MAX_INDEXED_FILES
+            );
+            return false;
+        }
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("skipping an entry under [{}] due to [{}]", dir.display(), e);
+                continue;
+            }
+        };
+
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        if let Some(file_entry) = FileEntry::from_path(entry.path()) {
+            index.insert(entry.into_path(), file_entry);
+        }
+    }
+
+    true
+}
+
+/// Walk through `search_paths` and collect the files found there.
+fn index_files_in<P: AsRef<Path>>(search_paths: &[P]) -> HashMap<PathBuf, FileEntry> {
+    let mut index = HashMap::new();
+
+    for search_path in search_paths {
+        if !index_files_under(
+            search_path.as_ref(),
+            MAX_INDEX_DEPTH,
+            MAX_INDEXED_FILES,
+            &mut index,
+        ) {
+            break;
+        }
+    }
+
+    index
+}
+
+/// Re-index all the files under `search_paths`, this function blocks.
+fn rebuild_index(search_paths: &[PathBuf]) {
+    let index = index_files_in(search_paths);
+    debug!(
+        "indexed [{}] local files under [{:?}]",
+        index.len(),
+        search_paths
+    );
+
+    *FILE_INDEX.write().unwrap() = index;
+}
+
+/// Apply the changes reported by the watcher to the index.
+fn apply_changes(roots: &[PathBuf], changed_paths: Vec<PathBuf>) {
+    for path in changed_paths {
+        if !is_indexable(roots, &path) {
+            continue;
+        }
+        // Within the depth limit of the root, as when the index is rebuilt
+        let depth = depth_in_root(roots, &path).unwrap_or_default();
+
+        if path.is_dir() {
+            // A directory was created or moved in, index everything inside it,
+            // as long as the index has room for it.
+            let max_files = MAX_INDEXED_FILES.saturating_sub(FILE_INDEX.read().unwrap().len());
+            let mut files = HashMap::new();
+            index_files_under(
+                &path,
+                MAX_INDEX_DEPTH.saturating_sub(depth),
+                max_files,
+                &mut files,
+            );
+            FILE_INDEX.write().unwrap().extend(files);
+        } else if let Some(file_entry) = FileEntry::from_path(&path) {
+            let mut index = FILE_INDEX.write().unwrap();
+            if depth <= MAX_INDEX_DEPTH
+                && (index.len() < MAX_INDEXED_FILES || index.contains_key(&path))
+            {
+                index.insert(path, file_entry);
+            }
+        } else {
+            // Removed or renamed away
+            let mut index = FILE_INDEX.write().unwrap();
+            if index.remove(&path).is_none() {
+                // Not a file we know, it could be a directory
+                index.retain(|indexed_path, _| !indexed_path.starts_with(&path));
+            }
+        }
+    }
+}
+
+/// Rebuild the index and (re)start watching the search paths for changes.
+///
+/// Work is done in a blocking task so that the caller won't be blocked.
+fn reindex_and_watch<R: Runtime>(tauri_app_handle: &AppHandle<R>) {
+    let tauri_app_handle = tauri_app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        // Holding this lock serializes concurrent re-indexing
+        let mut watcher_guard = FILE_WATCHER.lock().unwrap();
+        // Stop watching the old search paths
+        *watcher_guard = None;
+
+        let roots: Vec<PathBuf> = get_search_paths(&tauri_app_handle)
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        rebuild_index(&roots);
+
+        let roots_clone = roots.clone();
+        let watch_result = watch(
+            DATA_SOURCE_ID,
+            &roots,
+            RecursiveMode::Recursive,
+            move |event| match event {
+                WatchEvent::Changed(paths) => apply_changes(&roots_clone, paths),
+                WatchEvent::Rescan => rebuild_index(&roots_clone),
+            },
+        );
+        match watch_result {
+            Ok(watcher) => *watcher_guard = Some(watcher),
+            Err(e) => warn!(
+                "failed to watch local file search paths, the index won't be updated, error [{}]",
+                e
+            ),
+        }
+    });
+}
+
+fn get_default_search_paths() -> Vec<String> {
+    [
+        dirs::desktop_dir(),
+        dirs::document_dir(),
+        dirs::download_dir(),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|path| path.is_dir())
+    .filter_map(|path| path.into_os_string().into_string().ok())
+    .collect()
+}
+
+fn get_search_paths<R: Runtime>(tauri_app_handle: &AppHandle<R>) -> Vec<String> {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_FILE_SYSTEM_SEARCH)
+        .unwrap_or_else(|_| {
+            panic!(
+                "store [{}] not found/loaded",
+                TAURI_STORE_FILE_SYSTEM_SEARCH
+            )
+        });
+
+    let search_path_json = store
+        .get(TAURI_STORE_KEY_SEARCH_PATH)
+        .unwrap_or_else(|| panic!("key [{}] not found", TAURI_STORE_KEY_SEARCH_PATH));
+
+    match search_path_json {
+        Json::Array(array) => array
+            .into_iter()
+            .map(|json| match json {
+                Json::String(str) => str,
+                _ => unreachable!("search path is stored in a string"),
+            })
+            .collect(),
+        _ => unreachable!("search path is stored in an array"),
+    }
+}
+
+fn set_search_paths<R: Runtime>(tauri_app_handle: &AppHandle<R>, search_paths: Vec<String>) {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_FILE_SYSTEM_SEARCH)
+        .unwrap_or_else(|_| {
+            panic!(
+                "store [{}] not found/loaded",
+                TAURI_STORE_FILE_SYSTEM_SEARCH
+            )
+        });
+
+    store.set(TAURI_STORE_KEY_SEARCH_PATH, search_paths);
+}
+
+pub struct FileSystemSearchSource;
+
+impl FileSystemSearchSource {
+    pub async fn init<R: Runtime>(app_handle: AppHandle<R>) -> Result<(), String> {
+        let store = app_handle
+            .store(TAURI_STORE_FILE_SYSTEM_SEARCH)
+            .map_err(|e| e.to_string())?;
+        if store.get(TAURI_STORE_KEY_SEARCH_PATH).is_none() {
+            store.set(TAURI_STORE_KEY_SEARCH_PATH, get_default_search_paths());
+        }
+
+        reindex_and_watch(&app_handle);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SearchSource for FileSystemSearchSource {
+    fn get_type(&self) -> QuerySource {
+        QuerySource {
+            r#type: LOCAL_QUERY_SOURCE_TYPE.into(),
+            name: hostname::get()
+                .unwrap_or("My Computer".into())
+                .to_string_lossy()
+                .into(),
+            id: DATA_SOURCE_ID.into(),
+        }
+    }
+
+    async fn search(&self, query: SearchQuery) -> Result<QueryResponse, SearchError> {
+        let query_string = query
+            .query_strings
+            .get("query")
+            .map(|query_string| query_string.trim().to_lowercase())
+            .unwrap_or_default();
+
+        if query_string.is_empty() {
+            return Ok(QueryResponse {
+                source: self.get_type(),
+                hits: Vec::new(),
+                total_hits: 0,
+            });
+        }
+
+        let mut matches: Vec<(&FileEntry, f64)> = Vec::new();
+        let index = FILE_INDEX.read().unwrap();
+        for file_entry in index.values() {
+            if let Some(score) = file_entry.score(&query_string) {
+                matches.push((file_entry, score));
+            }
+        }
+
+        let total_hits = matches.len();
+        matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let hits = matches
+            .into_iter()
+            .skip(query.from as usize)
+            .take(query.size as usize)
+            .map(|(file_entry, score)| (file_entry.to_document(), score))
+            .collect();
+
+        Ok(QueryResponse {
+            source: self.get_type(),
+            hits,
+            total_hits,
+        })
+    }
+}
+
+#[tauri::command]
+pub async fn get_file_system_search_paths<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+) -> Vec<String> {
+    get_search_paths(&tauri_app_handle)
+}
+
+#[tauri::command]
+pub async fn add_file_system_search_path<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    search_path: String,
+) -> Result<(), String> {
+    if !Path::new(&search_path).is_dir() {
+        return Err(format!("search path [{}] is not a directory", search_path));
+    }
+
+    let mut search_paths = get_search_paths(&tauri_app_handle);
+    if search_paths.contains(&search_path) {
+        return Ok(());
+    }
+
+    search_paths.push(search_path);
+    set_search_paths(&tauri_app_handle, search_paths);
+    reindex_and_watch(&tauri_app_handle);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_file_system_search_path<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    search_path: String,
+) -> Result<(), String> {
+    let mut search_paths = get_search_paths(&tauri_app_handle);
+    let Some(index) = search_paths.iter().position(|path| path == &search_path) else {
+        return Ok(());
+    };
+
+    search_paths.remove(index);
+    set_search_paths(&tauri_app_handle, search_paths);
+    reindex_and_watch(&tauri_app_handle);
+
+    Ok(())
+}
+
+#[test]
+fn test_index_files_under() {
+    let root = std::env::temp_dir().join(format!("coco-test-index-{}", std::process::id()));
+    let nested = root.join("moved-in").join("nested");
+    std::fs::create_dir_all(&nested).unwrap();
+    for file in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(root.join("moved-in").join(file), "").unwrap();
+    }
+    std::fs::write(nested.join("deep.txt"), "").unwrap();
+
+    let roots = [root.clone()];
+    let moved_in = root.join("moved-in");
+    assert_eq!(depth_in_root(&roots, &root), Some(0));
+    assert_eq!(depth_in_root(&roots, &moved_in), Some(1));
+    assert_eq!(depth_in_root(&roots, Path::new("/elsewhere")), None);
+
+    // The depth limit applies from the root, not from the directory moved in
+    let mut index = HashMap::new();
+    assert!(index_files_under(
+        &moved_in,
+        1,
+        MAX_INDEXED_FILES,
+        &mut index
+    ));
+    assert_eq!(index.len(), 3);
+    assert!(!index.contains_key(&nested.join("deep.txt")));
+
+    // It stops once the index is full
+    let mut index = HashMap::new();
+    assert!(!index_files_under(
+        &moved_in,
+        MAX_INDEX_DEPTH,
+        2,
+        &mut index
+    ));
+    assert_eq!(index.len(), 2);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -1,6 +1,9 @@
 pub mod application;
 pub mod calculator;
 pub mod file_system;
+pub mod pinned;
+pub mod search_history;
+mod watcher;
 
 use std::any::Any;
 use std::collections::hash_map::Entry;
@@ -65,9 +68,22 @@ pub(crate) async fn init_local_search_source<R: Runtime>(
         );
         enabled_status_store.set(calculator::DATA_SOURCE_ID, Json::Bool(true));
     }
+    // Query sources added after the store was created are enabled by default.
+    if !enabled_status_store.has(file_system::DATA_SOURCE_ID) {
+        enabled_status_store.set(file_system::DATA_SOURCE_ID, Json::Bool(true));
+    }
+    if !enabled_status_store.has(search_history::DATA_SOURCE_ID) {
+        enabled_status_store.set(search_history::DATA_SOURCE_ID, Json::Bool(true));
+    }
+    if !enabled_status_store.has(pinned::DATA_SOURCE_ID) {
+        enabled_status_store.set(pinned::DATA_SOURCE_ID, Json::Bool(true));
+    }
     let registry = app_handle.state::<SearchSourceRegistry>();
 
     application::ApplicationSearchSource::init(app_handle.clone()).await?;
+    file_system::FileSystemSearchSource::init(app_handle.clone()).await?;
+    search_history::SearchHistorySource::init(app_handle.clone()).await?;
+    pinned::PinnedSearchSource::init(app_handle.clone()).await?;
 
     for (id, enabled) in enabled_status_store.entries() {
         let enabled = match enabled {
@@ -86,6 +102,22 @@ pub(crate) async fn init_local_search_source<R: Runtime>(
                 let calculator_search = calculator::CalculatorSource::new(2000f64);
                 registry.register_source(calculator_search).await;
             }
+
+            if id == file_system::DATA_SOURCE_ID {
+                registry
+                    .register_source(file_system::FileSystemSearchSource)
+                    .await;
+            }
+
+            if id == search_history::DATA_SOURCE_ID {
+                registry
+                    .register_source(search_history::SearchHistorySource)
+                    .await;
+            }
+
+            if id == pinned::DATA_SOURCE_ID {
+                registry.register_source(pinned::PinnedSearchSource).await;
+            }
         }
     }
 
@@ -132,6 +164,19 @@ pub async fn enable_local_query_source<R: Runtime>(
         let calculator_search = calculator::CalculatorSource::new(2000f64);
         registry.register_source(calculator_search).await;
     }
+    if query_source_id == file_system::DATA_SOURCE_ID {
+        registry
+            .register_source(file_system::FileSystemSearchSource)
+            .await;
+    }
+    if query_source_id == search_history::DATA_SOURCE_ID {
+        registry
+            .register_source(search_history::SearchHistorySource)
+            .await;
+    }
+    if query_source_id == pinned::DATA_SOURCE_ID {
+        registry.register_source(pinned::PinnedSearchSource).await;
+    }
 
     let enabled_status_store = app_handle
         .store(TAURI_STORE_LOCAL_QUERY_SOURCE_ENABLED_STATE)
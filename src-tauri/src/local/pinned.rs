@@ -0,0 +1,244 @@
+//! Pinned documents.
+//!
+//! A snapshot of every pinned document is kept, along with the query source
+//! it was found in, so that it can be found even when that source cannot be
+//! reached. Pinned documents matching a query are ranked above all the other
+//! hits, see [`rank_pinned_first`](crate::search::fusion::rank_pinned_first).
+
+use super::LOCAL_QUERY_SOURCE_TYPE;
+use crate::common::document::Document;
+use crate::common::error::SearchError;
+use crate::common::search::{QueryResponse, QuerySource, SearchQuery};
+use crate::common::traits::SearchSource;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_store::StoreExt;
+
+/// We use this as:
+///
+/// 1. querysource ID
+/// 2. datasource ID
+/// 3. datasource name
+pub(crate) const DATA_SOURCE_ID: &str = "Pinned";
+
+const TAURI_STORE_PINNED_DOCUMENTS: &str = "pinned_documents";
+const TAURI_STORE_KEY_DOCUMENTS: &str = "documents";
+
+/// `true` for the pinned hits.
+pub(crate) const METADATA_PINNED: &str = "pinned";
+/// The [`QuerySource`] a pinned hit was found in, to open it there.
+pub(crate) const METADATA_PINNED_SOURCE: &str = "pinned_source";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinnedDocument {
+    pub document: Document,
+    /// The query source the document was found in.
+    pub source: QuerySource,
+    /// When it was pinned, in milliseconds since the Unix epoch.
+    pub pinned_at: i64,
+}
+
+impl PinnedDocument {
+    fn is(&self, source_id: &str, document_id: &str) -> bool {
+        self.source.id == source_id && self.document.id == document_id
+    }
+
+    /// Whether the document matches `query`, which should be lowercased.
+    fn matches(&self, query: &str) -> bool {
+        if query.is_empty() {
+            return true;
+        }
+
+        let document = &self.document;
+        [
+            &document.title,
+            &document.summary,
+            &document.category,
+            &document.url,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(document.tags.iter().flatten())
+        .any(|text| text.to_lowercase().contains(query))
+    }
+
+    fn to_document(&self) -> Document {
+        let mut document = self.document.clone();
+        let metadata = document.metadata.get_or_insert_with(HashMap::new);
+        metadata.insert(METADATA_PINNED.to_string(), Json::Bool(true));
+        metadata.insert(
+            METADATA_PINNED_SOURCE.to_string(),
+            serde_json::to_value(&self.source).expect("query source should be serializable"),
+        );
+        document
+    }
+}
+
+/// The ID of the query source pinned hit `document` was found in.
+pub(crate) fn pinned_source_id(document: &Document) -> Option<&str> {
+    document
+        .metadata
+        .as_ref()?
+        .get(METADATA_PINNED_SOURCE)?
+        .get("id")?
+        .as_str()
+}
+
+lazy_static! {
+    /// Most recently pinned first
+    static ref PINNED_DOCUMENTS: RwLock<Vec<PinnedDocument>> = RwLock::new(Vec::new());
+}
+
+fn persist_pinned_documents<R: Runtime>(
+    tauri_app_handle: &AppHandle<R>,
+    pinned_documents: &[PinnedDocument],
+) {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_PINNED_DOCUMENTS)
+        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_PINNED_DOCUMENTS));
+
+    store.set(
+        TAURI_STORE_KEY_DOCUMENTS,
+        serde_json::to_value(pinned_documents).expect("pinned documents should be serializable"),
+    );
+}
+
+pub struct PinnedSearchSource;
+
+impl PinnedSearchSource {
+    pub async fn init<R: Runtime>(app_handle: AppHandle<R>) -> Result<(), String> {
+        let store = app_handle
+            .store(TAURI_STORE_PINNED_DOCUMENTS)
+            .map_err(|e| e.to_string())?;
+
+        let pinned_documents = match store.get(TAURI_STORE_KEY_DOCUMENTS) {
+            Some(pinned_documents) => {
+                serde_json::from_value(pinned_documents).unwrap_or_else(|e| {
+                    warn!("failed to load the pinned documents, error [{}]", e);
+                    Vec::new()
+                })
+            }
+            None => Vec::new(),
+        };
+        *PINNED_DOCUMENTS.write().unwrap() = pinned_documents;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SearchSource for PinnedSearchSource {
+    fn get_type(&self) -> QuerySource {
+        QuerySource {
+            r#type: LOCAL_QUERY_SOURCE_TYPE.into(),
+            name: DATA_SOURCE_ID.into(),
+            id: DATA_SOURCE_ID.into(),
+        }
+    }
+
+    async fn search(&self, query: SearchQuery) -> Result<QueryResponse, SearchError> {
+        let query_string = query
+            .query_strings
+            .get("query")
+            .map(|query_string| query_string.trim().to_lowercase())
+            .unwrap_or_default();
+
+        let pinned_documents = PINNED_DOCUMENTS.read().unwrap();
+        let matches: Vec<&PinnedDocument> = pinned_documents
+            .iter()
+            .filter(|pinned_document| pinned_document.matches(&query_string))
+            .collect();
+
+        let total_hits = matches.len();
+        let hits = matches
+            .into_iter()
+            .enumerate()
+            .skip(query.from as usize)
+            .take(query.size as usize)
+            // The most recently pinned documents first
+            .map(|(rank, pinned_document)| {
+                let score = (total_hits - rank) as f64 / total_hits as f64;
+                (pinned_document.to_document(), score)
+            })
+            .collect();
+
+        Ok(QueryResponse {
+            source: self.get_type(),
+            hits,
+            total_hits,
+        })
+    }
+}
+
+/// Pin `document`, found in query source `source`. Pinning it again refreshes
+/// its snapshot.
+#[tauri::command]
+pub async fn pin_document<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    document: Document,
+    source: QuerySource,
+) {
+    let pinned_document = PinnedDocument {
+        document,
+        source,
+        pinned_at: chrono::Utc::now().timestamp_millis(),
+    };
+
+    let mut pinned_documents = PINNED_DOCUMENTS.write().unwrap();
+    pinned_documents
+        .retain(|existing| !existing.is(&pinned_document.source.id, &pinned_document.document.id));
+    pinned_documents.insert(0, pinned_document);
+    persist_pinned_documents(&tauri_app_handle, &pinned_documents);
+}
+
+#[tauri::command]
+pub async fn unpin_document<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    source_id: String,
+    document_id: String,
+) {
+    let mut pinned_documents = PINNED_DOCUMENTS.write().unwrap();
+    pinned_documents.retain(|pinned_document| !pinned_document.is(&source_id, &document_id));
+    persist_pinned_documents(&tauri_app_handle, &pinned_documents);
+}
+
+/// The pinned documents, most recently pinned first.
+#[tauri::command]
+pub async fn list_pinned() -> Vec<PinnedDocument> {
+    PINNED_DOCUMENTS.read().unwrap().clone()
+}
+
+#[test]
+fn test_pinned_document_matches() {
+    let pinned_document = PinnedDocument {
+        document: Document {
+            id: "doc".to_string(),
+            title: Some("Release Checklist".to_string()),
+            tags: Some(vec!["ops".to_string()]),
+            ..Default::default()
+        },
+        source: QuerySource {
+            r#type: "coco-server".into(),
+            id: "server".into(),
+            name: "Server".into(),
+        },
+        pinned_at: 0,
+    };
+
+    assert!(pinned_document.matches(""));
+    assert!(pinned_document.matches("checklist"));
+    assert!(pinned_document.matches("ops"));
+    assert!(!pinned_document.matches("roadmap"));
+    assert!(pinned_document.is("server", "doc"));
+    assert!(!pinned_document.is("another server", "doc"));
+    assert_eq!(
+        pinned_source_id(&pinned_document.to_document()),
+        Some("server")
+    );
+}
@@ -0,0 +1,375 @@
+//! Local search history.
+//!
+//! The queries executed and the documents opened from their results are
+//! recorded, unless incognito mode is on. They are surfaced as "recent" hits
+//! when the query is empty or is the prefix of a recorded query.
+
+use super::LOCAL_QUERY_SOURCE_TYPE;
+use crate::common::document::{DataSourceReference, Document};
+use crate::common::error::SearchError;
+use crate::common::search::{QueryResponse, QuerySource, SearchQuery};
+use crate::common::traits::SearchSource;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_store::StoreExt;
+
+/// We use this as:
+///
+/// 1. querysource ID
+/// 2. datasource ID
+/// 3. datasource name
+pub(crate) const DATA_SOURCE_ID: &str = "SearchHistory";
+
+const TAURI_STORE_SEARCH_HISTORY: &str = "search_history";
+const TAURI_STORE_KEY_ENTRIES: &str = "entries";
+const TAURI_STORE_KEY_INCOGNITO: &str = "incognito";
+
+/// Upper bound of the number of entries kept, the oldest ones are dropped.
+const MAX_HISTORY_ENTRIES: usize = 500;
+
+/// A query recorded less than this many milliseconds after one it extends,
+/// e.g., "rus" then "rust" as the user types, replaces it.
+const TYPING_INTERVAL_MS: i64 = 10_000;
+
+/// The document opened from the results of a query.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenedDocument {
+    pub id: String,
+    pub source: Option<DataSourceReference>,
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub icon: Option<String>,
+}
+
+impl From<Document> for OpenedDocument {
+    fn from(document: Document) -> Self {
+        Self {
+            id: document.id,
+            source: document.source,
+            url: document.url,
+            title: document.title,
+            icon: document.icon,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHistoryEntry {
+    pub id: String,
+    pub query: String,
+    pub document: Option<OpenedDocument>,
+    /// When it was last recorded, in milliseconds since the Unix epoch.
+    pub timestamp: i64,
+}
+
+impl SearchHistoryEntry {
+    /// Whether `self` and `other` record the same query and document.
+    fn is_same_as(&self, other: &SearchHistoryEntry) -> bool {
+        self.query.to_lowercase() == other.query.to_lowercase()
+            && self.document.as_ref().map(|document| &document.id)
+                == other.document.as_ref().map(|document| &document.id)
+    }
+
+    /// Whether `self` is a query that `other` extends as the user types.
+    fn is_typed_into(&self, other: &SearchHistoryEntry) -> bool {
+        self.document.is_none()
+            && other.document.is_none()
+            && other.timestamp - self.timestamp < TYPING_INTERVAL_MS
+            && other
+                .query
+                .to_lowercase()
+                .starts_with(&self.query.to_lowercase())
+    }
+
+    fn to_document(&self) -> Document {
+        let mut metadata = HashMap::new();
+        metadata.insert("history_id".to_string(), Json::String(self.id.clone()));
+        metadata.insert("query".to_string(), Json::String(self.query.clone()));
+        metadata.insert("timestamp".to_string(), Json::from(self.timestamp));
+
+        let updated = chrono::DateTime::<chrono::Utc>::from_timestamp_millis(self.timestamp)
+            .map(|datetime| datetime.to_rfc3339());
+
+        match &self.document {
+            Some(document) => Document {
+                id: document.id.clone(),
+                source: document.source.clone(),
+                category: Some("History".to_string()),
+                title: document.title.clone(),
+                icon: document.icon.clone(),
+                url: document.url.clone(),
+                updated,
+                metadata: Some(metadata),
+
+                ..Default::default()
+            },
+            None => Document {
+                id: self.id.clone(),
+                source: Some(DataSourceReference {
+                    r#type: Some(LOCAL_QUERY_SOURCE_TYPE.into()),
+                    name: Some(DATA_SOURCE_ID.into()),
+                    id: Some(DATA_SOURCE_ID.into()),
+                    icon: None,
+                }),
+                category: Some("History".to_string()),
+                title: Some(self.query.clone()),
+                updated,
+                metadata: Some(metadata),
+
+                ..Default::default()
+            },
+        }
+    }
+}
+
+lazy_static! {
+    /// Most recent first
+    static ref SEARCH_HISTORY: RwLock<Vec<SearchHistoryEntry>> = RwLock::new(Vec::new());
+}
+
+static INCOGNITO: AtomicBool = AtomicBool::new(false);
+
+/// Record `entry` as the most recent one, replacing the same previous one and
+/// the queries it has been typed from.
+fn record(entries: &mut Vec<SearchHistoryEntry>, entry: SearchHistoryEntry) {
+    entries.retain(|existing_entry| {
+        !existing_entry.is_same_as(&entry) && !existing_entry.is_typed_into(&entry)
+    });
+    entries.insert(0, entry);
+    entries.truncate(MAX_HISTORY_ENTRIES);
+}
+
+/// The entries matching `query`, which should be lowercased, most recent
+/// first.
+fn matching_entries<'a>(
+    entries: &'a [SearchHistoryEntry],
+    query: &'a str,
+) -> impl Iterator<Item = &'a SearchHistoryEntry> {
+    entries
+        .iter()
+        .filter(move |entry| query.is_empty() || entry.query.to_lowercase().starts_with(query))
+}
+
+fn persist_history<R: Runtime>(tauri_app_handle: &AppHandle<R>, entries: &[SearchHistoryEntry]) {
+    let store = tauri_app_handle
+        .store(TAURI_STORE_SEARCH_HISTORY)
+        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_SEARCH_HISTORY));
+
+    store.set(
+        TAURI_STORE_KEY_ENTRIES,
+        serde_json::to_value(entries).expect("search history should be serializable"),
+    );
+}
+
+pub struct SearchHistorySource;
+
+impl SearchHistorySource {
+    pub async fn init<R: Runtime>(app_handle: AppHandle<R>) -> Result<(), String> {
+        let store = app_handle
+            .store(TAURI_STORE_SEARCH_HISTORY)
+            .map_err(|e| e.to_string())?;
+
+        if let Some(Json::Bool(incognito)) = store.get(TAURI_STORE_KEY_INCOGNITO) {
+            INCOGNITO.store(incognito, Ordering::Relaxed);
+        }
+
+        let entries = match store.get(TAURI_STORE_KEY_ENTRIES) {
+            Some(entries) => serde_json::from_value(entries).unwrap_or_else(|e| {
+                warn!("failed to load the search history, error [{}]", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        *SEARCH_HISTORY.write().unwrap() = entries;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SearchSource for SearchHistorySource {
+    fn get_type(&self) -> QuerySource {
+        QuerySource {
+            r#type: LOCAL_QUERY_SOURCE_TYPE.into(),
+            name: DATA_SOURCE_ID.into(),
+            id: DATA_SOURCE_ID.into(),
+        }
+    }
+
+    async fn search(&self, query: SearchQuery) -> Result<QueryResponse, SearchError> {
+        let query_string = query
+            .query_strings
+            .get("query")
+            .map(|query_string| query_string.trim().to_lowercase())
+            .unwrap_or_default();
+
+        let entries = SEARCH_HISTORY.read().unwrap();
+        let matches: Vec<&SearchHistoryEntry> = matching_entries(&entries, &query_string).collect();
+
+        let total_hits = matches.len();
+        let hits = matches
+            .into_iter()
+            .enumerate()
+            .skip(query.from as usize)
+            .take(query.size as usize)
+            // The most recent entries first
+            .map(|(rank, entry)| {
+                let score = (MAX_HISTORY_ENTRIES - rank) as f64 / MAX_HISTORY_ENTRIES as f64;
+                (entry.to_document(), score)
+            })
+            .collect();
+
+        Ok(QueryResponse {
+            source: self.get_type(),
+            hits,
+            total_hits,
+        })
+    }
+}
+
+/// Record that `query` has been executed, and that `document` has been opened
+/// from its results, if specified. Nothing is recorded in incognito mode.
+pub(crate) fn record_search<R: Runtime>(
+    tauri_app_handle: &AppHandle<R>,
+    query: &str,
+    document: Option<Document>,
+) {
+    let query = query.trim().to_string();
+    if INCOGNITO.load(Ordering::Relaxed) || (query.is_empty() && document.is_none()) {
+        return;
+    }
+
+    let entry = SearchHistoryEntry {
+        id: pizza_common::utils::uuid::Uuid::new().to_string(),
+        query,
+        document: document.map(OpenedDocument::from),
+        timestamp: chrono::Utc::now().timestamp_millis(),
+    };
+
+    let mut entries = SEARCH_HISTORY.write().unwrap();
+    record(&mut entries, entry);
+    persist_history(tauri_app_handle, &entries);
+}
+
+/// Queries are recorded by [`query_coco_fusion`](crate::search::query_coco_fusion),
+/// this records the documents opened from their results.
+#[tauri::command]
+pub async fn add_search_history<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    query: String,
+    document: Option<Document>,
+) {
+    record_search(&tauri_app_handle, &query, document);
+}
+
+/// The search history, most recent first.
+#[tauri::command]
+pub async fn get_search_history(from: u64, size: u64) -> Vec<SearchHistoryEntry> {
+    SEARCH_HISTORY
+        .read()
+        .unwrap()
+        .iter()
+        .skip(from as usize)
+        .take(size as usize)
+        .cloned()
+        .collect()
+}
+
+#[tauri::command]
+pub async fn delete_search_history<R: Runtime>(tauri_app_handle: AppHandle<R>, id: String) {
+    let mut entries = SEARCH_HISTORY.write().unwrap();
+    entries.retain(|entry| entry.id != id);
+    persist_history(&tauri_app_handle, &entries);
+}
+
+#[tauri::command]
+pub async fn clear_search_history<R: Runtime>(tauri_app_handle: AppHandle<R>) {
+    let mut entries = SEARCH_HISTORY.write().unwrap();
+    entries.clear();
+    persist_history(&tauri_app_handle, &entries);
+}
+
+#[tauri::command]
+pub async fn get_search_history_incognito() -> bool {
+    INCOGNITO.load(Ordering::Relaxed)
+}
+
+/// In incognito mode, queries and opened documents are not recorded, the
+/// existing history is kept.
+#[tauri::command]
+pub async fn set_search_history_incognito<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    incognito: bool,
+) {
+    INCOGNITO.store(incognito, Ordering::Relaxed);
+
+    let store = tauri_app_handle
+        .store(TAURI_STORE_SEARCH_HISTORY)
+        .unwrap_or_else(|_| panic!("store [{}] not found/loaded", TAURI_STORE_SEARCH_HISTORY));
+    store.set(TAURI_STORE_KEY_INCOGNITO, incognito);
+}
+
+#[test]
+fn test_record_and_match() {
+    let entry = |id: &str, query: &str, document_id: Option<&str>| SearchHistoryEntry {
+        id: id.to_string(),
+        query: query.to_string(),
+        document: document_id.map(|document_id| OpenedDocument {
+            id: document_id.to_string(),
+            source: None,
+            url: None,
+            title: None,
+            icon: None,
+        }),
+        timestamp: 0,
+    };
+
+    let mut entries = Vec::new();
+    record(&mut entries, entry("1", "rust book", None));
+    record(&mut entries, entry("2", "rust book", Some("doc")));
+    record(&mut entries, entry("3", "go", None));
+    // Recording the same query again moves it to the top
+    record(&mut entries, entry("4", "Rust Book", None));
+
+    let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+    assert_eq!(ids, ["4", "3", "2"]);
+
+    // Typing "gol" then "golang" keeps only "golang"
+    let typed = |id: &str, query: &str, timestamp: i64| SearchHistoryEntry {
+        timestamp,
+        ..entry(id, query, None)
+    };
+    record(&mut entries, typed("5", "gol", 60_000));
+    record(&mut entries, typed("6", "golang", 61_000));
+    // Unless it was long ago
+    record(
+        &mut entries,
+        typed("7", "golang tutorial", 61_000 + TYPING_INTERVAL_MS),
+    );
+    let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+    assert_eq!(ids, ["7", "6", "4", "3", "2"]);
+    entries.retain(|entry| !["6", "7"].contains(&entry.id.as_str()));
+
+    let matching_ids: Vec<&str> = matching_entries(&entries, "ru")
+        .map(|entry| entry.id.as_str())
+        .collect();
+    assert_eq!(matching_ids, ["4", "2"]);
+    assert_eq!(matching_entries(&entries, "").count(), 3);
+    assert_eq!(matching_entries(&entries, "book").count(), 0);
+
+    for i in 0..MAX_HISTORY_ENTRIES + 10 {
+        let timestamp = i as i64 * TYPING_INTERVAL_MS;
+        record(
+            &mut entries,
+            typed(&i.to_string(), &i.to_string(), timestamp),
+        );
+    }
+    assert_eq!(entries.len(), MAX_HISTORY_ENTRIES);
+}
@@ -0,0 +1,142 @@
+//! File system watchers that keep local indexes (applications, files) up to date.
+//!
+//! Raw `notify` events are pushed into a bounded queue and consumed by a worker
+//! thread, which debounces them and hands batches of changed paths to the
+//! handler. If events get lost (the queue is full, or the OS tells us so), the
+//! handler will be asked to do a full rescan instead.
+
+use log::{debug, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Events that arrive within this interval are merged into one batch.
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(500);
+/// Under a steady stream of events, still flush a batch after this long.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(5);
+/// Capacity of the queue between the watcher and the worker thread. Once it is
+/// full, further events are dropped and a full rescan will be requested.
+const EVENT_QUEUE_CAPACITY: usize = 4096;
+
+#[derive(Debug)]
+pub(crate) enum WatchEvent {
+    /// These paths have been created, modified, renamed or removed.
+    Changed(Vec<PathBuf>),
+    /// Some events were lost, the handler should rebuild its index from scratch.
+    Rescan,
+}
+
+/// A running watcher, it stops watching once dropped.
+pub(crate) struct LocalIndexWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+/// Start watching `roots`, `handler` will be invoked from a dedicated thread.
+///
+/// Roots that cannot be watched (e.g., they do not exist) are skipped.
+pub(crate) fn watch<F>(
+    name: &str,
+    roots: &[PathBuf],
+    recursive_mode: RecursiveMode,
+    handler: F,
+) -> Result<LocalIndexWatcher, String>
+where
+    F: FnMut(WatchEvent) + Send + 'static,
+{
+    let (tx, rx) = sync_channel::<notify::Result<Event>>(EVENT_QUEUE_CAPACITY);
+    let overflowed = Arc::new(AtomicBool::new(false));
+    let overflowed_clone = overflowed.clone();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        // Reading a file does not change any index
+        if let Ok(Event {
+            kind: EventKind::Access(_),
+            ..
+        }) = res
+        {
+            return;
+        }
+
+        if let Err(TrySendError::Full(_)) = tx.try_send(res) {
+            overflowed_clone.store(true, Ordering::Relaxed);
+        }
+    })
+    .map_err(|e| e.to_string())?;
+
+    for root in roots {
+        if let Err(e) = watcher.watch(root, recursive_mode) {
+            warn!(
+                "watcher [{}] cannot watch [{}] due to error [{}]",
+                name,
+                root.display(),
+                e
+            );
+        }
+    }
+
+    let thread_name = format!("local index watcher - {}", name);
+    std::thread::Builder::new()
+        .name(thread_name.clone())
+        .spawn(move || {
+            debug!("thread [{}] started", thread_name);
+            run_worker(rx, overflowed, handler);
+            debug!("thread [{}] exited", thread_name);
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(LocalIndexWatcher { _watcher: watcher })
+}
+
+/// Consume events until the sending end (owned by the `notify` watcher) is dropped.
+fn run_worker<F>(rx: Receiver<notify::Result<Event>>, overflowed: Arc<AtomicBool>, mut handler: F)
+where
+    F: FnMut(WatchEvent),
+{
+    while let Ok(first) = rx.recv() {
+        let batch_started = Instant::now();
+        let mut need_rescan = false;
+        let mut changed_paths = HashSet::new();
+
+        collect_event(first, &mut need_rescan, &mut changed_paths);
+        while batch_started.elapsed() < MAX_BATCH_DELAY {
+            match rx.recv_timeout(DEBOUNCE_INTERVAL) {
+                Ok(res) => collect_event(res, &mut need_rescan, &mut changed_paths),
+                // `Disconnected` will be noticed by the next `recv()`
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        if overflowed.swap(false, Ordering::Relaxed) {
+            need_rescan = true;
+        }
+
+        if need_rescan {
+            handler(WatchEvent::Rescan);
+        } else if !changed_paths.is_empty() {
+            handler(WatchEvent::Changed(changed_paths.into_iter().collect()));
+        }
+    }
+}
+
+fn collect_event(
+    res: notify::Result<Event>,
+    need_rescan: &mut bool,
+    changed_paths: &mut HashSet<PathBuf>,
+) {
+    match res {
+        Ok(event) => {
+            if event.need_rescan() {
+                *need_rescan = true;
+            }
+            changed_paths.extend(event.paths);
+        }
+        Err(e) => {
+            warn!("watcher reported an error [{}], a rescan is needed", e);
+            *need_rescan = true;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Running queries, tracked so that they can be cancelled, either explicitly
+//! via [`cancel_query`], or because a newer query from the same window has
+//! superseded them (as-you-type search issues a query per keystroke).
+
+use log::debug;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+struct RunningQuery {
+    query_id: String,
+    cancellation_token: CancellationToken,
+}
+
+lazy_static::lazy_static! {
+    /// The latest query of every window, keyed by window label.
+    static ref RUNNING_QUERIES: Mutex<HashMap<String, RunningQuery>> = Mutex::new(HashMap::new());
+}
+
+/// A registered query, it gets deregistered once dropped.
+pub(super) struct QueryGuard {
+    window_label: String,
+    query_id: String,
+    cancellation_token: CancellationToken,
+}
+
+impl QueryGuard {
+    pub(super) fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation_token
+    }
+}
+
+impl Drop for QueryGuard {
+    fn drop(&mut self) {
+        let mut running_queries = RUNNING_QUERIES.lock().unwrap();
+        let is_latest = running_queries
+            .get(&self.window_label)
+            .is_some_and(|running_query| running_query.query_id == self.query_id);
+        if is_latest {
+            running_queries.remove(&self.window_label);
+        }
+    }
+}
+
+/// Register query `query_id` issued by window `window_label`, the previous
+/// query of this window, if still running, is cancelled.
+pub(super) fn register_query(window_label: &str, query_id: &str) -> QueryGuard {
+    let cancellation_token = CancellationToken::new();
+
+    let previous_query = RUNNING_QUERIES.lock().unwrap().insert(
+        window_label.to_string(),
+        RunningQuery {
+            query_id: query_id.to_string(),
+            cancellation_token: cancellation_token.clone(),
+        },
+    );
+    if let Some(previous_query) = previous_query {
+        if previous_query.query_id != query_id {
+            debug!(
+                "query [{}] of window [{}] is superseded by query [{}], cancelling it",
+                previous_query.query_id, window_label, query_id
+            );
+            previous_query.cancellation_token.cancel();
+        }
+    }
+
+    QueryGuard {
+        window_label: window_label.to_string(),
+        query_id: query_id.to_string(),
+        cancellation_token,
+    }
+}
+
+/// Cancel query `query_id`, no-op if it has finished.
+#[tauri::command]
+pub async fn cancel_query(query_id: String) {
+    let mut running_queries = RUNNING_QUERIES.lock().unwrap();
+    running_queries.retain(|_window_label, running_query| {
+        if running_query.query_id == query_id {
+            running_query.cancellation_token.cancel();
+            false
+        } else {
+            true
+        }
+    });
+}
@@ -0,0 +1,379 @@
+//! Strategies to merge the hits returned by multiple search sources into one list.
+//!
+//! Sources score their hits differently (BM25 from Coco servers, a fixed score
+//! from the calculator, pizza engine scores, ...), so raw scores are not
+//! comparable across sources. Every strategy but [`FairShare`] rewrites the
+//! score of the fused hits so that they are.
+
+use crate::common::search::QueryHits;
+use crate::local::pinned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// The `k` constant of Reciprocal Rank Fusion, 60 is the value used in the paper
+/// and by most implementations.
+const RECIPROCAL_RANK_FUSION_K: f64 = 60.0;
+
+pub(crate) trait FusionStrategy: Send + Sync {
+    /// Merge `hits_per_source` into at most `size` hits, best first.
+    ///
+    /// Hits of every source should be sorted by score, descending. A document
+    /// returned by multiple sources appears only once in the result.
+    fn fuse(&self, hits_per_source: Vec<Vec<QueryHits>>, size: usize) -> Vec<QueryHits>;
+}
+
+/// The available fusion strategies, this is how they are specified in settings
+/// and in the `fusion` query string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FusionStrategyKind {
+    #[default]
+    FairShare,
+    ReciprocalRank,
+    MinMax,
+    ZScore,
+}
+
+impl FusionStrategyKind {
+    pub(crate) fn strategy(self) -> Box<dyn FusionStrategy> {
+        match self {
+            Self::FairShare => Box::new(FairShare),
+            Self::ReciprocalRank => Box::new(ReciprocalRankFusion {
+                k: RECIPROCAL_RANK_FUSION_K,
+            }),
+            Self::MinMax => Box::new(NormalizedScoreFusion {
+                normalization: ScoreNormalization::MinMax,
+            }),
+            Self::ZScore => Box::new(NormalizedScoreFusion {
+                normalization: ScoreNormalization::ZScore,
+            }),
+        }
+    }
+}
+
+impl FromStr for FusionStrategyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fair_share" => Ok(Self::FairShare),
+            "reciprocal_rank" | "rrf" => Ok(Self::ReciprocalRank),
+            "min_max" => Ok(Self::MinMax),
+            "z_score" => Ok(Self::ZScore),
+            _ => Err(format!("unknown fusion strategy [{}]", s)),
+        }
+    }
+}
+
+/// Give every source a fair share of `size`, then fill the remaining slots with
+/// the highest-scoring hits left. Raw scores are kept as-is.
+pub(crate) struct FairShare;
+
+impl FusionStrategy for FairShare {
+    fn fuse(&self, mut hits_per_source: Vec<Vec<QueryHits>>, size: usize) -> Vec<QueryHits> {
+        let max_hits_per_source = size.checked_div(hits_per_source.len()).unwrap_or(size);
+
+        let mut final_hits = Vec::new();
+        let mut seen_docs = std::collections::HashSet::new(); // To track documents we've already added
+
+        // Distribute hits fairly across sources
+        for hits in &mut hits_per_source {
+            let take_count = hits.len().min(max_hits_per_source);
+            for hit in hits.drain(0..take_count) {
+                if seen_docs.insert(hit.document.id.clone()) {
+                    final_hits.push(hit);
+                }
+            }
+        }
+
+        // If we still need more hits, take the highest-scoring remaining ones
+        if final_hits.len() < size {
+            let remaining_needed = size - final_hits.len();
+
+            let mut remaining_hits: Vec<QueryHits> =
+                hits_per_source.into_iter().flatten().collect();
+            sort_by_score(&mut remaining_hits);
+
+            let extra_hits = remaining_hits
+                .into_iter()
+                .filter(|hit| seen_docs.insert(hit.document.id.clone()))
+                .take(remaining_needed);
+
+            final_hits.extend(extra_hits);
+        }
+
+        sort_by_score(&mut final_hits);
+        final_hits
+    }
+}
+
+/// Reciprocal Rank Fusion, scores are ignored, a hit ranked `r` (starting from 1)
+/// by a source scores `1 / (k + r)`, and the scores of a document returned by
+/// multiple sources are summed.
+pub(crate) struct ReciprocalRankFusion {
+    k: f64,
+}
+
+impl FusionStrategy for ReciprocalRankFusion {
+    fn fuse(&self, hits_per_source: Vec<Vec<QueryHits>>, size: usize) -> Vec<QueryHits> {
+        let mut fused: HashMap<String, QueryHits> = HashMap::new();
+
+        for hits in hits_per_source {
+            for (rank, mut hit) in hits.into_iter().enumerate() {
+                let score = 1.0 / (self.k + (rank + 1) as f64);
+                match fused.get_mut(&hit.document.id) {
+                    Some(fused_hit) => fused_hit.score += score,
+                    None => {
+                        hit.score = score;
+                        fused.insert(hit.document.id.clone(), hit);
+                    }
+                }
+            }
+        }
+
+        let mut final_hits: Vec<QueryHits> = fused.into_values().collect();
+        sort_by_score(&mut final_hits);
+        final_hits.truncate(size);
+        final_hits
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ScoreNormalization {
+    /// Map scores to `[0, 1]`.
+    MinMax,
+    /// Map scores to their number of standard deviations from the mean.
+    ZScore,
+}
+
+impl ScoreNormalization {
+    fn normalize(self, scores: &mut [f64]) {
+        if scores.is_empty() {
+            return;
+        }
+
+        match self {
+            Self::MinMax => {
+                let min = scores.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                let range = max - min;
+                for score in scores.iter_mut() {
+                    // All the hits are equally good
+                    *score = if range > 0.0 {
+                        (*score - min) / range
+                    } else {
+                        1.0
+                    };
+                }
+            }
+            Self::ZScore => {
+                let len = scores.len() as f64;
+                let mean = scores.iter().sum::<f64>() / len;
+                let variance = scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / len;
+                let std_dev = variance.sqrt();
+                for score in scores.iter_mut() {
+                    *score = if std_dev > 0.0 {
+                        (*score - mean) / std_dev
+                    } else {
+                        0.0
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Normalize the scores of every source, then merge all the hits by their
+/// normalized scores. A document returned by multiple sources keeps its best score.
+pub(crate) struct NormalizedScoreFusion {
+    normalization: ScoreNormalization,
+}
+
+impl FusionStrategy for NormalizedScoreFusion {
+    fn fuse(&self, hits_per_source: Vec<Vec<QueryHits>>, size: usize) -> Vec<QueryHits> {
+        let mut fused: HashMap<String, QueryHits> = HashMap::new();
+
+        for mut hits in hits_per_source {
+            let mut scores: Vec<f64> = hits.iter().map(|hit| hit.score).collect();
+            self.normalization.normalize(&mut scores);
+
+            for (mut hit, score) in hits.drain(..).zip(scores) {
+                hit.score = score;
+                match fused.get_mut(&hit.document.id) {
+                    Some(fused_hit) if fused_hit.score >= score => {}
+                    _ => {
+                        fused.insert(hit.document.id.clone(), hit);
+                    }
+                }
+            }
+        }
+
+        let mut final_hits: Vec<QueryHits> = fused.into_values().collect();
+        sort_by_score(&mut final_hits);
+        final_hits.truncate(size);
+        final_hits
+    }
+}
+
+/// Put `pinned_hits` above the fused `hits`, whatever their scores, keeping at
+/// most `size` hits. A pinned document is not repeated if it is also a hit of
+/// the source it was pinned from.
+pub(crate) fn rank_pinned_first(
+    mut pinned_hits: Vec<QueryHits>,
+    hits: Vec<QueryHits>,
+    size: usize,
+) -> Vec<QueryHits> {
+    sort_by_score(&mut pinned_hits);
+
+    // (source ID, document ID)
+    let pinned_docs: std::collections::HashSet<(&str, &str)> = pinned_hits
+        .iter()
+        .filter_map(|hit| {
+            pinned::pinned_source_id(&hit.document)
+                .map(|source_id| (source_id, hit.document.id.as_str()))
+        })
+        .collect();
+    let is_pinned = |hit: &QueryHits| {
+        let source_id = hit.source.as_ref().map(|source| source.id.as_str());
+        source_id
+            .is_some_and(|source_id| pinned_docs.contains(&(source_id, hit.document.id.as_str())))
+    };
+
+    let hits: Vec<QueryHits> = hits.into_iter().filter(|hit| !is_pinned(hit)).collect();
+    let mut final_hits = pinned_hits;
+    final_hits.extend(hits);
+    final_hits.truncate(size);
+    final_hits
+}
+
+fn sort_by_score(hits: &mut [QueryHits]) {
+    hits.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
+
+#[cfg(test)]
+fn test_hits(source: &str, scored_ids: &[(&str, f64)]) -> Vec<QueryHits> {
+    use crate::common::document::Document;
+    use crate::common::search::QuerySource;
+
+    scored_ids
+        .iter()
+        .map(|(id, score)| QueryHits {
+            source: Some(QuerySource {
+                r#type: "test".into(),
+                id: source.into(),
+                name: source.into(),
+            }),
+            score: *score,
+            document: Document {
+                id: id.to_string(),
+                ..Default::default()
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn ids(hits: &[QueryHits]) -> Vec<&str> {
+    hits.iter().map(|hit| hit.document.id.as_str()).collect()
+}
+
+#[test]
+fn test_fair_share_gives_every_source_a_share() {
+    let hits_per_source = vec![
+        test_hits("server", &[("a", 30.0), ("b", 20.0), ("c", 10.0)]),
+        test_hits("calculator", &[("d", 2000.0)]),
+        test_hits("apps", &[("e", 1.0), ("f", 0.5)]),
+    ];
+
+    let fused = FairShare.fuse(hits_per_source, 4);
+
+    // 1 hit per source, then the best remaining one
+    assert_eq!(ids(&fused), vec!["d", "a", "b", "e"]);
+}
+
+#[test]
+fn test_reciprocal_rank_fusion_ignores_raw_scores() {
+    let hits_per_source = vec![
+        test_hits("server", &[("a", 30.0), ("b", 20.0)]),
+        test_hits("calculator", &[("c", 2000.0)]),
+        test_hits("apps", &[("b", 1.0), ("d", 0.5)]),
+    ];
+
+    let fused = FusionStrategyKind::ReciprocalRank
+        .strategy()
+        .fuse(hits_per_source, 10);
+
+    // "b" is returned by 2 sources
+    assert_eq!(ids(&fused)[0], "b");
+    // Top-ranked hits are equally good regardless of their raw scores
+    assert_eq!(fused[1].score, fused[2].score);
+    assert_eq!(ids(&fused)[3], "d");
+    assert_eq!(fused.len(), 4);
+}
+
+#[test]
+fn test_min_max_normalization() {
+    let hits_per_source = vec![
+        test_hits("server", &[("a", 30.0), ("b", 20.0), ("c", 10.0)]),
+        test_hits("calculator", &[("d", 2000.0)]),
+    ];
+
+    let fused = FusionStrategyKind::MinMax
+        .strategy()
+        .fuse(hits_per_source, 3);
+
+    assert_eq!(fused.len(), 3);
+    assert_eq!(fused[0].score, 1.0);
+    assert_eq!(fused[1].score, 1.0);
+    assert_eq!(ids(&fused)[2], "b");
+    assert_eq!(fused[2].score, 0.5);
+}
+
+#[test]
+fn test_z_score_normalization() {
+    let mut scores = vec![1.0, 2.0, 3.0];
+    ScoreNormalization::ZScore.normalize(&mut scores);
+    assert!(scores[0] < 0.0 && scores[1] == 0.0 && scores[2] > 0.0);
+    assert!((scores[0] + scores[2]).abs() < f64::EPSILON);
+
+    let mut equal_scores = vec![5.0, 5.0];
+    ScoreNormalization::ZScore.normalize(&mut equal_scores);
+    assert_eq!(equal_scores, vec![0.0, 0.0]);
+}
+
+#[test]
+fn test_rank_pinned_first() {
+    let mut pinned_hits = test_hits("pinned", &[("c", 0.5), ("b", 1.0)]);
+    for hit in &mut pinned_hits {
+        hit.document.metadata = Some(HashMap::from([(
+            pinned::METADATA_PINNED_SOURCE.to_string(),
+            serde_json::json!({ "type": "test", "id": "server", "name": "server" }),
+        )]));
+    }
+    let mut hits = test_hits("server", &[("a", 30.0), ("b", 20.0), ("d", 10.0)]);
+    // Another document with the ID of a pinned one
+    hits.extend(test_hits("files", &[("c", 15.0)]));
+
+    let ranked = rank_pinned_first(pinned_hits, hits, 5);
+    assert_eq!(ids(&ranked), vec!["b", "c", "a", "d", "c"]);
+    assert_eq!(ranked[0].source.as_ref().unwrap().id, "pinned");
+    assert_eq!(ranked[4].source.as_ref().unwrap().id, "files");
+}
+
+#[test]
+fn test_parse_fusion_strategy_kind() {
+    assert_eq!(
+        "rrf".parse::<FusionStrategyKind>(),
+        Ok(FusionStrategyKind::ReciprocalRank)
+    );
+    assert_eq!(
+        "z_score".parse::<FusionStrategyKind>(),
+        Ok(FusionStrategyKind::ZScore)
+    );
+    assert!("best".parse::<FusionStrategyKind>().is_err());
+}
@@ -1,39 +1,74 @@
 use crate::common::error::SearchError;
 use crate::common::register::SearchSourceRegistry;
 use crate::common::search::{
-    FailedRequest, MultiSourceQueryResponse, QueryHits, QuerySource, SearchQuery,
+    FailedRequest, MultiSourceQueryResponse, PartialQueryResponse, QueryHits, QueryResponse,
+    QuerySource, SearchQuery,
 };
+use crate::local::{pinned, search_history};
+use crate::settings::_get_search_fusion_strategy;
+use fusion::{FusionStrategy, FusionStrategyKind};
+use futures::future::BoxFuture;
 use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
+use log::warn;
 use std::collections::HashMap;
-use std::collections::HashSet;
-use tauri::{AppHandle, Manager, Runtime};
+use tauri::{AppHandle, Emitter, Manager, Runtime, Window};
 use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
 
-#[tauri::command]
-pub async fn query_coco_fusion<R: Runtime>(
-    app_handle: AppHandle<R>,
+pub(crate) mod cancellation;
+pub(crate) mod fusion;
+
+/// Key of the query string specifying the fusion strategy of a query, it
+/// overrides the one specified in settings.
+const QUERY_STRING_KEY_FUSION: &str = "fusion";
+
+/// A query that has been sent to the search sources.
+struct FusionQuery {
+    fusion_strategy: Box<dyn FusionStrategy>,
+    size: u64,
+    /// Every future resolves to the source queried and its result.
+    futures:
+        FuturesUnordered<BoxFuture<'static, (QuerySource, Result<QueryResponse, SearchError>)>>,
+}
+
+/// Send the query to all the search sources (or the one specified by the
+/// `querysource` query string), every source has `query_timeout` milliseconds
+/// to respond.
+///
+/// Once `cancellation_token` is cancelled, the queries still running are
+/// aborted and fail with [`SearchError::Cancelled`].
+async fn start_query<R: Runtime>(
+    app_handle: &AppHandle<R>,
     from: u64,
     size: u64,
-    query_strings: HashMap<String, String>,
+    mut query_strings: HashMap<String, String>,
     query_timeout: u64,
-) -> Result<MultiSourceQueryResponse, SearchError> {
+    cancellation_token: &CancellationToken,
+) -> FusionQuery {
+    // This is for us, do not pass it to the search sources
+    let fusion_strategy = match query_strings.remove(QUERY_STRING_KEY_FUSION) {
+        Some(kind) => kind.parse::<FusionStrategyKind>().unwrap_or_else(|e| {
+            warn!("{}, falling back to the one in settings", e);
+            _get_search_fusion_strategy(app_handle.clone())
+        }),
+        None => _get_search_fusion_strategy(app_handle.clone()),
+    }
+    .strategy();
+
     let query_source_to_search = query_strings.get("querysource");
 
     let search_sources = app_handle.state::<SearchSourceRegistry>();
 
-    let sources_future = search_sources.get_sources();
-    let mut futures = FuturesUnordered::new();
-    let mut sources = HashMap::new();
-
-    let sources_list = sources_future.await;
+    let sources_list = search_sources.get_sources().await;
+    let futures = FuturesUnordered::new();
 
     // Time limit for each query
     let timeout_duration = Duration::from_millis(query_timeout);
 
     // Push all queries into futures
     for query_source in sources_list {
-        let query_source_type = query_source.get_type().clone();
+        let query_source_type = query_source.get_type();
 
         if let Some(query_source_to_search) = query_source_to_search {
             // We should not search this data source
@@ -42,146 +77,276 @@ pub async fn query_coco_fusion<R: Runtime>(
             }
         }
 
-        sources.insert(query_source_type.id.clone(), query_source_type);
-
-        let query = SearchQuery::new(from, size, query_strings.clone());
+        let query =
+            SearchQuery::new(from, size, query_strings.clone()).with_timeout(timeout_duration);
         let query_source_clone = query_source.clone(); // Clone Arc to avoid ownership issues
+        let cancellation_token = cancellation_token.clone();
+
+        let query_task = tokio::spawn(async move {
+            tokio::select! {
+                // Dropping the query future aborts the HTTP requests/tasks it is waiting for
+                _ = cancellation_token.cancelled() => Err(SearchError::Cancelled),
+                // Timeout each query execution
+                result = timeout(timeout_duration, query_source_clone.search(query)) => {
+                    result.unwrap_or(Err(SearchError::Timeout))
+                }
+            }
+        });
+
+        futures.push(
+            async move {
+                let result = query_task.await.unwrap_or_else(|join_error| {
+                    Err(SearchError::InternalError(format!(
+                        "search task failed: {}",
+                        join_error
+                    )))
+                });
 
-        futures.push(tokio::spawn(async move {
-            // Timeout each query execution
-            timeout(timeout_duration, async {
-                query_source_clone.search(query).await
-            })
-            .await
-        }));
+                (query_source_type, result)
+            }
+            .boxed(),
+        );
     }
 
-    let mut total_hits = 0;
-    let mut failed_requests = Vec::new();
-    let mut all_hits: Vec<(String, QueryHits, f64)> = Vec::new();
-    let mut hits_per_source: HashMap<String, Vec<(QueryHits, f64)>> = HashMap::new();
+    FusionQuery {
+        fusion_strategy,
+        size,
+        futures,
+    }
+}
+
+/// Responses of the search sources collected so far.
+#[derive(Default)]
+struct CollectedResponses {
+    total_hits: usize,
+    failed_requests: Vec<FailedRequest>,
+    hits_per_source: HashMap<String, Vec<QueryHits>>,
+}
 
-    while let Some(result) = futures.next().await {
+impl CollectedResponses {
+    /// Collect the result of a search source, return its hits if it succeeded.
+    fn collect(
+        &mut self,
+        source: QuerySource,
+        result: Result<QueryResponse, SearchError>,
+    ) -> Option<PartialQueryResponse> {
         match result {
-            Ok(Ok(Ok(response))) => {
-                total_hits += response.total_hits;
+            Ok(response) => {
+                self.total_hits += response.total_hits;
                 let source_id = response.source.id.clone();
 
-                for (doc, score) in response.hits {
-                    let query_hit = QueryHits {
+                let hits: Vec<QueryHits> = response
+                    .hits
+                    .into_iter()
+                    .map(|(doc, score)| QueryHits {
                         source: Some(response.source.clone()),
                         score,
                         document: doc,
-                    };
+                    })
+                    .collect();
 
-                    all_hits.push((source_id.clone(), query_hit.clone(), score));
+                self.hits_per_source
+                    .entry(source_id)
+                    .or_default()
+                    .extend(hits.iter().cloned());
 
-                    hits_per_source
-                        .entry(source_id.clone())
-                        .or_insert_with(Vec::new)
-                        .push((query_hit, score));
-                }
-            }
-            Ok(Ok(Err(err))) => {
-                failed_requests.push(FailedRequest {
-                    source: QuerySource {
-                        r#type: "N/A".into(),
-                        name: "N/A".into(),
-                        id: "N/A".into(),
-                    },
-                    status: 0,
-                    error: Some(err.to_string()),
-                    reason: None,
-                });
+                Some(PartialQueryResponse {
+                    source: response.source,
+                    hits,
+                    total_hits: response.total_hits,
+                })
             }
-            Ok(Err(err)) => {
-                failed_requests.push(FailedRequest {
-                    source: QuerySource {
-                        r#type: "N/A".into(),
-                        name: "N/A".into(),
-                        id: "N/A".into(),
-                    },
-                    status: 0,
+            Err(err) => {
+                let (status, reason) = match &err {
+                    SearchError::ServerError { status, reason } => (*status, Some(reason.clone())),
+                    _ => (0, None),
+                };
+                self.failed_requests.push(FailedRequest {
+                    source,
+                    status,
                     error: Some(err.to_string()),
-                    reason: None,
-                });
-            }
-            // Timeout reached, skip this request
-            _ => {
-                failed_requests.push(FailedRequest {
-                    source: QuerySource {
-                        r#type: "N/A".into(),
-                        name: "N/A".into(),
-                        id: "N/A".into(),
-                    },
-                    status: 0,
-                    error: Some(format!("{:?}", &result)),
-                    reason: None,
+                    reason,
                 });
+                None
             }
         }
     }
 
-    // Sort hits within each source by score (descending)
-    for hits in hits_per_source.values_mut() {
-        hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    }
+    /// Merge the hits of all the sources into the final response, the pinned
+    /// documents come first.
+    fn fuse(self, fusion_strategy: &dyn FusionStrategy, size: u64) -> MultiSourceQueryResponse {
+        let mut hits_per_source = self.hits_per_source;
+        let pinned_hits = hits_per_source
+            .remove(pinned::DATA_SOURCE_ID)
+            .unwrap_or_default();
 
-    let total_sources = hits_per_source.len();
-    let max_hits_per_source = if total_sources > 0 {
-        size as usize / total_sources
-    } else {
-        size as usize
-    };
-
-    let mut final_hits = Vec::new();
-    let mut seen_docs = HashSet::new(); // To track documents we've already added
-
-    // Distribute hits fairly across sources
-    for (_source_id, hits) in &mut hits_per_source {
-        let take_count = hits.len().min(max_hits_per_source);
-        for (doc, _) in hits.drain(0..take_count) {
-            if !seen_docs.contains(&doc.document.id) {
-                seen_docs.insert(doc.document.id.clone());
-                final_hits.push(doc);
-            }
+        // Sort hits within each source by score (descending)
+        for hits in hits_per_source.values_mut() {
+            hits.sort_by(|a, b| {
+                b.score
+                    .partial_cmp(&a.score)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+
+        let final_hits =
+            fusion_strategy.fuse(hits_per_source.into_values().collect(), size as usize);
+        let final_hits = fusion::rank_pinned_first(pinned_hits, final_hits, size as usize);
+
+        MultiSourceQueryResponse {
+            failed: self.failed_requests,
+            hits: final_hits,
+            total_hits: self.total_hits,
         }
     }
+}
 
-    // If we still need more hits, take the highest-scoring remaining ones
-    if final_hits.len() < size as usize {
-        let remaining_needed = size as usize - final_hits.len();
-
-        // Sort all hits by score descending, removing duplicates by document ID
-        all_hits.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
-
-        let extra_hits = all_hits
-            .into_iter()
-            .filter(|(source_id, _, _)| hits_per_source.contains_key(source_id)) // Only take from known sources
-            .filter_map(|(_, doc, _)| {
-                if !seen_docs.contains(&doc.document.id) {
-                    seen_docs.insert(doc.document.id.clone());
-                    Some(doc)
-                } else {
-                    None
-                }
-            })
-            .take(remaining_needed)
-            .collect::<Vec<_>>();
+/// Record the query string of a query that has been executed in the search
+/// history, but not again for the next pages of its results.
+fn record_query<R: Runtime>(app_handle: &AppHandle<R>, from: u64, query: Option<String>) {
+    if from > 0 {
+        return;
+    }
+    if let Some(query) = query {
+        search_history::record_search(app_handle, &query, None);
+    }
+}
 
-        final_hits.extend(extra_hits);
+/// If `query_id` is specified, the query can be cancelled via
+/// [`cancel_query`](cancellation::cancel_query), and it cancels the previous
+/// query issued by `window`.
+#[tauri::command]
+pub async fn query_coco_fusion<R: Runtime>(
+    app_handle: AppHandle<R>,
+    window: Window<R>,
+    query_id: Option<String>,
+    from: u64,
+    size: u64,
+    query_strings: HashMap<String, String>,
+    query_timeout: u64,
+) -> Result<MultiSourceQueryResponse, SearchError> {
+    let query_guard =
+        query_id.map(|query_id| cancellation::register_query(window.label(), &query_id));
+    let cancellation_token = query_guard
+        .as_ref()
+        .map(|query_guard| query_guard.cancellation_token().clone())
+        .unwrap_or_default();
+
+    let query = query_strings.get("query").cloned();
+    let FusionQuery {
+        fusion_strategy,
+        size,
+        mut futures,
+    } = start_query(
+        &app_handle,
+        from,
+        size,
+        query_strings,
+        query_timeout,
+        &cancellation_token,
+    )
+    .await;
+
+    let mut responses = CollectedResponses::default();
+    while let Some((source, result)) = futures.next().await {
+        responses.collect(source, result);
+    }
+
+    if cancellation_token.is_cancelled() {
+        return Err(SearchError::Cancelled);
     }
+    record_query(&app_handle, from, query);
+
+    Ok(responses.fuse(fusion_strategy.as_ref(), size))
+}
+
+/// Streaming version of [`query_coco_fusion`], instead of waiting for all the
+/// search sources, the hits of every source are emitted as soon as it responds:
+///
+/// * `query-hits-{query_id}`: a [`PartialQueryResponse`], emitted once per
+///   successful source
+/// * `query-done-{query_id}`: the final [`MultiSourceQueryResponse`], with the
+///   hits of all the sources merged and the failed requests, emitted last
+///
+/// The query can be cancelled via [`cancel_query`](cancellation::cancel_query),
+/// and it cancels the previous query issued by `window`. No more events are
+/// emitted once it is cancelled.
+#[tauri::command]
+pub async fn query_coco_fusion_stream<R: Runtime>(
+    app_handle: AppHandle<R>,
+    window: Window<R>,
+    query_id: String,
+    from: u64,
+    size: u64,
+    query_strings: HashMap<String, String>,
+    query_timeout: u64,
+) -> Result<(), SearchError> {
+    let query_guard = cancellation::register_query(window.label(), &query_id);
+    let cancellation_token = query_guard.cancellation_token();
+
+    let query = query_strings.get("query").cloned();
+    let FusionQuery {
+        fusion_strategy,
+        size,
+        mut futures,
+    } = start_query(
+        &app_handle,
+        from,
+        size,
+        query_strings,
+        query_timeout,
+        cancellation_token,
+    )
+    .await;
+
+    let hits_event = format!("query-hits-{}", query_id);
+    let mut responses = CollectedResponses::default();
+    while let Some((source, result)) = futures.next().await {
+        if cancellation_token.is_cancelled() {
+            return Err(SearchError::Cancelled);
+        }
+
+        if let Some(partial_response) = responses.collect(source, result) {
+            let _ = app_handle.emit(&hits_event, partial_response);
+        }
+    }
+
+    if cancellation_token.is_cancelled() {
+        return Err(SearchError::Cancelled);
+    }
+    record_query(&app_handle, from, query);
+
+    let response = responses.fuse(fusion_strategy.as_ref(), size);
+    let _ = app_handle.emit(&format!("query-done-{}", query_id), response);
+
+    Ok(())
+}
+
+#[test]
+fn test_executed_query_is_recorded_in_search_history() {
+    let app = tauri::test::mock_builder()
+        .plugin(tauri_plugin_store::Builder::default().build())
+        .manage(SearchSourceRegistry::default())
+        .build(tauri::test::mock_context(tauri::test::noop_assets()))
+        .unwrap();
+    let window = tauri::WebviewWindowBuilder::new(&app, "main", Default::default())
+        .build()
+        .unwrap();
+
+    let query_strings = HashMap::from([("query".to_string(), "quarterly report".to_string())]);
+    tauri::async_runtime::block_on(query_coco_fusion(
+        app.handle().clone(),
+        window.as_ref().window(),
+        None,
+        0,
+        10,
+        query_strings,
+        100,
+    ))
+    .unwrap();
 
-    // **Sort final hits by score descending**
-    final_hits.sort_by(|a, b| {
-        b.score
-            .partial_cmp(&a.score)
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
-
-    Ok(MultiSourceQueryResponse {
-        failed: failed_requests,
-        hits: final_hits,
-        total_hits,
-    })
+    let history = tauri::async_runtime::block_on(search_history::get_search_history(0, 10));
+    assert!(history
+        .iter()
+        .any(|entry| entry.query == "quarterly report" && entry.document.is_none()));
 }
@@ -1,6 +1,8 @@
-use super::servers::{get_server_by_id, get_server_token};
+use super::auth::{get_valid_server_token, handle_auth_expired};
+use super::servers::get_server_by_id;
+use crate::common::error::CocoError;
 use crate::common::http::get_response_body_text;
-use crate::server::http_client::HttpClient;
+use crate::server::http_client::{HttpClient, ServerHttpClient};
 use reqwest::multipart::{Form, Part};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -62,19 +64,19 @@ pub async fn upload_attachment(
     server_id: String,
     session_id: String,
     file_paths: Vec<PathBuf>,
-) -> Result<UploadAttachmentResponse, String> {
+) -> Result<UploadAttachmentResponse, CocoError> {
     let mut form = Form::new();
 
     for file_path in file_paths {
-        let file = File::open(&file_path)
-            .await
-            .map_err(|err| err.to_string())?;
+        let file = File::open(&file_path).await.map_err(|err| {
+            CocoError::Internal(format!("Failed to open {}: {}", file_path.display(), err))
+        })?;
 
         let stream = FramedRead::new(file, BytesCodec::new());
         let file_name = file_path
             .file_name()
             .and_then(|n| n.to_str())
-            .ok_or("Invalid filename")?;
+            .ok_or_else(|| CocoError::Internal("Invalid filename".to_string()))?;
 
         let part =
             Part::stream(reqwest::Body::wrap_stream(stream)).file_name(file_name.to_string());
@@ -82,62 +84,69 @@ pub async fn upload_attachment(
         form = form.part("files", part);
     }
 
-    let server = get_server_by_id(&server_id).ok_or("Server not found")?;
+    let server = get_server_by_id(&server_id)
+        .ok_or_else(|| CocoError::NotFound(format!("server [{}]", server_id)))?;
     let url = HttpClient::join_url(&server.endpoint, &format!("chat/{}/_upload", session_id));
 
-    let token = get_server_token(&server_id).await?;
+    let token = get_valid_server_token(&server_id).await?;
+    let token_sent = token.is_some();
     let mut headers = HashMap::new();
     if let Some(token) = token {
         headers.insert("X-API-TOKEN".to_string(), token.access_token);
     }
 
-    let client = reqwest::Client::new();
+    let client = ServerHttpClient::for_upload(&server)?;
     let response = client
+        .client()
         .post(url)
         .multipart(form)
-        .headers((&headers).try_into().map_err(|err| format!("{}", err))?)
+        .headers(
+            (&headers)
+                .try_into()
+                .map_err(|err| CocoError::Internal(format!("Invalid headers: {}", err)))?,
+        )
         .send()
-        .await
-        .map_err(|err| err.to_string())?;
+        .await?;
+
+    if token_sent && response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        handle_auth_expired(&server_id).await;
+    }
 
     let body = get_response_body_text(response).await?;
 
     serde_json::from_str::<UploadAttachmentResponse>(&body)
-        .map_err(|e| format!("Failed to parse upload response: {}", e))
+        .map_err(|e| CocoError::Parse(format!("Failed to parse upload response: {}", e)))
 }
 
 #[command]
 pub async fn get_attachment(
     server_id: String,
     session_id: String,
-) -> Result<GetAttachmentResponse, String> {
+) -> Result<GetAttachmentResponse, CocoError> {
     let mut query_params = HashMap::new();
     query_params.insert("session".to_string(), serde_json::Value::String(session_id));
 
-    let response = HttpClient::get(&server_id, "/attachment/_search", Some(query_params))
-        .await
-        .map_err(|e| format!("Request error: {}", e))?;
+    let response = HttpClient::get(&server_id, "/attachment/_search", Some(query_params)).await?;
 
     let body = get_response_body_text(response).await?;
 
     serde_json::from_str::<GetAttachmentResponse>(&body)
-        .map_err(|e| format!("Failed to parse attachment response: {}", e))
+        .map_err(|e| CocoError::Parse(format!("Failed to parse attachment response: {}", e)))
 }
 
 #[command]
-pub async fn delete_attachment(server_id: String, id: String) -> Result<bool, String> {
-    let response = HttpClient::delete(&server_id, &format!("/attachment/{}", id), None, None)
-        .await
-        .map_err(|e| format!("Request error: {}", e))?;
+pub async fn delete_attachment(server_id: String, id: String) -> Result<bool, CocoError> {
+    let response =
+        HttpClient::delete(&server_id, &format!("/attachment/{}", id), None, None).await?;
 
     let body = get_response_body_text(response).await?;
 
     let parsed: DeleteAttachmentResponse = serde_json::from_str(&body)
-        .map_err(|e| format!("Failed to parse delete response: {}", e))?;
+        .map_err(|e| CocoError::Parse(format!("Failed to parse delete response: {}", e)))?;
 
     parsed
         .result
         .eq("deleted")
         .then_some(true)
-        .ok_or_else(|| "Delete operation was not successful".to_string())
+        .ok_or_else(|| CocoError::Internal("Delete operation was not successful".to_string()))
 }
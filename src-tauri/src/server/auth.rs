@@ -1,56 +1,359 @@
-use crate::common::server::ServerAccessToken;
-use crate::server::profile::get_user_profiles;
+use crate::common::auth::RequestAccessTokenResponse;
+use crate::common::error::CocoError;
+use crate::common::http::get_response_body_text;
+use crate::common::register::SearchSourceRegistry;
+use crate::common::server::{Server, ServerAccessToken};
+use crate::server::http_client::{HttpClient, ServerHttpClient};
+use crate::server::profile::fetch_user_profile;
 use crate::server::servers::{
-    get_server_by_id, persist_servers, persist_servers_token, save_access_token, save_server,
-    try_register_server_to_search_source,
+    get_server_by_id, get_server_token, persist_servers, persist_servers_token,
+    remove_server_token, save_access_token, save_server, try_register_server_to_search_source,
 };
-use tauri::{AppHandle, Runtime};
+use lazy_static::lazy_static;
+use reqwest::Method;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
 
-#[allow(dead_code)]
-fn request_access_token_url(request_id: &str) -> String {
+/// How long the user has to complete an SSO login.
+const SSO_LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// An SSO login started by [`start_sso_login`], waiting for its callback.
+struct PendingLogin {
+    server_id: String,
+    code_verifier: String,
+    started_at: Instant,
+}
+
+lazy_static! {
+    /// Pending SSO logins, keyed by request ID.
+    static ref PENDING_LOGINS: Mutex<HashMap<String, PendingLogin>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SsoLogin {
+    pub request_id: String,
+    /// The URL to open in the browser.
+    pub url: String,
+}
+
+fn random_url_safe_string(len: usize) -> Result<String, CocoError> {
+    let mut bytes = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| CocoError::Internal("Failed to generate random bytes".to_string()))?;
+    Ok(base64::encode_config(bytes, base64::URL_SAFE_NO_PAD))
+}
+
+/// The PKCE code challenge of `code_verifier`, using the `S256` method.
+fn code_challenge(code_verifier: &str) -> String {
+    let hash = digest::digest(&digest::SHA256, code_verifier.as_bytes());
+    base64::encode_config(hash.as_ref(), base64::URL_SAFE_NO_PAD)
+}
+
+/// Check that the pending login `request_id` was started for `server_id` and
+/// has not timed out, then remove it, so that its callback can only be handled
+/// once. A callback for another server leaves the login pending.
+fn take_pending_login(
+    pending_logins: &mut HashMap<String, PendingLogin>,
+    request_id: &str,
+    server_id: &str,
+    now: Instant,
+) -> Result<PendingLogin, CocoError> {
+    let unknown_login = || CocoError::NotFound(format!("pending login [{}]", request_id));
+
+    let pending_login = pending_logins.get(request_id).ok_or_else(unknown_login)?;
+    if pending_login.server_id != server_id {
+        return Err(unknown_login());
+    }
+    let expired = now.duration_since(pending_login.started_at) > SSO_LOGIN_TIMEOUT;
+
+    let pending_login = pending_logins
+        .remove(request_id)
+        .expect("pending login should exist");
+    if expired {
+        return Err(CocoError::AuthExpired);
+    }
+
+    Ok(pending_login)
+}
+
+/// Start an SSO login to server `server_id`, the browser should be sent to the
+/// returned URL, and the callback handled by [`handle_sso_callback`].
+#[tauri::command]
+pub async fn start_sso_login(server_id: String) -> Result<SsoLogin, CocoError> {
+    let server = get_server_by_id(&server_id)
+        .ok_or_else(|| CocoError::NotFound(format!("server [{}]", server_id)))?;
+
+    let request_id = pizza_common::utils::uuid::Uuid::new().to_string();
+    let code_verifier = random_url_safe_string(32)?;
+
+    let mut url = url::Url::parse(&server.auth_provider.sso.url)
+        .map_err(|e| CocoError::Parse(format!("Invalid SSO URL: {}", e)))?;
+    url.query_pairs_mut()
+        .append_pair("provider", &server_id)
+        .append_pair("product", "coco")
+        .append_pair("request_id", &request_id)
+        .append_pair("code_challenge", &code_challenge(&code_verifier))
+        .append_pair("code_challenge_method", "S256");
+
+    let now = Instant::now();
+    let mut pending_logins = PENDING_LOGINS.lock().unwrap();
+    // Forget the logins that have been abandoned
+    pending_logins.retain(|_, login| now.duration_since(login.started_at) <= SSO_LOGIN_TIMEOUT);
+    pending_logins.insert(
+        request_id.clone(),
+        PendingLogin {
+            server_id,
+            code_verifier,
+            started_at: now,
+        },
+    );
+
+    Ok(SsoLogin {
+        request_id,
+        url: url.to_string(),
+    })
+}
+
+fn request_access_token_url(request_id: &str, code_verifier: &str) -> String {
     // Remove the endpoint part and keep just the path for the request
-    format!("/auth/request_access_token?request_id={}", request_id)
+    format!(
+        "/auth/request_access_token?request_id={}&code_verifier={}",
+        request_id, code_verifier
+    )
+}
+
+/// Exchange the `code` of an SSO login for an access token, the server checks
+/// `code_verifier` against the code challenge the login was started with.
+async fn request_access_token(
+    server: &Server,
+    request_id: &str,
+    code_verifier: &str,
+    code: &str,
+) -> Result<RequestAccessTokenResponse, CocoError> {
+    let url = HttpClient::join_url(
+        &server.endpoint,
+        &request_access_token_url(request_id, code_verifier),
+    );
+
+    let mut headers = HashMap::new();
+    headers.insert("X-API-TOKEN".to_string(), code.to_string());
+
+    let client = ServerHttpClient::for_server(server)?;
+    let response =
+        HttpClient::send_raw_request(&client, Method::GET, &url, None, Some(headers), None).await?;
+    let body = get_response_body_text(response).await?;
+
+    serde_json::from_str(&body)
+        .map_err(|e| CocoError::Parse(format!("Failed to parse access token: {}", e)))
+}
+
+/// Get the token of server `server_id`, it is an error if it has expired.
+pub(crate) async fn get_valid_server_token(
+    server_id: &str,
+) -> Result<Option<ServerAccessToken>, CocoError> {
+    match get_server_token(server_id).await? {
+        Some(token) if token.is_expired() => {
+            handle_auth_expired(server_id).await;
+            Err(CocoError::AuthExpired)
+        }
+        token => Ok(token),
+    }
+}
+
+/// The token of server `server_id` has expired or has been rejected, forget
+/// it and the user profile, stop searching this server, and emit
+/// `server-auth-expired` with the server ID so that the frontend can ask the
+/// user to log in again.
+pub(crate) async fn handle_auth_expired(server_id: &str) {
+    // Concurrent requests can all find out that the token has expired, only
+    // report it once
+    if !remove_server_token(server_id) {
+        return;
+    }
+
+    log::warn!("access token of server [{}] has expired", server_id);
+
+    let app_handle = crate::GLOBAL_TAURI_APP_HANDLE
+        .get()
+        .expect("global tauri app handle not set");
+
+    if let Err(e) = persist_servers_token(app_handle) {
+        log::warn!("failed to persist server tokens: {}", e);
+    }
+
+    // Like a logout
+    if let Some(mut server) = get_server_by_id(server_id) {
+        server.profile = None;
+        save_server(&server);
+        if let Err(e) = persist_servers(app_handle).await {
+            log::warn!("failed to persist servers: {}", e);
+        }
+    }
+
+    let registry = app_handle.state::<SearchSourceRegistry>();
+    registry.remove_source(server_id).await;
+
+    let _ = app_handle.emit("server-auth-expired", server_id);
+}
+
+/// Log in to `server` with `access_token`, the token is only saved once the
+/// server accepts it to get the user profile.
+async fn complete_login<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    mut server: Server,
+    access_token: ServerAccessToken,
+) -> Result<Server, CocoError> {
+    let profile = fetch_user_profile(&server, &access_token.access_token).await?;
+
+    // Save the access token for the server
+    save_access_token(server.id.clone(), access_token);
+    persist_servers_token(app_handle)?;
+
+    server.profile = Some(profile);
+    server.available = true;
+    save_server(&server);
+    persist_servers(app_handle).await?;
+
+    // Register the server to the search source
+    try_register_server_to_search_source(app_handle.clone(), &server).await;
+
+    Ok(server)
 }
 
+/// Complete the SSO login `request_id` started by [`start_sso_login`], `code`
+/// comes from the `coco://oauth_callback` deep link, which anyone can open, so
+/// it is only trusted once exchanged along with the login's code verifier.
 #[tauri::command]
 pub async fn handle_sso_callback<R: Runtime>(
     app_handle: AppHandle<R>,
     server_id: String,
     request_id: String,
     code: String,
-) -> Result<(), String> {
+) -> Result<(), CocoError> {
     // Retrieve the server details using the server ID
     let server = get_server_by_id(&server_id);
 
-    let expire_in = 3600; // TODO, need to update to actual expire_in value
-    if let Some(mut server) = server {
-        // Save the access token for the server
-        let access_token = ServerAccessToken::new(server_id.clone(), code.clone(), expire_in);
-        // dbg!(&server_id, &request_id, &code, &token);
-        save_access_token(server_id.clone(), access_token);
-        persist_servers_token(&app_handle)?;
-
-        // Register the server to the search source
-        try_register_server_to_search_source(app_handle.clone(), &server).await;
-
-        // Update the server's profile using the util::http::HttpClient::get method
-        let profile = get_user_profiles(app_handle.clone(), server_id.clone()).await;
-        dbg!(&profile);
-
-        match profile {
-            Ok(p) => {
-                server.profile = Some(p);
-                server.available = true;
-                save_server(&server);
-                persist_servers(&app_handle).await?;
-                Ok(())
-            }
-            Err(e) => Err(format!("Failed to get user profile: {}", e)),
-        }
+    if let Some(server) = server {
+        let pending_login = take_pending_login(
+            &mut PENDING_LOGINS.lock().unwrap(),
+            &request_id,
+            &server_id,
+            Instant::now(),
+        )?;
+        let token =
+            request_access_token(&server, &request_id, &pending_login.code_verifier, &code).await?;
+
+        let access_token =
+            ServerAccessToken::expiring_in(server_id.clone(), token.access_token, token.expire_in);
+        complete_login(&app_handle, server, access_token).await?;
+
+        Ok(())
     } else {
-        Err(format!(
-            "Server not found for ID: {}, Request ID: {}, Code: {}",
-            server_id, request_id, code
-        ))
+        Err(CocoError::NotFound(format!(
+            "server [{}], request ID: {}",
+            server_id, request_id
+        )))
     }
 }
+
+/// Log in to server `server_id` with an API token (personal access token),
+/// for servers without SSO, or machines without a browser.
+#[tauri::command]
+pub async fn login_with_token<R: Runtime>(
+    app_handle: AppHandle<R>,
+    server_id: String,
+    token: String,
+) -> Result<Server, CocoError> {
+    let server = get_server_by_id(&server_id)
+        .ok_or_else(|| CocoError::NotFound(format!("server [{}]", server_id)))?;
+
+    // The lifetime of an API token is unknown
+    let access_token = ServerAccessToken::new(server_id, token.trim().to_string(), 0);
+
+    complete_login(&app_handle, server, access_token).await
+}
+
+/// Log in to server `server_id` with the credentials of a user, for servers
+/// without SSO.
+#[tauri::command]
+pub async fn login_with_password<R: Runtime>(
+    app_handle: AppHandle<R>,
+    server_id: String,
+    username: String,
+    password: String,
+) -> Result<Server, CocoError> {
+    let server = get_server_by_id(&server_id)
+        .ok_or_else(|| CocoError::NotFound(format!("server [{}]", server_id)))?;
+
+    let url = HttpClient::join_url(&server.endpoint, "/account/login");
+    let body = serde_json::json!({
+        "username": username,
+        "password": password,
+    });
+
+    let client = ServerHttpClient::for_server(&server)?;
+    let response = HttpClient::send_raw_request(
+        &client,
+        Method::POST,
+        &url,
+        None,
+        None,
+        Some(reqwest::Body::from(body.to_string())),
+    )
+    .await?;
+    let body = get_response_body_text(response).await?;
+    let token: RequestAccessTokenResponse = serde_json::from_str(&body)
+        .map_err(|e| CocoError::Parse(format!("Failed to parse access token: {}", e)))?;
+
+    let access_token =
+        ServerAccessToken::expiring_in(server_id, token.access_token, token.expire_in);
+
+    complete_login(&app_handle, server, access_token).await
+}
+
+#[test]
+fn test_code_challenge() {
+    // Example of RFC 7636, appendix B
+    assert_eq!(
+        code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+        "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+}
+
+#[test]
+fn test_take_pending_login() {
+    let now = Instant::now();
+    let pending_login = |server_id: &str, started_at: Instant| PendingLogin {
+        server_id: server_id.to_string(),
+        code_verifier: "verifier".to_string(),
+        started_at,
+    };
+    let mut pending_logins = HashMap::new();
+    pending_logins.insert("login".to_string(), pending_login("server", now));
+    pending_logins.insert("other".to_string(), pending_login("server", now));
+
+    // Replays are rejected
+    assert!(take_pending_login(&mut pending_logins, "login", "server", now).is_ok());
+    assert!(take_pending_login(&mut pending_logins, "login", "server", now).is_err());
+    // So are logins started for another server, which are still pending
+    assert!(take_pending_login(&mut pending_logins, "other", "another server", now).is_err());
+    assert!(take_pending_login(&mut pending_logins, "other", "server", now).is_ok());
+    assert!(take_pending_login(&mut pending_logins, "unknown", "server", now).is_err());
+
+    pending_logins.insert("login".to_string(), pending_login("server", now));
+    assert!(matches!(
+        take_pending_login(
+            &mut pending_logins,
+            "login",
+            "server",
+            now + SSO_LOGIN_TIMEOUT + Duration::from_secs(1)
+        ),
+        Err(CocoError::AuthExpired)
+    ));
+}
@@ -1,4 +1,5 @@
 use crate::common::connector::Connector;
+use crate::common::error::CocoError;
 use crate::common::search::parse_search_results;
 use crate::server::http_client::HttpClient;
 use crate::server::servers::get_all_servers;
@@ -28,7 +29,9 @@ pub fn get_connector_by_id(server_id: &str, connector_id: &str) -> Option<Connec
     Some(connector.clone())
 }
 
-pub async fn refresh_all_connectors<R: Runtime>(app_handle: &AppHandle<R>) -> Result<(), String> {
+pub async fn refresh_all_connectors<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<(), CocoError> {
     let servers = get_all_servers();
 
     // Collect all the tasks for fetching and refreshing connectors
@@ -72,7 +75,7 @@ pub async fn refresh_all_connectors<R: Runtime>(app_handle: &AppHandle<R>) -> Re
 #[allow(dead_code)]
 pub async fn get_connectors_from_cache_or_remote(
     server_id: &str,
-) -> Result<Vec<Connector>, String> {
+) -> Result<Vec<Connector>, CocoError> {
     // Acquire the read lock and check cache for connectors
     let cache = CONNECTOR_CACHE.read().unwrap(); // Acquire read lock
     if let Some(connectors) = cache.get(server_id).cloned() {
@@ -99,19 +102,12 @@ pub async fn get_connectors_from_cache_or_remote(
     Ok(connectors)
 }
 
-pub async fn fetch_connectors_by_server(id: &str) -> Result<Vec<Connector>, String> {
+pub async fn fetch_connectors_by_server(id: &str) -> Result<Vec<Connector>, CocoError> {
     // Use the generic GET method from HttpClient
-    let resp = HttpClient::get(&id, "/connector/_search", None)
-        .await
-        .map_err(|e| {
-            // dbg!("Error fetching connector for id {}: {}", &id, &e);
-            format!("Error fetching connector: {}", e)
-        })?;
+    let resp = HttpClient::get(&id, "/connector/_search", None).await?;
 
     // Parse the search results directly from the response body
-    let datasource: Vec<Connector> = parse_search_results(resp)
-        .await
-        .map_err(|e| e.to_string())?;
+    let datasource: Vec<Connector> = parse_search_results(resp).await?;
 
     // Save the connectors to the cache
     save_connectors_to_cache(&id, datasource.clone());
@@ -123,7 +119,7 @@ pub async fn fetch_connectors_by_server(id: &str) -> Result<Vec<Connector>, Stri
 pub async fn get_connectors_by_server<R: Runtime>(
     _app_handle: AppHandle<R>,
     id: String,
-) -> Result<Vec<Connector>, String> {
+) -> Result<Vec<Connector>, CocoError> {
     let connectors = fetch_connectors_by_server(&id).await?;
     Ok(connectors)
 }
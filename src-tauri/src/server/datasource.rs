@@ -1,4 +1,5 @@
 use crate::common::datasource::DataSource;
+use crate::common::error::CocoError;
 use crate::common::search::parse_search_results;
 use crate::server::connector::get_connector_by_id;
 use crate::server::http_client::HttpClient;
@@ -38,7 +39,9 @@ pub fn get_datasources_from_cache(server_id: &str) -> Option<HashMap<String, Dat
     Some(server_cache.clone())
 }
 
-pub async fn refresh_all_datasources<R: Runtime>(_app_handle: &AppHandle<R>) -> Result<(), String> {
+pub async fn refresh_all_datasources<R: Runtime>(
+    _app_handle: &AppHandle<R>,
+) -> Result<(), CocoError> {
     // dbg!("Attempting to refresh all datasources");
 
     let servers = get_all_servers();
@@ -98,7 +101,7 @@ pub async fn refresh_all_datasources<R: Runtime>(_app_handle: &AppHandle<R>) ->
 pub async fn datasource_search(
     id: &str,
     options: Option<GetDatasourcesByServerOptions>,
-) -> Result<Vec<DataSource>, String> {
+) -> Result<Vec<DataSource>, CocoError> {
     let from = options.as_ref().and_then(|opt| opt.from).unwrap_or(0);
     let size = options.as_ref().and_then(|opt| opt.size).unwrap_or(10000);
 
@@ -118,14 +121,10 @@ pub async fn datasource_search(
         None,
         Some(reqwest::Body::from(body.to_string())),
     )
-    .await
-    .map_err(|e| format!("Error fetching datasource: {}", e))?;
+    .await?;
 
     // Parse the search results from the response
-    let datasources: Vec<DataSource> = parse_search_results(resp).await.map_err(|e| {
-        //dbg!("Error parsing search results: {}", &e);
-        e.to_string()
-    })?;
+    let datasources: Vec<DataSource> = parse_search_results(resp).await?;
 
     // Save the updated datasources to cache
     save_datasource_to_cache(&id, datasources.clone());
@@ -139,14 +138,14 @@ pub async fn mcp_server_search(
     from: u32,
     size: u32,
     query: Option<HashMap<String, Value>>,
-) -> Result<Vec<DataSource>, String> {
+) -> Result<Vec<DataSource>, CocoError> {
     let mut body = serde_json::json!({
       "from": from,
       "size": size,
     });
 
     if let Some(q) = query {
-        body["query"] = serde_json::to_value(q).map_err(|e| e.to_string())?;
+        body["query"] = serde_json::to_value(q)?;
     }
 
     // Perform the async HTTP request outside the cache lock
@@ -156,14 +155,10 @@ pub async fn mcp_server_search(
         None,
         Some(reqwest::Body::from(body.to_string())),
     )
-    .await
-    .map_err(|e| format!("Error fetching datasource: {}", e))?;
+    .await?;
 
     // Parse the search results from the response
-    let mcp_server: Vec<DataSource> = parse_search_results(resp).await.map_err(|e| {
-        //dbg!("Error parsing search results: {}", &e);
-        e.to_string()
-    })?;
+    let mcp_server: Vec<DataSource> = parse_search_results(resp).await?;
 
     // Save the updated mcp_server to cache
     // save_datasource_to_cache(&id, mcp_server.clone());
@@ -0,0 +1,265 @@
+//! Background health checker of the Coco servers.
+//!
+//! Every enabled server gets its health endpoint probed periodically. Once a
+//! server goes offline, it is probed less and less frequently (exponential
+//! backoff) until it comes back online.
+//!
+//! A status change updates `Server.available` and `Server.health`, registers
+//! or unregisters the server's search source, and emits a
+//! `server-status-changed` event with a [`ServerStatusChanged`] payload,
+//! telling why the server is offline, e.g., a `tls` error if its certificate
+//! does not match the pinned one.
+
+use crate::common::error::CocoError;
+use crate::common::health::Health;
+use crate::common::http::get_response_body_text;
+use crate::common::register::SearchSourceRegistry;
+use crate::server::http_client::{HttpClient, ServerHttpClient};
+use crate::server::servers::{
+    get_all_servers, get_server_by_id, persist_servers, save_server,
+    try_register_server_to_search_source,
+};
+use futures::future::join_all;
+use reqwest::Method;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+/// Interval between 2 probes of a healthy server.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Upper bound of the interval between 2 probes of an offline server.
+const MAX_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// How often the checker wakes up to see which servers are due.
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+const HEALTH_PATH: &str = "/health";
+/// How long a server has to respond to a probe, so that a server that hangs
+/// does not hold up the probes of the others.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStatusChanged {
+    pub id: String,
+    pub available: bool,
+    pub health: Option<Health>,
+    /// Why the server is offline.
+    pub error: Option<CocoError>,
+}
+
+/// Probe state of a server.
+struct ProbeState {
+    consecutive_failures: u32,
+    next_probe: Instant,
+}
+
+/// Delay before the next probe of a server that failed `consecutive_failures`
+/// times in a row.
+fn next_probe_delay(consecutive_failures: u32) -> Duration {
+    HEALTH_CHECK_INTERVAL
+        .saturating_mul(2_u32.saturating_pow(consecutive_failures))
+        .min(MAX_HEALTH_CHECK_INTERVAL)
+}
+
+/// Whether `error` means that the server cannot serve requests, errors like
+/// an expired token are still answered by a working server.
+fn is_offline_error(error: &CocoError) -> bool {
+    match error {
+        CocoError::Network(_) | CocoError::Tls(_) | CocoError::Timeout => true,
+        CocoError::ServerError { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
+
+/// Probe the health endpoint of server `id`, return `Err` if it is offline.
+///
+/// It is a single request, not retried, and the server is offline if it does
+/// not respond within [`PROBE_TIMEOUT`].
+async fn probe(id: &str) -> Result<Option<Health>, CocoError> {
+    // It has been removed meanwhile
+    let Some(server) = get_server_by_id(id) else {
+        return Ok(None);
+    };
+
+    let request = async {
+        let client = ServerHttpClient::for_server(&server)?;
+        let url = HttpClient::join_url(&server.endpoint, HEALTH_PATH);
+        let response =
+            HttpClient::send_raw_request(&client, Method::GET, &url, None, None, None).await?;
+        get_response_body_text(response).await
+    };
+    let body = tokio::time::timeout(PROBE_TIMEOUT, request)
+        .await
+        .unwrap_or(Err(CocoError::Timeout));
+
+    match body {
+        // A server responding with something else than a health report is still online
+        Ok(body) => Ok(serde_json::from_str::<Health>(&body).ok()),
+        Err(e) if is_offline_error(&e) => Err(e),
+        Err(_) => Ok(None),
+    }
+}
+
+/// The health of a server once probed: an online server without a `probed`
+/// health report keeps the `current` one, e.g., from its provider info.
+fn reported_health(
+    available: bool,
+    probed: Option<Health>,
+    current: Option<Health>,
+) -> Option<Health> {
+    if available {
+        probed.or(current)
+    } else {
+        None
+    }
+}
+
+/// Record the probe result of server `id`, and propagate the change, if any.
+async fn update_server_status<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    id: &str,
+    available: bool,
+    health: Option<Health>,
+    error: Option<CocoError>,
+) {
+    // The server could have been updated or removed while being probed
+    let Some(mut server) = get_server_by_id(id) else {
+        return;
+    };
+
+    let health = reported_health(available, health, server.health.clone());
+
+    let availability_changed = server.available != available;
+    if !availability_changed && server.health == health {
+        return;
+    }
+
+    server.available = available;
+    server.health = health.clone();
+    save_server(&server);
+
+    if availability_changed {
+        log::info!(
+            "server [{}] is now {}",
+            id,
+            if available { "online" } else { "offline" }
+        );
+
+        if available {
+            try_register_server_to_search_source(app_handle.clone(), &server).await;
+        } else {
+            let registry = app_handle.state::<SearchSourceRegistry>();
+            registry.remove_source(id).await;
+        }
+
+        if let Err(e) = persist_servers(app_handle).await {
+            log::warn!("failed to persist servers: {}", e);
+        }
+    }
+
+    let _ = app_handle.emit(
+        "server-status-changed",
+        ServerStatusChanged {
+            id: id.to_string(),
+            available,
+            health,
+            error,
+        },
+    );
+}
+
+/// Start the health checker, it runs until the app exits.
+pub fn start_health_checker<R: Runtime>(app_handle: AppHandle<R>) {
+    tauri::async_runtime::spawn(async move {
+        let mut probe_states: HashMap<String, ProbeState> = HashMap::new();
+        let mut ticker = tokio::time::interval(TICK_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let now = Instant::now();
+            let servers: Vec<String> = get_all_servers()
+                .into_iter()
+                .filter(|server| server.enabled)
+                .map(|server| server.id)
+                .collect();
+
+            // Forget the servers that have been removed or disabled
+            probe_states.retain(|id, _| servers.contains(id));
+
+            let due_servers: Vec<String> = servers
+                .into_iter()
+                .filter(|id| {
+                    !probe_states
+                        .get(id)
+                        .is_some_and(|state| state.next_probe > now)
+                })
+                .collect();
+
+            let results = join_all(due_servers.iter().map(|id| probe(id))).await;
+
+            for (id, result) in due_servers.into_iter().zip(results) {
+                let state = probe_states.entry(id.clone()).or_insert(ProbeState {
+                    consecutive_failures: 0,
+                    next_probe: now,
+                });
+
+                match result {
+                    Ok(health) => {
+                        state.consecutive_failures = 0;
+                        update_server_status(&app_handle, &id, true, health, None).await;
+                    }
+                    Err(e) => {
+                        match &e {
+                            CocoError::Tls(_) => {
+                                log::warn!("server [{}] cannot be trusted: {}", id, e)
+                            }
+                            _ => log::debug!("health check of server [{}] failed: {}", id, e),
+                        }
+                        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+                        update_server_status(&app_handle, &id, false, None, Some(e)).await;
+                    }
+                }
+
+                state.next_probe = Instant::now() + next_probe_delay(state.consecutive_failures);
+            }
+        }
+    });
+}
+
+#[test]
+fn test_next_probe_delay() {
+    assert_eq!(next_probe_delay(0), HEALTH_CHECK_INTERVAL);
+    assert_eq!(next_probe_delay(1), HEALTH_CHECK_INTERVAL * 2);
+    assert_eq!(next_probe_delay(3), HEALTH_CHECK_INTERVAL * 8);
+    assert_eq!(next_probe_delay(5), MAX_HEALTH_CHECK_INTERVAL);
+    assert_eq!(next_probe_delay(u32::MAX), MAX_HEALTH_CHECK_INTERVAL);
+}
+
+#[test]
+fn test_reported_health() {
+    use crate::common::health::Status;
+
+    let health = |status| Health {
+        services: None,
+        status,
+    };
+
+    assert_eq!(
+        reported_health(
+            true,
+            Some(health(Status::Yellow)),
+            Some(health(Status::Green))
+        ),
+        Some(health(Status::Yellow))
+    );
+    // E.g., the server has no health endpoint
+    assert_eq!(
+        reported_health(true, None, Some(health(Status::Green))),
+        Some(health(Status::Green))
+    );
+    assert_eq!(
+        reported_health(false, None, Some(health(Status::Green))),
+        None
+    );
+}
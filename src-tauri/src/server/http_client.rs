@@ -1,31 +1,140 @@
-use crate::server::servers::{get_server_by_id, get_server_token};
+use crate::common::error::CocoError;
+use crate::common::server::{HttpConfig, ProxyConfig, Server, TlsConfig};
+use crate::server::auth::{get_valid_server_token, handle_auth_expired};
+use crate::server::retry::{is_transient_error, is_transient_status, random_jitter, RetryPolicy};
+use crate::server::servers::get_server_by_id;
+use crate::server::tls::configure_http_client;
 use http::{HeaderName, HeaderValue};
 use once_cell::sync::Lazy;
-use reqwest::{Client, Method, RequestBuilder};
+use reqwest::header::HeaderMap;
+use reqwest::{Client, ClientBuilder, Method, NoProxy, Proxy, RequestBuilder, StatusCode};
 use std::collections::HashMap;
+use std::sync::RwLock;
 use std::time::Duration;
 use tauri_plugin_store::JsonValue;
-use tokio::sync::Mutex;
-
-pub(crate) fn new_reqwest_http_client(accept_invalid_certs: bool) -> Client {
-    Client::builder()
-        .read_timeout(Duration::from_secs(3)) // Set a timeout of 3 second
-        .connect_timeout(Duration::from_secs(3)) // Set a timeout of 3 second
-        .timeout(Duration::from_secs(10)) // Set a timeout of 10 seconds
-        .danger_accept_invalid_certs(accept_invalid_certs) // allow self-signed certificates
-        .build()
-        .expect("Failed to build client")
-}
 
-pub static HTTP_CLIENT: Lazy<Mutex<Client>> = Lazy::new(|| {
+/// A client builder honoring `http` and `tls`, but the read and request
+/// timeouts, which depend on the kind of requests sent.
+fn http_client_builder(http: &HttpConfig, tls: &TlsConfig) -> Result<ClientBuilder, CocoError> {
     let allow_self_signature = crate::settings::_get_allow_self_signature(
         crate::GLOBAL_TAURI_APP_HANDLE
             .get()
             .expect("global tauri app store not set")
             .clone(),
     );
-    Mutex::new(new_reqwest_http_client(allow_self_signature))
-});
+
+    let mut default_headers = HeaderMap::new();
+    for (key, value) in http.default_headers.iter() {
+        let name = HeaderName::from_bytes(key.as_bytes())
+            .map_err(|e| CocoError::Internal(format!("Invalid header name [{}]: {}", key, e)))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|e| {
+            CocoError::Internal(format!("Invalid value of header [{}]: {}", key, e))
+        })?;
+        default_headers.insert(name, value);
+    }
+
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(http.connect_timeout))
+        .default_headers(default_headers)
+        .danger_accept_invalid_certs(allow_self_signature); // allow self-signed certificates
+
+    // The proxy of the environment is used by default
+    match &http.proxy {
+        ProxyConfig::System => {}
+        ProxyConfig::None => builder = builder.no_proxy(),
+        ProxyConfig::Custom { url, no_proxy } => {
+            let proxy = Proxy::all(url)
+                .map_err(|e| CocoError::Internal(format!("Invalid proxy [{}]: {}", url, e)))?
+                .no_proxy(no_proxy.as_deref().and_then(NoProxy::from_string));
+            builder = builder.proxy(proxy);
+        }
+    }
+
+    configure_http_client(builder, tls)
+}
+
+fn build_client(builder: ClientBuilder) -> Result<Client, CocoError> {
+    builder
+        .build()
+        .map_err(|e| CocoError::Internal(format!("Failed to build client: {}", e)))
+}
+
+/// A client honoring the HTTP and TLS settings of a server.
+#[derive(Clone)]
+pub(crate) struct ServerHttpClient {
+    client: Client,
+    http: HttpConfig,
+    tls: TlsConfig,
+}
+
+/// Clients of the servers, keyed by server ID.
+static SERVER_HTTP_CLIENTS: Lazy<RwLock<HashMap<String, ServerHttpClient>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+impl ServerHttpClient {
+    /// A client with settings `http` and `tls`, it fails if they are invalid,
+    /// e.g., a certificate cannot be parsed.
+    pub(crate) fn new(http: &HttpConfig, tls: &TlsConfig) -> Result<Self, CocoError> {
+        let builder = http_client_builder(http, tls)?
+            .read_timeout(Duration::from_secs(http.read_timeout))
+            .timeout(Duration::from_secs(http.request_timeout));
+
+        Ok(Self {
+            client: build_client(builder)?,
+            http: http.clone(),
+            tls: tls.clone(),
+        })
+    }
+
+    /// The client of `server`, it is rebuilt once the settings of the server
+    /// change.
+    pub(crate) fn for_server(server: &Server) -> Result<Self, CocoError> {
+        let is_up_to_date =
+            |client: &ServerHttpClient| client.http == server.http && client.tls == server.tls;
+
+        if let Some(client) = SERVER_HTTP_CLIENTS.read().unwrap().get(&server.id) {
+            if is_up_to_date(client) {
+                return Ok(client.clone());
+            }
+        }
+
+        let client = Self::new(&server.http, &server.tls)?;
+        SERVER_HTTP_CLIENTS
+            .write()
+            .unwrap()
+            .insert(server.id.clone(), client.clone());
+
+        Ok(client)
+    }
+
+    /// A client to upload attachments to `server`, without a read timeout, as
+    /// the server does not respond before receiving the whole upload.
+    pub(crate) fn for_upload(server: &Server) -> Result<Self, CocoError> {
+        let builder = http_client_builder(&server.http, &server.tls)?
+            .timeout(Duration::from_secs(server.http.upload_timeout));
+
+        Ok(Self {
+            client: build_client(builder)?,
+            http: server.http.clone(),
+            tls: server.tls.clone(),
+        })
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+/// Forget the client of server `id`.
+pub(crate) fn remove_server_http_client(id: &str) {
+    SERVER_HTTP_CLIENTS.write().unwrap().remove(id);
+}
+
+/// Forget the clients of all the servers, they are rebuilt on next use, e.g.,
+/// with the new `allow_self_signature` setting.
+pub(crate) fn clear_server_http_clients() {
+    SERVER_HTTP_CLIENTS.write().unwrap().clear();
+}
 
 pub struct HttpClient;
 
@@ -37,13 +146,38 @@ impl HttpClient {
         format!("{}/{}", base, path)
     }
 
-    pub async fn send_raw_request(
+    /// Send a request to `url` with `client`, the client of the server.
+    pub(crate) async fn send_raw_request(
+        client: &ServerHttpClient,
         method: Method,
         url: &str,
         query_params: Option<HashMap<String, JsonValue>>,
         headers: Option<HashMap<String, String>>,
         body: Option<reqwest::Body>,
-    ) -> Result<reqwest::Response, String> {
+    ) -> Result<reqwest::Response, CocoError> {
+        Self::send_with_retry(
+            client,
+            &RetryPolicy::NONE,
+            method,
+            url,
+            query_params,
+            headers,
+            body,
+        )
+        .await
+    }
+
+    /// Like [`send_raw_request`](Self::send_raw_request), but the request is
+    /// sent again, as specified by `retry_policy`, while it fails transiently.
+    async fn send_with_retry(
+        client: &ServerHttpClient,
+        retry_policy: &RetryPolicy,
+        method: Method,
+        url: &str,
+        query_params: Option<HashMap<String, JsonValue>>,
+        headers: Option<HashMap<String, String>>,
+        body: Option<reqwest::Body>,
+    ) -> Result<reqwest::Response, CocoError> {
         log::debug!(
             "Sending Request: {}, query_params: {:?}, header: {:?}, body: {:?}",
             &url,
@@ -52,33 +186,61 @@ impl HttpClient {
             &body
         );
 
-        let request_builder =
-            Self::get_request_builder(method, url, headers, query_params, body).await;
+        let mut request_builder =
+            Self::get_request_builder(client.client(), method, url, headers, query_params, body);
 
-        let response = request_builder.send().await.map_err(|e| {
-            //dbg!("Failed to send request: {}", &e);
-            format!("Failed to send request: {}", e)
-        })?;
+        let mut retry = 0;
+        loop {
+            // A request with a streamed body cannot be sent again
+            let next_request_builder = if retry < retry_policy.max_retries {
+                request_builder.try_clone()
+            } else {
+                None
+            };
 
-        log::debug!(
-            "Request: {}, Response status: {:?}, header: {:?}",
-            &url,
-            &response.status(),
-            &response.headers()
-        );
+            let result = request_builder.send().await.map_err(CocoError::from);
+            let is_transient = match &result {
+                Ok(response) => is_transient_status(response.status()),
+                Err(e) => is_transient_error(e),
+            };
 
-        Ok(response)
+            match next_request_builder {
+                Some(next_request_builder) if is_transient => {
+                    let delay = retry_policy.delay(retry, random_jitter());
+                    log::debug!(
+                        "Request: {} failed transiently, retrying in {:?}",
+                        &url,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+
+                    request_builder = next_request_builder;
+                    retry += 1;
+                }
+                _ => {
+                    if let Ok(response) = &result {
+                        log::debug!(
+                            "Request: {}, Response status: {:?}, header: {:?}",
+                            &url,
+                            &response.status(),
+                            &response.headers()
+                        );
+                    }
+
+                    return result;
+                }
+            }
+        }
     }
 
-    pub async fn get_request_builder(
+    pub fn get_request_builder(
+        client: &Client,
         method: Method,
         url: &str,
         headers: Option<HashMap<String, String>>,
         query_params: Option<HashMap<String, JsonValue>>, // Add query parameters
         body: Option<reqwest::Body>,
     ) -> RequestBuilder {
-        let client = HTTP_CLIENT.lock().await; // Acquire the lock on HTTP_CLIENT
-
         // Build the request
         let mut request_builder = client.request(method.clone(), url);
 
@@ -142,7 +304,32 @@ impl HttpClient {
         custom_headers: Option<HashMap<String, String>>,
         query_params: Option<HashMap<String, JsonValue>>,
         body: Option<reqwest::Body>,
-    ) -> Result<reqwest::Response, String> {
+    ) -> Result<reqwest::Response, CocoError> {
+        let retry_policy = RetryPolicy::for_method(&method);
+        Self::send_request_with_retry_policy(
+            server_id,
+            &retry_policy,
+            method,
+            path,
+            custom_headers,
+            query_params,
+            body,
+        )
+        .await
+    }
+
+    /// Like [`send_request`](Self::send_request), but the request is retried
+    /// as specified by `retry_policy`, rather than by the default policy of
+    /// `method`.
+    pub(crate) async fn send_request_with_retry_policy(
+        server_id: &str,
+        retry_policy: &RetryPolicy,
+        method: Method,
+        path: &str,
+        custom_headers: Option<HashMap<String, String>>,
+        query_params: Option<HashMap<String, JsonValue>>,
+        body: Option<reqwest::Body>,
+    ) -> Result<reqwest::Response, CocoError> {
         // Fetch the server using the server_id
         let server = get_server_by_id(server_id);
         if let Some(s) = server {
@@ -150,9 +337,10 @@ impl HttpClient {
             let url = HttpClient::join_url(&s.endpoint, path);
 
             // Retrieve the token for the server (token is optional)
-            let token = get_server_token(server_id)
+            let token = get_valid_server_token(server_id)
                 .await?
                 .map(|t| t.access_token.clone());
+            let has_token = token.is_some();
 
             let mut headers = if let Some(custom_headers) = custom_headers {
                 custom_headers
@@ -172,9 +360,26 @@ impl HttpClient {
             //     &headers
             // );
 
-            Self::send_raw_request(method, &url, query_params, Some(headers), body).await
+            let client = ServerHttpClient::for_server(&s)?;
+            let response = Self::send_with_retry(
+                &client,
+                retry_policy,
+                method,
+                &url,
+                query_params,
+                Some(headers),
+                body,
+            )
+            .await?;
+
+            // The server no longer accepts the token
+            if has_token && response.status() == StatusCode::UNAUTHORIZED {
+                handle_auth_expired(server_id).await;
+            }
+
+            Ok(response)
         } else {
-            Err("Server not found".to_string())
+            Err(CocoError::NotFound(format!("server [{}]", server_id)))
         }
     }
 
@@ -183,7 +388,7 @@ impl HttpClient {
         server_id: &str,
         path: &str,
         query_params: Option<HashMap<String, JsonValue>>, // Add query parameters
-    ) -> Result<reqwest::Response, String> {
+    ) -> Result<reqwest::Response, CocoError> {
         HttpClient::send_request(server_id, Method::GET, path, None, query_params, None).await
     }
 
@@ -193,7 +398,7 @@ impl HttpClient {
         path: &str,
         query_params: Option<HashMap<String, JsonValue>>, // Add query parameters
         body: Option<reqwest::Body>,
-    ) -> Result<reqwest::Response, String> {
+    ) -> Result<reqwest::Response, CocoError> {
         HttpClient::send_request(server_id, Method::POST, path, None, query_params, body).await
     }
 
@@ -203,7 +408,7 @@ impl HttpClient {
         custom_headers: Option<HashMap<String, String>>,
         query_params: Option<HashMap<String, JsonValue>>, // Add query parameters
         body: Option<reqwest::Body>,
-    ) -> Result<reqwest::Response, String> {
+    ) -> Result<reqwest::Response, CocoError> {
         HttpClient::send_request(
             server_id,
             Method::POST,
@@ -223,7 +428,7 @@ impl HttpClient {
         custom_headers: Option<HashMap<String, String>>,
         query_params: Option<HashMap<String, JsonValue>>, // Add query parameters
         body: Option<reqwest::Body>,
-    ) -> Result<reqwest::Response, String> {
+    ) -> Result<reqwest::Response, CocoError> {
         HttpClient::send_request(
             server_id,
             Method::PUT,
@@ -242,7 +447,7 @@ impl HttpClient {
         path: &str,
         custom_headers: Option<HashMap<String, String>>,
         query_params: Option<HashMap<String, JsonValue>>, // Add query parameters
-    ) -> Result<reqwest::Response, String> {
+    ) -> Result<reqwest::Response, CocoError> {
         HttpClient::send_request(
             server_id,
             Method::DELETE,
@@ -254,3 +459,36 @@ impl HttpClient {
         .await
     }
 }
+
+#[test]
+fn test_pin_mismatch_is_a_tls_error_and_not_retried() {
+    use std::sync::atomic::Ordering;
+
+    let (address, connections) = crate::server::tls::spawn_test_server();
+    let tls = TlsConfig {
+        pinned_cert_sha256: Some("00".repeat(32)),
+        ..Default::default()
+    };
+    let client = ServerHttpClient {
+        client: configure_http_client(Client::builder(), &tls)
+            .unwrap()
+            .build()
+            .unwrap(),
+        http: HttpConfig::default(),
+        tls,
+    };
+
+    let url = format!("https://{}/health", address);
+    let result = tauri::async_runtime::block_on(HttpClient::send_with_retry(
+        &client,
+        &RetryPolicy::for_method(&Method::GET),
+        Method::GET,
+        &url,
+        None,
+        None,
+        None,
+    ));
+
+    assert!(matches!(result, Err(CocoError::Tls(_))), "{:?}", result);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
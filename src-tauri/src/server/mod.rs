@@ -4,10 +4,15 @@ pub mod attachment;
 pub mod auth;
 pub mod connector;
 pub mod datasource;
+pub mod health;
 pub mod http_client;
 pub mod profile;
+pub mod retry;
 pub mod search;
+pub mod search_cache;
+pub mod secrets;
 pub mod servers;
 pub mod system_settings;
+pub mod tls;
 pub mod transcription;
 pub mod websocket;
@@ -1,29 +1,53 @@
+use crate::common::error::CocoError;
 use crate::common::http::get_response_body_text;
 use crate::common::profile::UserProfile;
-use crate::server::http_client::HttpClient;
+use crate::common::server::Server;
+use crate::server::http_client::{HttpClient, ServerHttpClient};
+use reqwest::Method;
+use std::collections::HashMap;
 use tauri::{AppHandle, Runtime};
 
-#[tauri::command]
-pub async fn get_user_profiles<R: Runtime>(
-    _app_handle: AppHandle<R>,
-    server_id: String,
-) -> Result<UserProfile, String> {
-    // Use the generic GET method from HttpClient
-    let response = HttpClient::get(&server_id, "/account/profile", None)
-        .await
-        .map_err(|e| format!("Error fetching profile: {}", e))?;
+const PROFILE_PATH: &str = "/account/profile";
 
+async fn parse_user_profile(response: reqwest::Response) -> Result<UserProfile, CocoError> {
     // Use get_response_body_text to extract the body content
-    let response_body = get_response_body_text(response)
-        .await
-        .map_err(|e| format!("Failed to read response body: {}", e))?;
+    let response_body = get_response_body_text(response).await?;
 
     // Check if the response body is not empty before deserializing
     if !response_body.is_empty() {
         let profile: UserProfile = serde_json::from_str(&response_body)
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
+            .map_err(|e| CocoError::Parse(format!("Failed to parse profile: {}", e)))?;
         return Ok(profile);
     }
 
-    Err("Profile not found or empty response".to_string())
+    Err(CocoError::NotFound("profile".to_string()))
+}
+
+#[tauri::command]
+pub async fn get_user_profiles<R: Runtime>(
+    _app_handle: AppHandle<R>,
+    server_id: String,
+) -> Result<UserProfile, CocoError> {
+    // Use the generic GET method from HttpClient
+    let response = HttpClient::get(&server_id, PROFILE_PATH, None).await?;
+
+    parse_user_profile(response).await
+}
+
+/// Get the profile of the user of `access_token`, which is not necessarily
+/// saved yet, this is how a token is validated.
+pub(crate) async fn fetch_user_profile(
+    server: &Server,
+    access_token: &str,
+) -> Result<UserProfile, CocoError> {
+    let url = HttpClient::join_url(&server.endpoint, PROFILE_PATH);
+
+    let mut headers = HashMap::new();
+    headers.insert("X-API-TOKEN".to_string(), access_token.to_string());
+
+    let client = ServerHttpClient::for_server(server)?;
+    let response =
+        HttpClient::send_raw_request(&client, Method::GET, &url, None, Some(headers), None).await?;
+
+    parse_user_profile(response).await
 }
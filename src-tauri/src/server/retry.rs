@@ -0,0 +1,222 @@
+//! Handling of the transient failures of the Coco servers.
+//!
+//! * [`RetryPolicy`]: idempotent requests failing with a connection error, a
+//!   timeout or a 502/503/504 are retried, with an exponential backoff and full
+//!   jitter
+//! * [`CircuitBreaker`]: once a server fails a number of times in a row, the
+//!   requests to it fail right away for a while, then a single request is let
+//!   through to check whether it has recovered
+
+use crate::common::error::CocoError;
+use reqwest::{Method, StatusCode};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub(crate) struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Send a request once.
+    pub(crate) const NONE: Self = Self {
+        max_retries: 0,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    /// The policy of the requests sent to a server, only idempotent requests
+    /// are retried.
+    pub(crate) fn for_method(method: &Method) -> Self {
+        if !is_idempotent(method) {
+            return Self::NONE;
+        }
+
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+
+    /// Delay before retry `retry` (0 for the first one), a random duration up
+    /// to the exponential backoff, so that clients do not retry in lockstep.
+    ///
+    /// `jitter` is in `[0, 1]`.
+    pub(crate) fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(retry))
+            .min(self.max_delay);
+        backoff.mul_f64(jitter.clamp(0.0, 1.0))
+    }
+}
+
+/// A random number in `[0, 1]`.
+pub(crate) fn random_jitter() -> f64 {
+    let mut bytes = [0; 4];
+    match SystemRandom::new().fill(&mut bytes) {
+        Ok(()) => f64::from(u32::from_le_bytes(bytes)) / f64::from(u32::MAX),
+        Err(_) => 1.0,
+    }
+}
+
+/// Whether sending a request more than once has the same effect as sending it
+/// once.
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    )
+}
+
+/// Whether a request that got a response with `status` can succeed later.
+pub(crate) fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Whether a request that failed with `error` can succeed later, a TLS error
+/// is a configuration issue, it would fail again.
+pub(crate) fn is_transient_error(error: &CocoError) -> bool {
+    match error {
+        CocoError::Network(_) | CocoError::Timeout => true,
+        CocoError::ServerError { status, .. } => StatusCode::from_u16(*status)
+            .map(is_transient_status)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Failures in a row after which a server is considered failing.
+const FAILURE_THRESHOLD: u32 = 5;
+/// How long the requests to a failing server fail right away.
+const OPEN_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    Closed {
+        consecutive_failures: u32,
+    },
+    /// Requests fail right away until `until`.
+    Open {
+        until: Instant,
+    },
+    /// A request has been let through at `since` to check whether the server
+    /// has recovered, the others fail right away meanwhile.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+/// Circuit breakers of the servers, keyed by server ID.
+#[derive(Default)]
+pub(crate) struct CircuitBreaker {
+    circuits: Mutex<HashMap<String, CircuitState>>,
+}
+
+impl CircuitBreaker {
+    /// Whether a request can be sent to server `server_id`, if so its outcome
+    /// must be recorded with [`record_success`](Self::record_success) or
+    /// [`record_failure`](Self::record_failure).
+    pub(crate) fn allow_request(&self, server_id: &str, now: Instant) -> bool {
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(state) = circuits.get_mut(server_id) else {
+            return true;
+        };
+
+        match *state {
+            CircuitState::Closed { .. } => true,
+            CircuitState::Open { until } if now < until => false,
+            // The request checking whether the server has recovered could have
+            // been dropped before recording its outcome, e.g., its search
+            // timed out, let another one through
+            CircuitState::HalfOpen { since } if now.duration_since(since) < OPEN_DURATION => false,
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => {
+                *state = CircuitState::HalfOpen { since: now };
+                true
+            }
+        }
+    }
+
+    pub(crate) fn record_success(&self, server_id: &str) {
+        self.circuits.lock().unwrap().remove(server_id);
+    }
+
+    pub(crate) fn record_failure(&self, server_id: &str, now: Instant) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let state = circuits
+            .entry(server_id.to_string())
+            .or_insert(CircuitState::Closed {
+                consecutive_failures: 0,
+            });
+
+        *state = match *state {
+            CircuitState::Closed {
+                consecutive_failures,
+            } if consecutive_failures + 1 < FAILURE_THRESHOLD => CircuitState::Closed {
+                consecutive_failures: consecutive_failures + 1,
+            },
+            _ => {
+                log::warn!(
+                    "server [{}] is failing, not sending requests to it for {:?}",
+                    server_id,
+                    OPEN_DURATION
+                );
+                CircuitState::Open {
+                    until: now + OPEN_DURATION,
+                }
+            }
+        };
+    }
+}
+
+#[test]
+fn test_retry_delay() {
+    let policy = RetryPolicy::for_method(&Method::GET);
+    assert_eq!(policy.delay(0, 1.0), Duration::from_millis(200));
+    assert_eq!(policy.delay(1, 1.0), Duration::from_millis(400));
+    assert_eq!(policy.delay(1, 0.5), Duration::from_millis(200));
+    assert_eq!(policy.delay(10, 1.0), Duration::from_secs(2));
+    assert_eq!(policy.delay(u32::MAX, 1.0), Duration::from_secs(2));
+    assert_eq!(policy.delay(3, 0.0), Duration::ZERO);
+
+    assert_eq!(RetryPolicy::for_method(&Method::POST).max_retries, 0);
+}
+
+#[test]
+fn test_circuit_breaker() {
+    let breaker = CircuitBreaker::default();
+    let now = Instant::now();
+
+    for _ in 0..FAILURE_THRESHOLD - 1 {
+        assert!(breaker.allow_request("server", now));
+        breaker.record_failure("server", now);
+    }
+    // A success resets the count
+    breaker.record_success("server");
+    for _ in 0..FAILURE_THRESHOLD {
+        assert!(breaker.allow_request("server", now));
+        breaker.record_failure("server", now);
+    }
+    assert!(!breaker.allow_request("server", now));
+    assert!(breaker.allow_request("another server", now));
+
+    // A single request is let through once open for long enough
+    let later = now + OPEN_DURATION;
+    assert!(breaker.allow_request("server", later));
+    assert!(!breaker.allow_request("server", later));
+    breaker.record_failure("server", later);
+    assert!(!breaker.allow_request("server", later));
+
+    let even_later = later + OPEN_DURATION;
+    assert!(breaker.allow_request("server", even_later));
+    breaker.record_success("server");
+    assert!(breaker.allow_request("server", even_later));
+}
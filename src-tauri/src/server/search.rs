@@ -5,10 +5,15 @@ use crate::common::search::{QueryHits, QueryResponse, QuerySource, SearchQuery,
 use crate::common::server::Server;
 use crate::common::traits::SearchSource;
 use crate::server::http_client::HttpClient;
+use crate::server::retry::{CircuitBreaker, RetryPolicy};
+use crate::server::search_cache::search_cache;
 use async_trait::async_trait;
+use once_cell::sync::Lazy;
 // use futures::stream::StreamExt;
 use ordered_float::OrderedFloat;
+use reqwest::Method;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tauri_plugin_store::JsonValue;
 // use std::hash::Hash;
 
@@ -71,6 +76,34 @@ impl DocumentsSizedCollector {
 
 const COCO_SERVERS: &str = "coco-servers";
 
+/// A server failing to respond is not searched for a while, rather than
+/// making every search wait for it to time out.
+static SEARCH_CIRCUIT_BREAKER: Lazy<CircuitBreaker> = Lazy::new(CircuitBreaker::default);
+
+/// Share of the query timeout given to the server, the rest is left to fall
+/// back on the cached results.
+const SERVER_TIMEOUT_RATIO: f64 = 0.8;
+
+/// How long the server has to respond to `query`, shorter than the timeout
+/// of the query so that a server that hangs counts as a failure of the
+/// server, rather than the search being dropped.
+fn server_timeout(query: &SearchQuery) -> Option<Duration> {
+    query
+        .timeout
+        .map(|timeout| timeout.mul_f64(SERVER_TIMEOUT_RATIO))
+}
+
+/// Whether a search failed with `error` because of the server, rather than the
+/// query. A server that cannot be trusted is not failing, its cached results
+/// are not served either.
+fn is_server_failure(error: &SearchError) -> bool {
+    match error {
+        SearchError::HttpError(_) | SearchError::Timeout => true,
+        SearchError::ServerError { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
+
 pub struct CocoSearchSource {
     server: Server,
 }
@@ -92,6 +125,69 @@ impl SearchSource for CocoSearchSource {
     }
 
     async fn search(&self, query: SearchQuery) -> Result<QueryResponse, SearchError> {
+        let server_id = &self.server.id;
+        let result = if SEARCH_CIRCUIT_BREAKER.allow_request(server_id, Instant::now()) {
+            let search = self.search_server(query.clone());
+            let result = match server_timeout(&query) {
+                Some(server_timeout) => tokio::time::timeout(server_timeout, search)
+                    .await
+                    .unwrap_or(Err(SearchError::Timeout)),
+                None => search.await,
+            };
+            match &result {
+                Err(e) if is_server_failure(e) => {
+                    SEARCH_CIRCUIT_BREAKER.record_failure(server_id, Instant::now())
+                }
+                _ => SEARCH_CIRCUIT_BREAKER.record_success(server_id),
+            }
+            result
+        } else {
+            Err(SearchError::HttpError(format!(
+                "server [{}] is failing, it is not searched until it recovers",
+                server_id
+            )))
+        };
+
+        let Some(cache) = search_cache() else {
+            return result;
+        };
+
+        match result {
+            Ok(response) => {
+                let server_id = server_id.clone();
+                let total_hits = response.total_hits;
+                let hits = response.hits.clone();
+                // Do not make the search wait for the disk
+                tauri::async_runtime::spawn_blocking(move || {
+                    if let Err(e) = cache.put(&server_id, &query, total_hits, hits) {
+                        log::warn!("failed to cache search results: {}", e);
+                    }
+                });
+                Ok(response)
+            }
+            // Serve the results of the last time the server could be reached
+            Err(e) if is_server_failure(&e) => match cache.get(server_id, &query) {
+                Some(cached_search) => {
+                    log::debug!(
+                        "server [{}] cannot be reached, serving cached results: {}",
+                        server_id,
+                        e
+                    );
+                    Ok(QueryResponse {
+                        source: self.get_type(),
+                        total_hits: cached_search.total_hits,
+                        hits: cached_search.into_marked_hits(),
+                    })
+                }
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl CocoSearchSource {
+    async fn search_server(&self, query: SearchQuery) -> Result<QueryResponse, SearchError> {
         let url = "/query/_search";
 
         let mut query_args: HashMap<String, JsonValue> = HashMap::new();
@@ -101,14 +197,21 @@ impl SearchSource for CocoSearchSource {
             query_args.insert(key, JsonValue::String(value));
         }
 
-        let response = HttpClient::get(&self.server.id, &url, Some(query_args))
-            .await
-            .map_err(|e| SearchError::HttpError(format!("Error to send search request: {}", e)))?;
-
-        // Use the helper function to parse the response body
-        let response_body = get_response_body_text(response)
-            .await
-            .map_err(|e| SearchError::ParseError(format!("Failed to read response body: {}", e)))?;
+        // A retry would not fit in the time the server has to respond
+        let response = HttpClient::send_request_with_retry_policy(
+            &self.server.id,
+            &RetryPolicy::NONE,
+            Method::GET,
+            url,
+            None,
+            Some(query_args),
+            None,
+        )
+        .await?;
+
+        // Use the helper function to parse the response body, an unsuccessful
+        // response is reported with the status and reason given by the server
+        let response_body = get_response_body_text(response).await?;
 
         // Parse the search response from the body text
         let parsed: SearchResponse<Document> =
@@ -133,3 +236,18 @@ impl SearchSource for CocoSearchSource {
         })
     }
 }
+
+#[test]
+fn test_server_timeout_leaves_time_for_cached_results() {
+    let query = SearchQuery::new(0, 10, HashMap::new());
+    assert_eq!(server_timeout(&query), None);
+
+    let query_timeout = Duration::from_millis(500);
+    let server_timeout = server_timeout(&query.with_timeout(query_timeout)).unwrap();
+    assert!(server_timeout < query_timeout);
+    // So a server that hangs is failing, and its cached results are served
+    assert!(is_server_failure(&SearchError::Timeout));
+    assert!(!is_server_failure(&SearchError::TlsError(
+        "certificate does not match the pin".to_string()
+    )));
+}
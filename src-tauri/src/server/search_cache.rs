@@ -0,0 +1,379 @@
+//! On-disk cache of the recent search results of the Coco servers, served when
+//! a server cannot be reached, e.g., the laptop is offline.
+//!
+//! Every response is stored in its own file, named after the server and the
+//! normalized query. The cache is bounded in size, the oldest entries are
+//! evicted first, and entries older than [`CACHE_TTL`] are never served.
+//!
+//! The hits served from the cache have their `Document.metadata` marked with
+//! [`METADATA_CACHED`] and [`METADATA_CACHED_AT`].
+
+use crate::common::document::Document;
+use crate::common::error::CocoError;
+use crate::common::search::SearchQuery;
+use crate::server::secrets::write_private_file;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tauri::Manager;
+
+const CACHE_DIR_NAME: &str = "search_cache";
+const CACHE_FILE_EXTENSION: &str = "json";
+const MAX_CACHE_SIZE: u64 = 20 * 1024 * 1024;
+const CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// `true` for the hits served from the cache.
+pub(crate) const METADATA_CACHED: &str = "cached";
+/// When the hits served from the cache were cached, in RFC 3339.
+pub(crate) const METADATA_CACHED_AT: &str = "cached_at";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CachedSearch {
+    /// Unix timestamp in seconds.
+    pub cached_at: i64,
+    pub total_hits: usize,
+    pub hits: Vec<(Document, f64)>,
+}
+
+impl CachedSearch {
+    /// The cached hits, marked as cached.
+    pub(crate) fn into_marked_hits(self) -> Vec<(Document, f64)> {
+        let cached_at = chrono::DateTime::from_timestamp(self.cached_at, 0)
+            .map(|cached_at| cached_at.to_rfc3339())
+            .unwrap_or_default();
+
+        self.hits
+            .into_iter()
+            .map(|(mut document, score)| {
+                let metadata = document.metadata.get_or_insert_with(HashMap::new);
+                metadata.insert(METADATA_CACHED.to_string(), true.into());
+                metadata.insert(METADATA_CACHED_AT.to_string(), cached_at.clone().into());
+                (document, score)
+            })
+            .collect()
+    }
+}
+
+struct CacheEntry {
+    size: u64,
+    modified: SystemTime,
+}
+
+pub(crate) struct SearchCache {
+    dir: PathBuf,
+    max_size: u64,
+    ttl: Duration,
+    /// Keyed by file name.
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+static SEARCH_CACHE: OnceLock<Option<SearchCache>> = OnceLock::new();
+
+/// The search cache of the app, opened on first use, `None` if it cannot be.
+pub(crate) fn search_cache() -> Option<&'static SearchCache> {
+    SEARCH_CACHE
+        .get_or_init(|| {
+            let app_handle = crate::GLOBAL_TAURI_APP_HANDLE
+                .get()
+                .expect("global tauri app handle not set");
+            let dir = match app_handle.path().app_cache_dir() {
+                Ok(dir) => dir.join(CACHE_DIR_NAME),
+                Err(e) => {
+                    log::error!("failed to get app cache dir: {}", e);
+                    return None;
+                }
+            };
+
+            SearchCache::open(&dir, MAX_CACHE_SIZE, CACHE_TTL)
+                .map_err(|e| log::error!("failed to open search cache {:?}: {}", dir, e))
+                .ok()
+        })
+        .as_ref()
+}
+
+fn hex_digest(data: &str) -> String {
+    digest::digest(&digest::SHA256, data.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// `query` in a canonical form, so that queries only differing by the case
+/// or the spacing of their text share a cache entry.
+fn normalized_query(query: &SearchQuery) -> String {
+    let mut query_strings: Vec<(&String, String)> = query
+        .query_strings
+        .iter()
+        .map(|(key, value)| {
+            let value = if key == "query" {
+                value
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .to_lowercase()
+            } else {
+                value.clone()
+            };
+            (key, value)
+        })
+        .collect();
+    query_strings.sort();
+
+    let query_strings: Vec<String> = query_strings
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    format!("{}:{}:{}", query.from, query.size, query_strings.join("&"))
+}
+
+/// Prefix of the file names of the entries of server `server_id`.
+fn server_prefix(server_id: &str) -> String {
+    format!("{}_", &hex_digest(server_id)[..16])
+}
+
+fn entry_file_name(server_id: &str, query: &SearchQuery) -> String {
+    format!(
+        "{}{}.{}",
+        server_prefix(server_id),
+        hex_digest(&normalized_query(query)),
+        CACHE_FILE_EXTENSION
+    )
+}
+
+impl SearchCache {
+    /// Open the cache in `dir`, its expired entries are removed.
+    pub(crate) fn open(dir: &Path, max_size: u64, ttl: Duration) -> Result<Self, CocoError> {
+        fs::create_dir_all(dir)
+            .map_err(|e| CocoError::Internal(format!("Failed to create {:?}: {}", dir, e)))?;
+
+        let read_dir = fs::read_dir(dir)
+            .map_err(|e| CocoError::Internal(format!("Failed to read {:?}: {}", dir, e)))?;
+
+        let now = SystemTime::now();
+        let mut entries = HashMap::new();
+        for dir_entry in read_dir.flatten() {
+            let path = dir_entry.path();
+            let Ok(metadata) = dir_entry.metadata() else {
+                continue;
+            };
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            let is_expired = now.duration_since(modified).unwrap_or_default() > ttl;
+
+            // Leftovers of interrupted writes are removed too
+            if is_expired || path.extension().and_then(|e| e.to_str()) != Some(CACHE_FILE_EXTENSION)
+            {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+
+            entries.insert(
+                dir_entry.file_name().to_string_lossy().into_owned(),
+                CacheEntry {
+                    size: metadata.len(),
+                    modified,
+                },
+            );
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_size,
+            ttl,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// The cached response of server `server_id` to `query`, if it has not
+    /// expired.
+    pub(crate) fn get(&self, server_id: &str, query: &SearchQuery) -> Option<CachedSearch> {
+        let file_name = entry_file_name(server_id, query);
+        if !self.entries.lock().unwrap().contains_key(&file_name) {
+            return None;
+        }
+
+        let path = self.dir.join(&file_name);
+        let cached_search = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<CachedSearch>(&data).ok());
+
+        let Some(cached_search) = cached_search else {
+            log::warn!("removing invalid search cache entry {:?}", path);
+            self.remove(&file_name);
+            return None;
+        };
+
+        let age = chrono::Utc::now().timestamp() - cached_search.cached_at;
+        if age < 0 || age as u64 > self.ttl.as_secs() {
+            self.remove(&file_name);
+            return None;
+        }
+
+        Some(cached_search)
+    }
+
+    /// Cache the response of server `server_id` to `query`, evicting the
+    /// oldest entries if the cache gets too big.
+    pub(crate) fn put(
+        &self,
+        server_id: &str,
+        query: &SearchQuery,
+        total_hits: usize,
+        hits: Vec<(Document, f64)>,
+    ) -> Result<(), CocoError> {
+        let file_name = entry_file_name(server_id, query);
+        let data = serde_json::to_vec(&CachedSearch {
+            cached_at: chrono::Utc::now().timestamp(),
+            total_hits,
+            hits,
+        })?;
+
+        let mut entries = self.entries.lock().unwrap();
+        write_private_file(&self.dir.join(&file_name), &data)?;
+        entries.insert(
+            file_name,
+            CacheEntry {
+                size: data.len() as u64,
+                modified: SystemTime::now(),
+            },
+        );
+
+        let mut size: u64 = entries.values().map(|entry| entry.size).sum();
+        while size > self.max_size {
+            let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.modified)
+                .map(|(file_name, _)| file_name.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = entries.remove(&oldest) {
+                size -= entry.size;
+            }
+            let _ = fs::remove_file(self.dir.join(&oldest));
+        }
+
+        Ok(())
+    }
+
+    /// Remove the entries of server `server_id`, or all of them.
+    pub(crate) fn purge(&self, server_id: Option<&str>) -> Result<(), CocoError> {
+        let prefix = server_id.map(server_prefix);
+
+        let mut entries = self.entries.lock().unwrap();
+        let file_names: Vec<String> = entries
+            .keys()
+            .filter(|file_name| match &prefix {
+                Some(prefix) => file_name.starts_with(prefix),
+                None => true,
+            })
+            .cloned()
+            .collect();
+
+        for file_name in file_names {
+            let path = self.dir.join(&file_name);
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(CocoError::Internal(format!(
+                        "Failed to remove {:?}: {}",
+                        path, e
+                    )))
+                }
+            }
+            entries.remove(&file_name);
+        }
+
+        Ok(())
+    }
+
+    fn remove(&self, file_name: &str) {
+        self.entries.lock().unwrap().remove(file_name);
+        let _ = fs::remove_file(self.dir.join(file_name));
+    }
+}
+
+/// Remove the cached search results of server `server_id`, or of all the
+/// servers.
+#[tauri::command]
+pub async fn purge_search_cache(server_id: Option<String>) -> Result<(), CocoError> {
+    match search_cache() {
+        Some(cache) => cache.purge(server_id.as_deref()),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_normalized_query() {
+    let query = |text: &str, datasource: &str| {
+        let mut query_strings = HashMap::new();
+        query_strings.insert("query".to_string(), text.to_string());
+        query_strings.insert("datasource".to_string(), datasource.to_string());
+        SearchQuery::new(0, 10, query_strings)
+    };
+
+    assert_eq!(
+        normalized_query(&query("  Rust   Book ", "Docs")),
+        "0:10:datasource=Docs&query=rust book"
+    );
+    assert_eq!(
+        normalized_query(&query("rust book", "Docs")),
+        normalized_query(&query("RUST  book", "Docs"))
+    );
+    assert_ne!(
+        normalized_query(&query("rust book", "Docs")),
+        normalized_query(&query("rust book", "docs"))
+    );
+}
+
+#[test]
+fn test_search_cache() {
+    let dir = std::env::temp_dir().join(format!(
+        "coco-search-cache-test-{}",
+        pizza_common::utils::uuid::Uuid::new()
+    ));
+    let query = |text: &str| {
+        let mut query_strings = HashMap::new();
+        query_strings.insert("query".to_string(), text.to_string());
+        SearchQuery::new(0, 10, query_strings)
+    };
+    let document = Document {
+        id: "doc".to_string(),
+        ..Default::default()
+    };
+
+    let cache = SearchCache::open(&dir, 1024 * 1024, CACHE_TTL).unwrap();
+    cache
+        .put("server", &query("rust"), 1, vec![(document.clone(), 1.0)])
+        .unwrap();
+    cache
+        .put("another server", &query("rust"), 1, vec![(document, 1.0)])
+        .unwrap();
+
+    let cached = cache.get("server", &query(" Rust")).unwrap();
+    assert_eq!(cached.total_hits, 1);
+    let hits = cached.into_marked_hits();
+    assert_eq!(
+        hits[0].0.metadata.as_ref().unwrap()[METADATA_CACHED],
+        serde_json::Value::Bool(true)
+    );
+    assert!(cache.get("server", &query("go")).is_none());
+
+    // Entries survive a restart
+    let cache = SearchCache::open(&dir, 1024 * 1024, CACHE_TTL).unwrap();
+    cache.purge(Some("server")).unwrap();
+    assert!(cache.get("server", &query("rust")).is_none());
+    assert!(cache.get("another server", &query("rust")).is_some());
+
+    // The oldest entries are evicted once the cache is too big
+    let cache = SearchCache::open(&dir, 1, CACHE_TTL).unwrap();
+    cache.put("server", &query("rust"), 0, Vec::new()).unwrap();
+    assert!(cache.get("another server", &query("rust")).is_none());
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,243 @@
+//! Storage of secrets, e.g., server access tokens.
+//!
+//! The default backend, [`EncryptedFileVault`], keeps secrets in a file
+//! encrypted with AES-256-GCM. Without a passphrase, the key is derived from a
+//! random key generated on first use and stored next to the vault, this
+//! protects secrets against casual disk reads (backups, synced folders,
+//! `grep`), not against a process running as the user.
+
+use crate::common::error::CocoError;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Manager, Runtime};
+
+const VAULT_FILE_NAME: &str = "secrets.vault";
+const VAULT_KEY_FILE_NAME: &str = "secrets.key";
+
+/// Header of the vault file, followed by the salt, the nonce and the encrypted
+/// entries.
+const VAULT_MAGIC: &[u8] = b"COCOVLT1";
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+pub(crate) trait SecretsBackend: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<String>, CocoError>;
+
+    fn set(&self, key: &str, value: String) -> Result<(), CocoError>;
+
+    fn delete(&self, key: &str) -> Result<(), CocoError>;
+}
+
+static SECRETS_BACKEND: OnceLock<Box<dyn SecretsBackend>> = OnceLock::new();
+
+/// The secrets backend of the app, opened on first use.
+pub(crate) fn secrets_backend<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<&'static dyn SecretsBackend, CocoError> {
+    if let Some(backend) = SECRETS_BACKEND.get() {
+        return Ok(backend.as_ref());
+    }
+
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| CocoError::Internal(format!("Failed to get app data dir: {}", e)))?;
+    let vault = EncryptedFileVault::open(&dir, None)?;
+
+    Ok(SECRETS_BACKEND.get_or_init(|| Box::new(vault)).as_ref())
+}
+
+/// Secrets kept in a file encrypted with a key derived from `passphrase`, or
+/// from a random key stored next to the vault if there is no passphrase.
+pub(crate) struct EncryptedFileVault {
+    path: PathBuf,
+    passphrase: Vec<u8>,
+    entries: Mutex<HashMap<String, String>>,
+}
+
+impl EncryptedFileVault {
+    /// Open the vault in `dir`, an empty vault is created if there is none.
+    ///
+    /// A vault that cannot be decrypted, e.g., its key file has been lost, is
+    /// moved aside and replaced by an empty one.
+    pub(crate) fn open(dir: &Path, passphrase: Option<&[u8]>) -> Result<Self, CocoError> {
+        fs::create_dir_all(dir)
+            .map_err(|e| CocoError::Internal(format!("Failed to create {:?}: {}", dir, e)))?;
+
+        let passphrase = match passphrase {
+            Some(passphrase) => passphrase.to_vec(),
+            None => load_or_create_key(&dir.join(VAULT_KEY_FILE_NAME))?,
+        };
+
+        let path = dir.join(VAULT_FILE_NAME);
+        let entries = match fs::read(&path) {
+            Ok(data) => match decrypt(&passphrase, &data)
+                .and_then(|plaintext| serde_json::from_slice(&plaintext).map_err(CocoError::from))
+            {
+                Ok(entries) => entries,
+                Err(e) => {
+                    let corrupted_path = path.with_extension("vault.corrupted");
+                    log::error!(
+                        "failed to open secrets vault {:?}, moving it to {:?}: {}",
+                        path,
+                        corrupted_path,
+                        e
+                    );
+                    let _ = fs::rename(&path, &corrupted_path);
+                    HashMap::new()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                return Err(CocoError::Internal(format!(
+                    "Failed to read {:?}: {}",
+                    path, e
+                )))
+            }
+        };
+
+        Ok(Self {
+            path,
+            passphrase,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    fn persist(&self, entries: &HashMap<String, String>) -> Result<(), CocoError> {
+        let plaintext = serde_json::to_vec(entries)?;
+        let data = encrypt(&self.passphrase, &plaintext)?;
+        write_private_file(&self.path, &data)
+    }
+}
+
+impl SecretsBackend for EncryptedFileVault {
+    fn get(&self, key: &str) -> Result<Option<String>, CocoError> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: String) -> Result<(), CocoError> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_string(), value);
+        self.persist(&entries)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), CocoError> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.remove(key).is_some() {
+            self.persist(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+fn load_or_create_key(path: &Path) -> Result<Vec<u8>, CocoError> {
+    match fs::read(path) {
+        Ok(key) if key.len() == KEY_LEN => return Ok(key),
+        Ok(_) => log::warn!("invalid secrets key {:?}, generating a new one", path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(CocoError::Internal(format!(
+                "Failed to read {:?}: {}",
+                path, e
+            )))
+        }
+    }
+
+    let mut key = vec![0; KEY_LEN];
+    fill_random(&mut key)?;
+    write_private_file(path, &key)?;
+    Ok(key)
+}
+
+/// Atomically replace `path` with `data`, readable by the user only.
+pub(crate) fn write_private_file(path: &Path, data: &[u8]) -> Result<(), CocoError> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let write = || -> std::io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    };
+
+    write().map_err(|e| CocoError::Internal(format!("Failed to write {:?}: {}", path, e)))
+}
+
+fn fill_random(buf: &mut [u8]) -> Result<(), CocoError> {
+    SystemRandom::new()
+        .fill(buf)
+        .map_err(|_| CocoError::Internal("Failed to generate random bytes".to_string()))
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8]) -> LessSafeKey {
+    let mut key = [0; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        passphrase,
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("key length is valid"))
+}
+
+fn encrypt(passphrase: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CocoError> {
+    let mut salt = [0; SALT_LEN];
+    fill_random(&mut salt)?;
+    let mut nonce = [0; NONCE_LEN];
+    fill_random(&mut nonce)?;
+
+    let mut in_out = plaintext.to_vec();
+    derive_key(passphrase, &salt)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(VAULT_MAGIC),
+            &mut in_out,
+        )
+        .map_err(|_| CocoError::Internal("Failed to encrypt secrets".to_string()))?;
+
+    Ok([VAULT_MAGIC, &salt[..], &nonce[..], &in_out[..]].concat())
+}
+
+fn decrypt(passphrase: &[u8], data: &[u8]) -> Result<Vec<u8>, CocoError> {
+    let invalid = || CocoError::Parse("invalid secrets vault".to_string());
+
+    let data = data.strip_prefix(VAULT_MAGIC).ok_or_else(invalid)?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err(invalid());
+    }
+    let (salt, data) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| invalid())?;
+
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = derive_key(passphrase, salt)
+        .open_in_place(nonce, Aad::from(VAULT_MAGIC), &mut in_out)
+        .map_err(|_| CocoError::Parse("failed to decrypt secrets vault".to_string()))?;
+
+    Ok(plaintext.to_vec())
+}
+
+#[test]
+fn test_encrypt_decrypt() {
+    let data = encrypt(b"passphrase", b"secret").unwrap();
+    assert!(!data.windows(6).any(|window| window == b"secret"));
+
+    assert_eq!(decrypt(b"passphrase", &data).unwrap(), b"secret");
+    assert!(decrypt(b"wrong passphrase", &data).is_err());
+    assert!(decrypt(b"passphrase", &data[..data.len() - 1]).is_err());
+}
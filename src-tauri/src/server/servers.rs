@@ -1,10 +1,17 @@
+use crate::assistant::chat_mirror::chat_mirror;
+use crate::common::error::CocoError;
 use crate::common::http::get_response_body_text;
 use crate::common::register::SearchSourceRegistry;
-use crate::common::server::{AuthProvider, Provider, Server, ServerAccessToken, Sso, Version};
+use crate::common::server::{
+    AuthProvider, HttpConfig, Provider, Server, ServerAccessToken, Sso, TlsConfig, Version,
+};
 use crate::server::connector::fetch_connectors_by_server;
 use crate::server::datasource::datasource_search;
-use crate::server::http_client::HttpClient;
+use crate::server::http_client::{remove_server_http_client, HttpClient, ServerHttpClient};
 use crate::server::search::CocoSearchSource;
+use crate::server::search_cache::search_cache;
+use crate::server::secrets::secrets_backend;
+use crate::server::tls::native_tls_connector;
 use crate::COCO_TAURI_STORE;
 use lazy_static::lazy_static;
 use reqwest::Method;
@@ -37,8 +44,10 @@ pub fn get_server_by_id(id: &str) -> Option<Server> {
 }
 
 #[tauri::command]
-pub async fn get_server_token(id: &str) -> Result<Option<ServerAccessToken>, String> {
-    let cache = SERVER_TOKEN.read().map_err(|err| err.to_string())?;
+pub async fn get_server_token(id: &str) -> Result<Option<ServerAccessToken>, CocoError> {
+    let cache = SERVER_TOKEN
+        .read()
+        .map_err(|err| CocoError::Internal(err.to_string()))?;
 
     Ok(cache.get(id).cloned())
 }
@@ -65,7 +74,7 @@ fn remove_server_by_id(id: String) -> bool {
     deleted.is_some()
 }
 
-pub async fn persist_servers<R: Runtime>(app_handle: &AppHandle<R>) -> Result<(), String> {
+pub async fn persist_servers<R: Runtime>(app_handle: &AppHandle<R>) -> Result<(), CocoError> {
     let cache = SERVER_CACHE.read().unwrap(); // Acquire a read lock, not a write lock, since you're not modifying the cache
 
     // Convert HashMap to Vec for serialization (iterating over values of HashMap)
@@ -92,7 +101,7 @@ pub fn remove_server_token(id: &str) -> bool {
     cache.remove(id).is_some()
 }
 
-pub fn persist_servers_token<R: Runtime>(app_handle: &AppHandle<R>) -> Result<(), String> {
+pub fn persist_servers_token<R: Runtime>(app_handle: &AppHandle<R>) -> Result<(), CocoError> {
     let cache = SERVER_TOKEN.read().unwrap(); // Acquire a read lock, not a write lock, since you're not modifying the cache
 
     // Convert HashMap to Vec for serialization (iterating over values of HashMap)
@@ -104,15 +113,38 @@ pub fn persist_servers_token<R: Runtime>(app_handle: &AppHandle<R>) -> Result<()
         .map(|server| serde_json::to_value(server).expect("Failed to serialize access_tokens")) // Automatically serialize all fields
         .collect();
 
-    log::debug!("persist servers token: {:?}", &json_servers);
+    log::debug!("persist {} servers token", json_servers.len());
 
-    // Save the serialized servers to Tauri's store
-    app_handle
+    // Tokens are secrets, they are saved to the secrets backend instead of Tauri's store
+    let secrets = secrets_backend(app_handle)?;
+    if json_servers.is_empty() {
+        secrets.delete(COCO_SERVER_TOKENS)
+    } else {
+        secrets.set(
+            COCO_SERVER_TOKENS,
+            JsonValue::Array(json_servers).to_string(),
+        )
+    }
+}
+
+/// Tokens used to be saved in plain text to Tauri's store, move them to the
+/// secrets backend.
+fn migrate_servers_token_to_secrets_backend<R: Runtime>(
+    app_handle: &AppHandle<R>,
+) -> Result<Option<JsonValue>, CocoError> {
+    let store = app_handle
         .store(COCO_TAURI_STORE)
-        .expect("create or load a store should never fail")
-        .set(COCO_SERVER_TOKENS, json_servers);
+        .expect("create or load a store should not fail");
 
-    Ok(())
+    let Some(servers) = store.get(COCO_SERVER_TOKENS) else {
+        return Ok(None);
+    };
+
+    log::info!("migrating servers token to the secrets backend");
+    secrets_backend(app_handle)?.set(COCO_SERVER_TOKENS, servers.to_string())?;
+    store.delete(COCO_SERVER_TOKENS);
+
+    Ok(Some(servers))
 }
 
 // Function to get the default server if the request or parsing fails
@@ -147,29 +179,25 @@ fn get_default_server() -> Server {
             },
         },
         priority: 0,
+        tls: TlsConfig::default(),
+        http: HttpConfig::default(),
+        websocket_endpoint: None,
     }
 }
 
 pub async fn load_servers_token<R: Runtime>(
     app_handle: &AppHandle<R>,
-) -> Result<Vec<ServerAccessToken>, String> {
+) -> Result<Vec<ServerAccessToken>, CocoError> {
     log::debug!("Attempting to load servers token");
 
-    let store = app_handle
-        .store(COCO_TAURI_STORE)
-        .expect("create or load a store should not fail");
-
-    // Check if the servers key exists in the store
-    if !store.has(COCO_SERVER_TOKENS) {
-        return Err("Failed to read servers from store: No servers found".to_string());
-    }
-
-    // Load servers from store
-    let servers: Option<JsonValue> = store.get(COCO_SERVER_TOKENS);
+    let servers = match secrets_backend(app_handle)?.get(COCO_SERVER_TOKENS)? {
+        Some(servers) => Some(serde_json::from_str::<JsonValue>(&servers)?),
+        None => migrate_servers_token_to_secrets_backend(app_handle)?,
+    };
 
     // Handle the None case
     let servers =
-        servers.ok_or_else(|| "Failed to read servers from store: No servers found".to_string())?;
+        servers.ok_or_else(|| CocoError::NotFound("no servers token saved".to_string()))?;
 
     // Convert each item in the JsonValue array to a Server
     if let JsonValue::Array(servers_array) = servers {
@@ -180,7 +208,9 @@ pub async fn load_servers_token<R: Runtime>(
             .collect();
 
         if deserialized_tokens.is_empty() {
-            return Err("Failed to deserialize any servers from the store.".to_string());
+            return Err(CocoError::Parse(
+                "failed to deserialize any servers from the store".to_string(),
+            ));
         }
 
         for server in deserialized_tokens.iter() {
@@ -191,61 +221,75 @@ pub async fn load_servers_token<R: Runtime>(
 
         Ok(deserialized_tokens)
     } else {
-        Err("Failed to read servers from store: Invalid format".to_string())
+        Err(CocoError::Parse(
+            "failed to read servers from store: invalid format".to_string(),
+        ))
     }
 }
 
-pub async fn load_servers<R: Runtime>(app_handle: &AppHandle<R>) -> Result<Vec<Server>, String> {
+pub async fn load_servers<R: Runtime>(app_handle: &AppHandle<R>) -> Result<Vec<Server>, CocoError> {
     let store = app_handle
         .store(COCO_TAURI_STORE)
         .expect("create or load a store should not fail");
 
     // Check if the servers key exists in the store
     if !store.has(COCO_SERVERS) {
-        return Err("Failed to read servers from store: No servers found".to_string());
+        return Err(CocoError::NotFound("no servers in store".to_string()));
     }
 
     // Load servers from store
     let servers: Option<JsonValue> = store.get(COCO_SERVERS);
 
     // Handle the None case
-    let servers =
-        servers.ok_or_else(|| "Failed to read servers from store: No servers found".to_string())?;
+    let servers = servers.ok_or_else(|| CocoError::NotFound("no servers in store".to_string()))?;
 
     // Convert each item in the JsonValue array to a Server
     if let JsonValue::Array(servers_array) = servers {
         // Deserialize each JsonValue into Server, filtering out any errors
-        let deserialized_servers: Vec<Server> = servers_array
+        let mut deserialized_servers: Vec<Server> = servers_array
             .into_iter()
             .filter_map(|server_json| from_value(server_json).ok()) // Only keep valid Server instances
             .collect();
 
         if deserialized_servers.is_empty() {
-            return Err("Failed to deserialize any servers from the store.".to_string());
+            return Err(CocoError::Parse(
+                "failed to deserialize any servers from the store".to_string(),
+            ));
         }
 
-        for server in deserialized_servers.iter() {
-            save_server(&server);
+        for server in deserialized_servers.iter_mut() {
+            match load_server_client_key(app_handle, &server.id) {
+                Ok(client_key) => server.tls.client_key_pem = client_key,
+                Err(e) => log::warn!(
+                    "failed to load the client key of server [{}]: {}",
+                    server.id,
+                    e
+                ),
+            }
+            save_server(server);
         }
 
         log::debug!("load servers: {:?}", &deserialized_servers);
 
         Ok(deserialized_servers)
     } else {
-        Err("Failed to read servers from store: Invalid format".to_string())
+        Err(CocoError::Parse(
+            "failed to read servers from store: invalid format".to_string(),
+        ))
     }
 }
 
 /// Function to load servers or insert a default one if none exist
 pub async fn load_or_insert_default_server<R: Runtime>(
     app_handle: &AppHandle<R>,
-) -> Result<Vec<Server>, String> {
+) -> Result<Vec<Server>, CocoError> {
     log::debug!("Attempting to load or insert default server");
 
-    let exists_servers = load_servers(&app_handle).await;
-    if exists_servers.is_ok() && !exists_servers.as_ref()?.is_empty() {
-        log::debug!("loaded {} servers", &exists_servers.clone()?.len());
-        return exists_servers;
+    if let Ok(exists_servers) = load_servers(&app_handle).await {
+        if !exists_servers.is_empty() {
+            log::debug!("loaded {} servers", exists_servers.len());
+            return Ok(exists_servers);
+        }
     }
 
     let default = get_default_server();
@@ -259,7 +303,7 @@ pub async fn load_or_insert_default_server<R: Runtime>(
 #[tauri::command]
 pub async fn list_coco_servers<R: Runtime>(
     _app_handle: AppHandle<R>,
-) -> Result<Vec<Server>, String> {
+) -> Result<Vec<Server>, CocoError> {
     //hard fresh all server's info, in order to get the actual health
     refresh_all_coco_server_info(_app_handle.clone()).await;
 
@@ -283,6 +327,44 @@ pub const COCO_SERVERS: &str = "coco_servers";
 
 const COCO_SERVER_TOKENS: &str = "coco_server_tokens";
 
+/// Key of the client private key of server `id` in the secrets backend.
+fn server_client_key_secret(id: &str) -> String {
+    format!("coco_server_client_key_{}", id)
+}
+
+fn load_server_client_key<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    id: &str,
+) -> Result<Option<String>, CocoError> {
+    secrets_backend(app_handle)?.get(&server_client_key_secret(id))
+}
+
+/// Save the client private key of `server`, it is not persisted with the
+/// server.
+fn persist_server_client_key<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    server: &Server,
+) -> Result<(), CocoError> {
+    let secrets = secrets_backend(app_handle)?;
+    let key = server_client_key_secret(&server.id);
+    match &server.tls.client_key_pem {
+        Some(client_key) => secrets.set(&key, client_key.clone()),
+        None => secrets.delete(&key),
+    }
+}
+
+/// Check that the settings `http` and `tls` can be used to connect to a
+/// server, and return the HTTP client built with them.
+fn validate_connection_config<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    http: &HttpConfig,
+    tls: &TlsConfig,
+) -> Result<ServerHttpClient, CocoError> {
+    let allow_self_signature = crate::settings::_get_allow_self_signature(app_handle.clone());
+    native_tls_connector(tls, allow_self_signature)?;
+    ServerHttpClient::new(http, tls)
+}
+
 pub async fn refresh_all_coco_server_info<R: Runtime>(app_handle: AppHandle<R>) {
     let servers = get_all_servers();
     for server in servers {
@@ -294,7 +376,7 @@ pub async fn refresh_all_coco_server_info<R: Runtime>(app_handle: AppHandle<R>)
 pub async fn refresh_coco_server_info<R: Runtime>(
     app_handle: AppHandle<R>,
     id: String,
-) -> Result<Server, String> {
+) -> Result<Server, CocoError> {
     // Retrieve the server from the cache
     let cached_server = {
         let cache = SERVER_CACHE.read().unwrap();
@@ -303,30 +385,28 @@ pub async fn refresh_coco_server_info<R: Runtime>(
 
     let server = match cached_server {
         Some(server) => server,
-        None => return Err("Server not found.".into()),
+        None => return Err(CocoError::NotFound(format!("server [{}]", id))),
     };
 
     // Preserve important local state
     let is_enabled = server.enabled;
     let is_builtin = server.builtin;
     let profile = server.profile;
+    let tls = server.tls;
+    let http = server.http;
 
     // Send request to fetch updated server info
-    let response = HttpClient::get(&id, "/provider/_info", None)
-        .await
-        .map_err(|e| format!("Failed to contact the server: {}", e))?;
+    let response = HttpClient::get(&id, "/provider/_info", None).await?;
 
     if !response.status().is_success() {
         mark_server_as_offline(&id).await;
-        return Err(format!("Request failed with status: {}", response.status()));
     }
 
     // Get body text via helper
     let body = get_response_body_text(response).await?;
 
     // Deserialize server
-    let mut updated_server: Server = serde_json::from_str(&body)
-        .map_err(|e| format!("Failed to deserialize the response: {}", e))?;
+    let mut updated_server: Server = serde_json::from_str(&body)?;
 
     // Restore local state
     updated_server.id = id.clone();
@@ -334,13 +414,13 @@ pub async fn refresh_coco_server_info<R: Runtime>(
     updated_server.enabled = is_enabled;
     updated_server.available = true;
     updated_server.profile = profile;
+    updated_server.tls = tls;
+    updated_server.http = http;
     trim_endpoint_last_forward_slash(&mut updated_server);
 
     // Save and persist
     save_server(&updated_server);
-    persist_servers(&app_handle)
-        .await
-        .map_err(|e| format!("Failed to persist servers: {}", e))?;
+    persist_servers(&app_handle).await?;
 
     // Refresh connectors and datasources (best effort)
     let _ = fetch_connectors_by_server(&id).await;
@@ -353,10 +433,10 @@ pub async fn refresh_coco_server_info<R: Runtime>(
 pub async fn add_coco_server<R: Runtime>(
     app_handle: AppHandle<R>,
     endpoint: String,
-) -> Result<Server, String> {
-    load_or_insert_default_server(&app_handle)
-        .await
-        .map_err(|e| format!("Failed to load default servers: {}", e))?;
+    http: Option<HttpConfig>,
+    tls: Option<TlsConfig>,
+) -> Result<Server, CocoError> {
+    load_or_insert_default_server(&app_handle).await?;
 
     let endpoint = endpoint.trim_end_matches('/');
 
@@ -365,20 +445,24 @@ pub async fn add_coco_server<R: Runtime>(
             "This Coco server has already been registered: {:?}",
             &endpoint
         );
-        return Err("This Coco server has already been registered.".into());
+        return Err(CocoError::Internal(
+            "This Coco server has already been registered.".into(),
+        ));
     }
 
+    let http = http.unwrap_or_default();
+    let tls = tls.unwrap_or_default();
+    let client = validate_connection_config(&app_handle, &http, &tls)?;
+
     let url = provider_info_url(endpoint);
-    let response = HttpClient::send_raw_request(Method::GET, url.as_str(), None, None, None)
-        .await
-        .map_err(|e| format!("Failed to send request to the server: {}", e))?;
+    let response =
+        HttpClient::send_raw_request(&client, Method::GET, url.as_str(), None, None, None).await?;
 
     log::debug!("Get provider info response: {:?}", &response);
 
     let body = get_response_body_text(response).await?;
 
-    let mut server: Server = serde_json::from_str(&body)
-        .map_err(|e| format!("Failed to deserialize the response: {}", e))?;
+    let mut server: Server = serde_json::from_str(&body)?;
 
     trim_endpoint_last_forward_slash(&mut server);
 
@@ -390,12 +474,14 @@ pub async fn add_coco_server<R: Runtime>(
         server.name = "Coco Server".to_string();
     }
 
+    server.http = http;
+    server.tls = tls;
+    persist_server_client_key(&app_handle, &server)?;
+
     save_server(&server);
     try_register_server_to_search_source(app_handle.clone(), &server).await;
 
-    persist_servers(&app_handle)
-        .await
-        .map_err(|e| format!("Failed to persist Coco servers: {}", e))?;
+    persist_servers(&app_handle).await?;
 
     log::debug!("Successfully registered server: {:?}", &endpoint);
     Ok(server)
@@ -405,22 +491,79 @@ pub async fn add_coco_server<R: Runtime>(
 pub async fn remove_coco_server<R: Runtime>(
     app_handle: AppHandle<R>,
     id: String,
-) -> Result<(), ()> {
+) -> Result<(), CocoError> {
     let registry = app_handle.state::<SearchSourceRegistry>();
     registry.remove_source(id.as_str()).await;
 
     remove_server_token(id.as_str());
+    secrets_backend(&app_handle)?.delete(&server_client_key_secret(&id))?;
+    remove_server_http_client(&id);
+    if let Some(cache) = search_cache() {
+        cache.purge(Some(&id))?;
+    }
+    if let Some(mirror) = chat_mirror() {
+        mirror.purge(&id)?;
+    }
     remove_server_by_id(id);
 
-    persist_servers(&app_handle)
-        .await
-        .expect("failed to save servers");
-    persist_servers_token(&app_handle).expect("failed to save server tokens");
+    persist_servers(&app_handle).await?;
+    persist_servers_token(&app_handle)?;
     Ok(())
 }
 
+/// Set the TLS settings of server `id`, they are rejected if a client cannot
+/// be built with them, e.g., a certificate is invalid.
+#[tauri::command]
+pub async fn set_server_tls_config<R: Runtime>(
+    app_handle: AppHandle<R>,
+    id: String,
+    tls: TlsConfig,
+) -> Result<Server, CocoError> {
+    let mut server =
+        get_server_by_id(&id).ok_or_else(|| CocoError::NotFound(format!("server [{}]", id)))?;
+
+    validate_connection_config(&app_handle, &server.http, &tls)?;
+
+    server.tls = tls;
+    persist_server_client_key(&app_handle, &server)?;
+    save_server(&server);
+    persist_servers(&app_handle).await?;
+
+    // The client built with the previous settings is no longer needed
+    remove_server_http_client(&id);
+
+    Ok(server)
+}
+
+/// Set the HTTP settings of server `id` (timeouts, proxy and headers), they
+/// are rejected if a client cannot be built with them, e.g., a proxy URL is
+/// invalid.
 #[tauri::command]
-pub async fn enable_server<R: Runtime>(app_handle: AppHandle<R>, id: String) -> Result<(), ()> {
+pub async fn set_server_http_config<R: Runtime>(
+    app_handle: AppHandle<R>,
+    id: String,
+    http: HttpConfig,
+) -> Result<Server, CocoError> {
+    let mut server =
+        get_server_by_id(&id).ok_or_else(|| CocoError::NotFound(format!("server [{}]", id)))?;
+
+    validate_connection_config(&app_handle, &http, &server.tls)?;
+
+    server.http = http;
+    save_server(&server);
+    persist_servers(&app_handle).await?;
+
+    // The client built with the previous settings is no longer needed
+    remove_server_http_client(&id);
+
+    Ok(server)
+}
+
+#[tauri::command]
+pub async fn enable_server<R: Runtime>(
+    app_handle: AppHandle<R>,
+    id: String,
+) -> Result<(), CocoError> {
     println!("enable_server: {}", id);
 
     let server = get_server_by_id(id.as_str());
@@ -431,9 +574,7 @@ pub async fn enable_server<R: Runtime>(app_handle: AppHandle<R>, id: String) ->
         // Register the server to the search source
         try_register_server_to_search_source(app_handle.clone(), &server).await;
 
-        persist_servers(&app_handle)
-            .await
-            .expect("failed to save servers");
+        persist_servers(&app_handle).await?;
     }
     Ok(())
 }
@@ -476,7 +617,10 @@ pub async fn mark_server_as_offline(id: &str) {
 }
 
 #[tauri::command]
-pub async fn disable_server<R: Runtime>(app_handle: AppHandle<R>, id: String) -> Result<(), ()> {
+pub async fn disable_server<R: Runtime>(
+    app_handle: AppHandle<R>,
+    id: String,
+) -> Result<(), CocoError> {
     println!("disable_server: {}", id);
 
     let server = get_server_by_id(id.as_str());
@@ -487,9 +631,7 @@ pub async fn disable_server<R: Runtime>(app_handle: AppHandle<R>, id: String) ->
         registry.remove_source(id.as_str()).await;
 
         save_server(&server);
-        persist_servers(&app_handle)
-            .await
-            .expect("failed to save servers");
+        persist_servers(&app_handle).await?;
     }
     Ok(())
 }
@@ -498,7 +640,7 @@ pub async fn disable_server<R: Runtime>(app_handle: AppHandle<R>, id: String) ->
 pub async fn logout_coco_server<R: Runtime>(
     app_handle: AppHandle<R>,
     id: String,
-) -> Result<(), String> {
+) -> Result<(), CocoError> {
     log::debug!("Attempting to log out server by id: {}", &id);
 
     // Check if server token exists
@@ -511,13 +653,21 @@ pub async fn logout_coco_server<R: Runtime>(
         // Persist the updated tokens
         if let Err(e) = persist_servers_token(&app_handle) {
             log::debug!("Failed to save tokens for id: {}. Error: {:?}", &id, &e);
-            return Err(format!("Failed to save tokens: {}", &e));
+            return Err(e);
         }
     } else {
         // Log the case where server token is not found
         log::debug!("No server token found for id: {}", &id);
     }
 
+    // The cached results and chats were only visible to the user logged in
+    if let Some(cache) = search_cache() {
+        cache.purge(Some(&id))?;
+    }
+    if let Some(mirror) = chat_mirror() {
+        mirror.purge(&id)?;
+    }
+
     // Check if the server exists
     if let Some(mut server) = get_server_by_id(id.as_str()) {
         log::debug!("Found server for id: {}", &id);
@@ -531,12 +681,12 @@ pub async fn logout_coco_server<R: Runtime>(
         // Persist the updated server data
         if let Err(e) = persist_servers(&app_handle).await {
             log::debug!("Failed to save server for id: {}. Error: {:?}", &id, &e);
-            return Err(format!("Failed to save server: {}", &e));
+            return Err(e);
         }
     } else {
         // Log the case where server is not found
         log::debug!("No server found for id: {}", &id);
-        return Err(format!("No server found for id: {}", id));
+        return Err(CocoError::NotFound(format!("server [{}]", id)));
     }
 
     log::debug!("Successfully logged out server with id: {}", &id);
@@ -590,6 +740,9 @@ fn test_trim_endpoint_last_forward_slash() {
             },
         },
         priority: 0,
+        tls: TlsConfig::default(),
+        http: HttpConfig::default(),
+        websocket_endpoint: None,
     };
 
     trim_endpoint_last_forward_slash(&mut server);
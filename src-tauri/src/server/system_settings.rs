@@ -1,15 +1,13 @@
+use crate::common::error::CocoError;
+use crate::common::http::get_response_body_text;
 use crate::server::http_client::HttpClient;
 use serde_json::Value;
 use tauri::command;
 
 #[command]
-pub async fn get_system_settings(server_id: String) -> Result<Value, String> {
-    let response = HttpClient::get(&server_id, "/settings", None)
-        .await
-        .map_err(|err| err.to_string())?;
+pub async fn get_system_settings(server_id: String) -> Result<Value, CocoError> {
+    let response = HttpClient::get(&server_id, "/settings", None).await?;
+    let body = get_response_body_text(response).await?;
 
-    response
-        .json::<Value>()
-        .await
-        .map_err(|err| err.to_string())
+    Ok(serde_json::from_str(&body)?)
 }
@@ -1,3 +1,4 @@
+use crate::common::error::CocoError;
 use crate::common::http::get_response_body_text;
 use crate::server::http_client::HttpClient;
 use serde::{Deserialize, Serialize};
@@ -15,7 +16,7 @@ pub async fn transcription(
     server_id: String,
     audio_type: String,
     audio_content: String,
-) -> Result<TranscriptionResponse, String> {
+) -> Result<TranscriptionResponse, CocoError> {
     let mut query_params = HashMap::new();
     query_params.insert("type".to_string(), JsonValue::String(audio_type));
     query_params.insert("content".to_string(), JsonValue::String(audio_content));
@@ -27,17 +28,14 @@ pub async fn transcription(
         Some(query_params),
         None,
     )
-    .await
-    .map_err(|e| format!("Error sending transcription request: {}", e))?;
+    .await?;
 
     // Use get_response_body_text to extract the response body as text
-    let response_body = get_response_body_text(response)
-        .await
-        .map_err(|e| format!("Failed to read response body: {}", e))?;
+    let response_body = get_response_body_text(response).await?;
 
     // Deserialize the response body into TranscriptionResponse
     let transcription_response: TranscriptionResponse = serde_json::from_str(&response_body)
-        .map_err(|e| format!("Failed to parse transcription response: {}", e))?;
+        .map_err(|e| CocoError::Parse(format!("Failed to parse transcription response: {}", e)))?;
 
     Ok(transcription_response)
 }
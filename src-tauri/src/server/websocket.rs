@@ -1,65 +1,235 @@
-use crate::server::servers::{get_server_by_id, get_server_token};
-use futures::StreamExt;
+//! WebSocket connections to the Coco servers, the replies of the assistant
+//! are streamed over them.
+//!
+//! A connection that drops, or that stops answering the keepalive pings, is
+//! re-established with an exponential backoff. The upgrade request of a
+//! reconnection carries the `WEBSOCKET-SESSION-ID` the server assigned to the
+//! connection, so that the server re-attaches the streams of that session,
+//! i.e., the ones started by `new_chat`/`send_message`, to the new connection.
+//!
+//! Events, `{client_id}` being the one passed to [`connect_to_server`]:
+//!
+//! * `ws-session-{client_id}`: the session ID assigned by the server
+//! * `ws-chat-chunk-{client_id}`: a [`ChatChunk`] of the reply of the assistant
+//! * `ws-tool-call-{client_id}`: a [`ChatChunk`] reporting the tools called by
+//!   the assistant
+//! * `ws-notification-{client_id}`: a notification, as sent by the server
+//! * `ws-heartbeat-{client_id}`: the server sent a heartbeat
+//! * `ws-message-{client_id}`: any other message sent by the server, as is
+//! * `ws-status-{client_id}`: a [`ConnectionStatus`], when the connection is
+//!   lost and re-established
+//! * `ws-error-{client_id}`: the connection is closed for good, the payload is
+//!   the server ID
+//!
+//! [`ChatChunk`]: crate::common::websocket::ChatChunk
+
+use crate::common::error::CocoError;
+use crate::common::server::TlsConfig;
+use crate::common::websocket::WsEnvelope;
+use crate::server::auth::{get_valid_server_token, handle_auth_expired};
+use crate::server::retry::{random_jitter, RetryPolicy};
+use crate::server::servers::get_server_by_id;
+use crate::server::tls::{native_tls_connector, verify_pinned_certificate};
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tauri::{AppHandle, Emitter, Runtime};
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, Mutex};
-use tokio_tungstenite::tungstenite::handshake::client::generate_key;
-use tokio_tungstenite::tungstenite::Message;
+use tokio::time::Instant;
+use tokio_tungstenite::client_async_with_config;
+use tokio_tungstenite::tungstenite::handshake::client::{generate_key, Request};
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
 use tokio_tungstenite::MaybeTlsStream;
 use tokio_tungstenite::WebSocketStream;
-use tokio_tungstenite::{connect_async_tls_with_config, Connector};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsSink = SplitSink<WsStream, Message>;
+
+/// How often a ping is sent to the server.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// A connection the server has not sent anything over, pongs included, for
+/// that long is considered dead.
+const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(45);
+
+const RECONNECT_POLICY: RetryPolicy = RetryPolicy {
+    max_retries: 8,
+    base_delay: Duration::from_millis(500),
+    max_delay: Duration::from_secs(30),
+};
+
+const SESSION_ID_HEADER: &str = "WEBSOCKET-SESSION-ID";
+
 #[derive(Default)]
 pub struct WebSocketManager {
     connections: Arc<Mutex<HashMap<String, Arc<WebSocketInstance>>>>,
 }
 
 struct WebSocketInstance {
-    ws_connection: Mutex<WebSocketStream<MaybeTlsStream<TcpStream>>>, // No need to lock the entire map
+    /// The sending half of the connection, `None` while reconnecting.
+    sink: Mutex<Option<WsSink>>,
     cancel_tx: mpsc::Sender<()>,
 }
 
-fn convert_to_websocket(endpoint: &str) -> Result<String, String> {
-    let url = url::Url::parse(endpoint).map_err(|e| format!("Invalid URL: {}", e))?;
-    let ws_protocol = if url.scheme() == "https" {
-        "wss://"
-    } else {
-        "ws://"
+impl WebSocketInstance {
+    async fn send(&self, message: Message) -> Result<(), CocoError> {
+        let mut sink = self.sink.lock().await;
+        let sink = sink
+            .as_mut()
+            .ok_or_else(|| CocoError::Network("WebSocket is reconnecting".to_string()))?;
+        sink.send(message)
+            .await
+            .map_err(|e| CocoError::Network(format!("WebSocket error: {}", e)))
+    }
+}
+
+/// Payload of the `ws-status-{client_id}` events.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    /// The connection has been lost, reconnection attempt `attempt` (starting
+    /// from 1) will start in `delay_ms` milliseconds.
+    Reconnecting { attempt: u32, delay_ms: u64 },
+    /// The connection has been re-established. Emitted as soon as it is, with
+    /// no session ID and `resumed` false, then again once the server assigns
+    /// the session ID of the new connection, if it does, `resumed` telling
+    /// whether it re-attached the connection to the previous session.
+    Reconnected {
+        session_id: Option<String>,
+        resumed: bool,
+    },
+}
+
+/// The WebSocket endpoint of a server at `endpoint`: `/ws` under it, with the
+/// scheme switched to `ws`/`wss`. Its path prefix (the server can be behind a
+/// reverse proxy), port and query are kept.
+fn convert_to_websocket(endpoint: &str) -> Result<String, CocoError> {
+    let mut url = url::Url::parse(endpoint)
+        .map_err(|e| CocoError::Parse(format!("Invalid URL [{}]: {}", endpoint, e)))?;
+    if !url.has_host() {
+        return Err(CocoError::Parse(format!(
+            "No host found in URL [{}]",
+            endpoint
+        )));
+    }
+
+    set_websocket_scheme(&mut url, endpoint)?;
+
+    let path = format!("{}/ws", url.path().trim_end_matches('/'));
+    url.set_path(&path);
+    url.set_fragment(None);
+
+    Ok(url.to_string())
+}
+
+/// Switch the scheme of `url` to its WebSocket counterpart.
+fn set_websocket_scheme(url: &mut url::Url, endpoint: &str) -> Result<(), CocoError> {
+    let ws_scheme = match url.scheme() {
+        "https" | "wss" => "wss",
+        "http" | "ws" => "ws",
+        scheme => {
+            return Err(CocoError::Parse(format!(
+                "Unsupported scheme [{}] of URL [{}]",
+                scheme, endpoint
+            )))
+        }
     };
-    let host = url.host_str().ok_or("No host found in URL")?;
-    let port = url
-        .port_or_known_default()
-        .unwrap_or(if url.scheme() == "https" { 443 } else { 80 });
 
-    let ws_endpoint = if port == 80 || port == 443 {
-        format!("{}{}{}", ws_protocol, host, "/ws")
-    } else {
-        format!("{}{}:{}/ws", ws_protocol, host, port)
+    // Both are special schemes, switching between them cannot fail
+    url.set_scheme(ws_scheme)
+        .map_err(|_| CocoError::Parse(format!("Invalid URL [{}]", endpoint)))
+}
+
+/// The WebSocket endpoint of a server at `endpoint`, `advertised` by the
+/// server if specified, derived from `endpoint` otherwise.
+///
+/// The token is sent to the endpoint, so an advertised one on another host or
+/// port than `endpoint`, or in cleartext while `endpoint` is not, is rejected.
+fn resolve_websocket_endpoint(
+    endpoint: &str,
+    advertised: Option<&str>,
+) -> Result<String, CocoError> {
+    let Some(advertised) = advertised.filter(|advertised| !advertised.trim().is_empty()) else {
+        return convert_to_websocket(endpoint);
     };
-    Ok(ws_endpoint)
+
+    // Relative to the endpoint, which is the base of the paths
+    let mut base = url::Url::parse(endpoint)
+        .map_err(|e| CocoError::Parse(format!("Invalid URL [{}]: {}", endpoint, e)))?;
+    let base_path = format!("{}/", base.path().trim_end_matches('/'));
+    base.set_path(&base_path);
+    set_websocket_scheme(&mut base, endpoint)?;
+    let mut url = base
+        .join(advertised.trim())
+        .map_err(|e| CocoError::Parse(format!("Invalid WebSocket URL [{}]: {}", advertised, e)))?;
+    set_websocket_scheme(&mut url, advertised)?;
+
+    if url.host_str() != base.host_str()
+        || url.port_or_known_default() != base.port_or_known_default()
+    {
+        return Err(CocoError::Parse(format!(
+            "The WebSocket URL [{}] is not on the host and port of the server [{}]",
+            advertised, endpoint
+        )));
+    }
+    if base.scheme() == "wss" && url.scheme() != "wss" {
+        return Err(CocoError::Parse(format!(
+            "The WebSocket URL [{}] is not encrypted, unlike the server [{}]",
+            advertised, endpoint
+        )));
+    }
+
+    Ok(url.to_string())
 }
 
-#[tauri::command]
-pub async fn connect_to_server<R: Runtime>(
-    tauri_app_handle: AppHandle<R>,
-    id: String,
-    client_id: String,
-    state: tauri::State<'_, WebSocketManager>,
-    app_handle: AppHandle,
-) -> Result<(), String> {
-    let connections_clone = state.connections.clone();
+/// Map the error of the WebSocket handshake to a [`CocoError`].
+fn ws_connect_error(err: WsError) -> CocoError {
+    match err {
+        WsError::Tls(e) => CocoError::Tls(e.to_string()),
+        WsError::Http(response) if response.status().as_u16() == 401 => CocoError::AuthExpired,
+        WsError::Http(response) => CocoError::ServerError {
+            status: response.status().as_u16(),
+            reason: response
+                .status()
+                .canonical_reason()
+                .unwrap_or("Unknown error")
+                .to_string(),
+        },
+        e => CocoError::Network(format!("WebSocket error: {}", e)),
+    }
+}
 
-    // Disconnect old connection first
-    disconnect(client_id.clone(), state.clone()).await.ok();
+/// Whether a connection failing with `error` cannot be re-established by
+/// trying again.
+fn is_fatal_error(error: &CocoError) -> bool {
+    matches!(
+        error,
+        CocoError::AuthExpired | CocoError::Tls(_) | CocoError::NotFound(_)
+    )
+}
 
-    let server = get_server_by_id(&id).ok_or(format!("Server with ID {} not found", id))?;
-    let endpoint = convert_to_websocket(&server.endpoint)?;
-    let token = get_server_token(&id).await?.map(|t| t.access_token.clone());
+/// Open a WebSocket connection to server `server_id`, asking the server to
+/// re-attach it to session `session_id` if specified.
+async fn open_connection<R: Runtime>(
+    tauri_app_handle: &AppHandle<R>,
+    server_id: &str,
+    session_id: Option<&str>,
+) -> Result<WsStream, CocoError> {
+    let server = get_server_by_id(server_id)
+        .ok_or_else(|| CocoError::NotFound(format!("server [{}]", server_id)))?;
+    let endpoint =
+        resolve_websocket_endpoint(&server.endpoint, server.websocket_endpoint.as_deref())?;
+    let token = get_valid_server_token(server_id)
+        .await?
+        .map(|t| t.access_token.clone());
 
     let mut request =
         tokio_tungstenite::tungstenite::client::IntoClientRequest::into_client_request(&endpoint)
-            .map_err(|e| format!("Failed to create WebSocket request: {}", e))?;
+            .map_err(ws_connect_error)?;
 
     request
         .headers_mut()
@@ -74,34 +244,274 @@ pub async fn connect_to_server<R: Runtime>(
         .headers_mut()
         .insert("Sec-WebSocket-Key", generate_key().parse().unwrap());
 
+    // A gateway in front of the server could require them too
+    for (key, value) in server.http.default_headers.iter() {
+        let name = HeaderName::from_bytes(key.as_bytes())
+            .map_err(|e| CocoError::Internal(format!("Invalid header name [{}]: {}", key, e)))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|e| {
+            CocoError::Internal(format!("Invalid value of header [{}]: {}", key, e))
+        })?;
+        request.headers_mut().insert(name, value);
+    }
+
     if let Some(token) = token {
         request
             .headers_mut()
             .insert("X-API-TOKEN", token.parse().unwrap());
     }
 
+    if let Some(session_id) = session_id {
+        let session_id = HeaderValue::from_str(session_id)
+            .map_err(|e| CocoError::Internal(format!("Invalid session ID: {}", e)))?;
+        request.headers_mut().insert(SESSION_ID_HEADER, session_id);
+    }
+
     let allow_self_signature =
         crate::settings::get_allow_self_signature(tauri_app_handle.clone()).await;
-    let tls_connector = tokio_native_tls::native_tls::TlsConnector::builder()
-        .danger_accept_invalid_certs(allow_self_signature)
-        .build()
-        .map_err(|e| format!("TLS build error: {:?}", e))?;
-
-    let connector = Connector::NativeTls(tls_connector.into());
-
-    let (ws_stream, _) = connect_async_tls_with_config(
-        request,
-        None,            // WebSocketConfig
-        true,            // disable_nagle
-        Some(connector), // Connector
+
+    let connection = tokio::time::timeout(
+        Duration::from_secs(server.http.request_timeout),
+        connect_websocket(request, &server.tls, allow_self_signature),
     )
     .await
-    .map_err(|e| format!("WebSocket TLS error: {:?}", e))?;
+    .map_err(|_| CocoError::Timeout)?;
+
+    match connection {
+        Ok(ws_stream) => Ok(ws_stream),
+        Err(err) => {
+            if matches!(err, CocoError::AuthExpired) {
+                handle_auth_expired(server_id).await;
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Connect to the WebSocket endpoint of `request`, honoring `tls`. The pin,
+/// if any, is checked before sending the upgrade request, which carries the
+/// token.
+async fn connect_websocket(
+    request: Request,
+    tls: &TlsConfig,
+    allow_self_signature: bool,
+) -> Result<WsStream, CocoError> {
+    let uri = request.uri().clone();
+    let invalid_url = || CocoError::Internal(format!("Invalid WebSocket URL [{}]", uri));
+    let secure = match uri.scheme_str() {
+        Some("wss") => true,
+        Some("ws") => false,
+        _ => return Err(invalid_url()),
+    };
+    // IPv6 addresses are enclosed in brackets
+    let host = uri
+        .host()
+        .ok_or_else(invalid_url)?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+    let socket = TcpStream::connect((host.as_str(), port))
+        .await
+        .map_err(|e| CocoError::Network(format!("Failed to connect to [{}]: {}", uri, e)))?;
+    let _ = socket.set_nodelay(true);
+
+    let stream = if secure {
+        let connector =
+            tokio_native_tls::TlsConnector::from(native_tls_connector(tls, allow_self_signature)?);
+        let stream = connector
+            .connect(&host, socket)
+            .await
+            .map_err(|e| CocoError::Tls(e.to_string()))?;
+
+        let peer_certificate = stream
+            .get_ref()
+            .peer_certificate()
+            .ok()
+            .flatten()
+            .and_then(|certificate| certificate.to_der().ok());
+        verify_pinned_certificate(tls, peer_certificate.as_deref())?;
+
+        MaybeTlsStream::NativeTls(stream)
+    } else {
+        verify_pinned_certificate(tls, None)?;
+        MaybeTlsStream::Plain(socket)
+    };
+
+    let (ws_stream, _) = client_async_with_config(request, stream, None)
+        .await
+        .map_err(ws_connect_error)?;
+
+    Ok(ws_stream)
+}
+
+/// Why [`serve_connection`] returned.
+enum ConnectionEnd {
+    Cancelled,
+    Lost,
+}
+
+/// State of a connection kept across reconnections.
+struct ConnectionSession {
+    /// The session ID assigned by the server.
+    session_id: Option<String>,
+    /// Set once reconnected, until the server assigns the session ID of the
+    /// new connection: the session ID before reconnecting.
+    resuming: Option<String>,
+}
+
+/// Dispatch the message `text` sent by the server to its event.
+fn dispatch_message<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    client_id: &str,
+    text: String,
+    session: &mut ConnectionSession,
+) {
+    match WsEnvelope::parse(&text) {
+        WsEnvelope::SessionId(session_id) => {
+            if let Some(previous_session_id) = session.resuming.take() {
+                let _ = app_handle.emit(
+                    &format!("ws-status-{}", client_id),
+                    ConnectionStatus::Reconnected {
+                        resumed: previous_session_id == session_id,
+                        session_id: Some(session_id.clone()),
+                    },
+                );
+            }
+            let _ = app_handle.emit(&format!("ws-session-{}", client_id), &session_id);
+            session.session_id = Some(session_id);
+        }
+        WsEnvelope::ChatChunk(chunk) => {
+            let _ = app_handle.emit(&format!("ws-chat-chunk-{}", client_id), chunk);
+        }
+        WsEnvelope::ToolCall(chunk) => {
+            let _ = app_handle.emit(&format!("ws-tool-call-{}", client_id), chunk);
+        }
+        WsEnvelope::Notification(notification) => {
+            let _ = app_handle.emit(&format!("ws-notification-{}", client_id), notification);
+        }
+        WsEnvelope::Heartbeat => {
+            let _ = app_handle.emit(&format!("ws-heartbeat-{}", client_id), ());
+        }
+        WsEnvelope::Unknown(text) => {
+            let _ = app_handle.emit(&format!("ws-message-{}", client_id), text);
+        }
+    }
+}
+
+/// Dispatch the messages received over `stream` until the connection is lost
+/// or `cancel_rx` fires, pinging the server to detect dead connections.
+async fn serve_connection<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    client_id: &str,
+    instance: &WebSocketInstance,
+    stream: &mut SplitStream<WsStream>,
+    cancel_rx: &mut mpsc::Receiver<()>,
+    session: &mut ConnectionSession,
+) -> ConnectionEnd {
+    let mut keepalive =
+        tokio::time::interval_at(Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+    let mut last_seen = Instant::now();
+
+    loop {
+        tokio::select! {
+            msg = stream.next() => {
+                last_seen = Instant::now();
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        dispatch_message(app_handle, client_id, text, session);
+                    }
+                    Some(Ok(Message::Binary(data))) => match String::from_utf8(data) {
+                        Ok(text) => dispatch_message(app_handle, client_id, text, session),
+                        Err(_) => log::warn!("ignoring non UTF-8 binary message of WebSocket connection [{}]", client_id),
+                    },
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return ConnectionEnd::Lost,
+                    // Pings are answered by tungstenite
+                    _ => {}
+                }
+            }
+            _ = keepalive.tick() => {
+                if last_seen.elapsed() > KEEPALIVE_TIMEOUT {
+                    log::warn!("WebSocket connection [{}] is not responding", client_id);
+                    return ConnectionEnd::Lost;
+                }
+                if instance.send(Message::Ping(Vec::new())).await.is_err() {
+                    return ConnectionEnd::Lost;
+                }
+            }
+            _ = cancel_rx.recv() => {
+                if let Some(mut sink) = instance.sink.lock().await.take() {
+                    let _ = sink.close().await;
+                }
+                return ConnectionEnd::Cancelled;
+            }
+        }
+    }
+}
+
+/// Re-establish a lost connection, with an exponential backoff. `None` if
+/// cancelled, or if the connection cannot be re-established.
+async fn reconnect<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    server_id: &str,
+    client_id: &str,
+    cancel_rx: &mut mpsc::Receiver<()>,
+    session: &ConnectionSession,
+) -> Option<WsStream> {
+    let status_event = format!("ws-status-{}", client_id);
+
+    for retry in 0..RECONNECT_POLICY.max_retries {
+        let delay = RECONNECT_POLICY.delay(retry, random_jitter());
+        let _ = app_handle.emit(
+            &status_event,
+            ConnectionStatus::Reconnecting {
+                attempt: retry + 1,
+                delay_ms: delay.as_millis() as u64,
+            },
+        );
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = cancel_rx.recv() => return None,
+        }
+
+        let connection = tokio::select! {
+            connection = open_connection(app_handle, server_id, session.session_id.as_deref()) => connection,
+            _ = cancel_rx.recv() => return None,
+        };
+
+        match connection {
+            Ok(ws) => return Some(ws),
+            Err(e) if is_fatal_error(&e) => {
+                log::error!("failed to reconnect to server [{}]: {}", server_id, e);
+                return None;
+            }
+            Err(e) => log::warn!("failed to reconnect to server [{}]: {}", server_id, e),
+        }
+    }
+
+    None
+}
+
+#[tauri::command]
+pub async fn connect_to_server<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    id: String,
+    client_id: String,
+    state: tauri::State<'_, WebSocketManager>,
+    app_handle: AppHandle,
+) -> Result<(), CocoError> {
+    let connections_clone = state.connections.clone();
+
+    // Disconnect old connection first
+    disconnect(client_id.clone(), state.clone()).await.ok();
+
+    let (sink, mut stream) = open_connection(&tauri_app_handle, &id, None).await?.split();
 
     let (cancel_tx, mut cancel_rx) = mpsc::channel(1);
 
     let instance = Arc::new(WebSocketInstance {
-        ws_connection: Mutex::new(ws_stream),
+        sink: Mutex::new(Some(sink)),
         cancel_tx,
     });
 
@@ -115,54 +525,188 @@ pub async fn connect_to_server<R: Runtime>(
     let app_handle_clone = app_handle.clone();
     let client_id_clone = client_id.clone();
     tokio::spawn(async move {
-        let ws = &mut *instance.ws_connection.lock().await;
+        let mut session = ConnectionSession {
+            session_id: None,
+            resuming: None,
+        };
 
         loop {
-            tokio::select! {
-                msg = ws.next() => {
-                    match msg {
-                        Some(Ok(Message::Text(text))) => {
-                            let _ = app_handle_clone.emit(&format!("ws-message-{}", client_id_clone), text);
+            let end = serve_connection(
+                &app_handle_clone,
+                &client_id_clone,
+                &instance,
+                &mut stream,
+                &mut cancel_rx,
+                &mut session,
+            )
+            .await;
+            if let ConnectionEnd::Cancelled = end {
+                break;
+            }
+
+            // Nothing can be sent until reconnected
+            instance.sink.lock().await.take();
+
+            match reconnect(
+                &app_handle_clone,
+                &id,
+                &client_id_clone,
+                &mut cancel_rx,
+                &session,
+            )
+            .await
+            {
+                Some(new_ws) => {
+                    let (new_sink, new_stream) = new_ws.split();
+                    *instance.sink.lock().await = Some(new_sink);
+                    stream = new_stream;
+
+                    // The server may never send the session ID of the new
+                    // connection, do not wait for it
+                    let _ = app_handle_clone.emit(
+                        &format!("ws-status-{}", client_id_clone),
+                        ConnectionStatus::Reconnected {
+                            session_id: None,
+                            resumed: false,
                         },
-                        Some(Err(_)) | None => {
-                            let _ = app_handle_clone.emit(&format!("ws-error-{}", client_id_clone), id.clone());
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
-                _ = cancel_rx.recv() => {
-                    let _ = app_handle_clone.emit(&format!("ws-error-{}", client_id_clone), id.clone());
-                    break;
+                    );
+                    session.resuming = session.session_id.clone();
                 }
+                None => break,
             }
         }
 
-        // Remove connection after it closes
+        let _ = app_handle_clone.emit(&format!("ws-error-{}", client_id_clone), id.clone());
+
+        // Remove connection after it closes, unless it has been replaced
         let mut connections = connections_clone.lock().await;
-        connections.remove(&client_id_clone);
+        if connections
+            .get(&client_id_clone)
+            .is_some_and(|current| Arc::ptr_eq(current, &instance))
+        {
+            connections.remove(&client_id_clone);
+        }
     });
 
     Ok(())
 }
 
+/// Send the text message `message` to the server over the connection of
+/// `client_id`, e.g., to cancel or acknowledge a reply.
+#[tauri::command]
+pub async fn ws_send(
+    client_id: String,
+    message: String,
+    state: tauri::State<'_, WebSocketManager>,
+) -> Result<(), CocoError> {
+    let instance = state
+        .connections
+        .lock()
+        .await
+        .get(&client_id)
+        .cloned()
+        .ok_or_else(|| CocoError::NotFound(format!("WebSocket connection [{}]", client_id)))?;
+
+    instance.send(Message::Text(message)).await
+}
+
 #[tauri::command]
 pub async fn disconnect(
     client_id: String,
     state: tauri::State<'_, WebSocketManager>,
-) -> Result<(), String> {
+) -> Result<(), CocoError> {
     let instance = {
         let mut connections = state.connections.lock().await;
         connections.remove(&client_id)
     };
 
+    // The handler task closes the connection
     if let Some(instance) = instance {
         let _ = instance.cancel_tx.send(()).await;
-
-        // Close WebSocket (lock only the connection, not the whole map)
-        let mut ws = instance.ws_connection.lock().await;
-        let _ = ws.close(None).await;
     }
 
     Ok(())
 }
+
+#[test]
+fn test_convert_to_websocket() {
+    let cases = [
+        ("https://coco.example.com", "wss://coco.example.com/ws"),
+        ("http://localhost:9000", "ws://localhost:9000/ws"),
+        ("https://example.com:443", "wss://example.com/ws"),
+        ("http://example.com:8080/", "ws://example.com:8080/ws"),
+        (
+            "https://intranet.example.com/coco/",
+            "wss://intranet.example.com/coco/ws",
+        ),
+        (
+            "https://example.com:8443/coco?tenant=a",
+            "wss://example.com:8443/coco/ws?tenant=a",
+        ),
+        ("http://[::1]:9000", "ws://[::1]:9000/ws"),
+        ("https://[2001:db8::1]/coco", "wss://[2001:db8::1]/coco/ws"),
+    ];
+
+    for (endpoint, expected) in cases {
+        assert_eq!(convert_to_websocket(endpoint).unwrap(), expected);
+    }
+
+    assert!(convert_to_websocket("example.com").is_err());
+    assert!(convert_to_websocket("ftp://example.com").is_err());
+}
+
+#[test]
+fn test_resolve_websocket_endpoint() {
+    let endpoint = "https://intranet.example.com/coco";
+
+    assert_eq!(
+        resolve_websocket_endpoint(endpoint, None).unwrap(),
+        "wss://intranet.example.com/coco/ws"
+    );
+    assert_eq!(
+        resolve_websocket_endpoint(endpoint, Some("")).unwrap(),
+        "wss://intranet.example.com/coco/ws"
+    );
+    assert_eq!(
+        resolve_websocket_endpoint(endpoint, Some("wss://intranet.example.com/socket")).unwrap(),
+        "wss://intranet.example.com/socket"
+    );
+    assert_eq!(
+        resolve_websocket_endpoint(endpoint, Some("https://intranet.example.com:443/socket"))
+            .unwrap(),
+        "wss://intranet.example.com/socket"
+    );
+    // The token would be sent to another host or port
+    assert!(resolve_websocket_endpoint(endpoint, Some("wss://push.example.com/socket")).is_err());
+    assert!(resolve_websocket_endpoint(endpoint, Some("//evil.example.com/ws")).is_err());
+    assert!(
+        resolve_websocket_endpoint(endpoint, Some("wss://intranet.example.com:8443/socket"))
+            .is_err()
+    );
+    // Or in cleartext
+    assert!(resolve_websocket_endpoint(endpoint, Some("ws://intranet.example.com/ws")).is_err());
+    assert!(
+        resolve_websocket_endpoint(endpoint, Some("http://intranet.example.com:443/ws")).is_err()
+    );
+    assert_eq!(
+        resolve_websocket_endpoint("http://localhost:9000", Some("ws://localhost:9000/ws"))
+            .unwrap(),
+        "ws://localhost:9000/ws"
+    );
+    assert_eq!(
+        resolve_websocket_endpoint(endpoint, Some("realtime")).unwrap(),
+        "wss://intranet.example.com/coco/realtime"
+    );
+    assert_eq!(
+        resolve_websocket_endpoint(endpoint, Some("/realtime")).unwrap(),
+        "wss://intranet.example.com/realtime"
+    );
+    assert_eq!(
+        resolve_websocket_endpoint(
+            "https://intranet.example.com/coco?tenant=a",
+            Some("realtime")
+        )
+        .unwrap(),
+        "wss://intranet.example.com/coco/realtime"
+    );
+}
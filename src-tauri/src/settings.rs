@@ -1,9 +1,11 @@
+use crate::search::fusion::FusionStrategyKind;
 use crate::COCO_TAURI_STORE;
 use serde_json::Value as Json;
 use tauri::{AppHandle, Runtime};
 use tauri_plugin_store::StoreExt;
 
 const SETTINGS_ALLOW_SELF_SIGNATURE: &str = "settings_allow_self_signature";
+const SETTINGS_SEARCH_FUSION_STRATEGY: &str = "settings_search_fusion_strategy";
 
 #[tauri::command]
 pub async fn set_allow_self_signature<R: Runtime>(tauri_app_handle: AppHandle<R>, value: bool) {
@@ -35,8 +37,7 @@ pub async fn set_allow_self_signature<R: Runtime>(tauri_app_handle: AppHandle<R>
 
     store.set(SETTINGS_ALLOW_SELF_SIGNATURE, value);
 
-    let mut guard = http_client::HTTP_CLIENT.lock().await;
-    *guard = http_client::new_reqwest_http_client(value)
+    http_client::clear_server_http_clients();
 }
 
 /// Synchronous version of `async get_allow_self_signature()`.
@@ -70,3 +71,54 @@ pub fn _get_allow_self_signature<R: Runtime>(tauri_app_handle: AppHandle<R>) ->
 pub async fn get_allow_self_signature<R: Runtime>(tauri_app_handle: AppHandle<R>) -> bool {
     _get_allow_self_signature(tauri_app_handle)
 }
+
+/// Synchronous version of `async get_search_fusion_strategy()`.
+pub fn _get_search_fusion_strategy<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+) -> FusionStrategyKind {
+    let store = tauri_app_handle
+        .store(COCO_TAURI_STORE)
+        .unwrap_or_else(|e| {
+            panic!(
+                "store [{}] not found/loaded, error [{}]",
+                COCO_TAURI_STORE, e
+            )
+        });
+
+    match store.get(SETTINGS_SEARCH_FUSION_STRATEGY) {
+        Some(json) => serde_json::from_value(json).unwrap_or_else(|e| {
+            unreachable!(
+                "{} should be stored as a fusion strategy, error [{}]",
+                SETTINGS_SEARCH_FUSION_STRATEGY, e
+            )
+        }),
+        None => FusionStrategyKind::default(),
+    }
+}
+
+#[tauri::command]
+pub async fn get_search_fusion_strategy<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+) -> FusionStrategyKind {
+    _get_search_fusion_strategy(tauri_app_handle)
+}
+
+#[tauri::command]
+pub async fn set_search_fusion_strategy<R: Runtime>(
+    tauri_app_handle: AppHandle<R>,
+    strategy: FusionStrategyKind,
+) {
+    let store = tauri_app_handle
+        .store(COCO_TAURI_STORE)
+        .unwrap_or_else(|e| {
+            panic!(
+                "store [{}] not found/loaded, error [{}]",
+                COCO_TAURI_STORE, e
+            )
+        });
+
+    store.set(
+        SETTINGS_SEARCH_FUSION_STRATEGY,
+        serde_json::to_value(strategy).expect("FusionStrategyKind should be serializable"),
+    );
+}
@@ -0,0 +1,273 @@
+//! Launch history of the applications opened via [`open()`](super::open), used
+//! to rank frequently and recently opened applications higher.
+//!
+//! The ranking resembles Firefox's "frecency": every recent launch contributes
+//! a weight that decays with its age, and the total is extrapolated to the
+//! number of launches.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_store::StoreExt;
+
+const TAURI_STORE_LAUNCH_HISTORY: &str = "launch_history";
+
+/// How many launch timestamps we keep per path.
+const MAX_RECENT_LAUNCHES: usize = 10;
+/// How many paths we keep, the least frecent ones are dropped beyond it.
+const MAX_LAUNCH_RECORDS: usize = 500;
+/// Paths not launched for that long are dropped.
+const LAUNCH_RECORD_TTL_DAYS: i64 = 365;
+
+const DAY_IN_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Launch weights by age, the first bucket whose age limit (in days) is not
+/// exceeded is used.
+const LAUNCH_WEIGHT_BUCKETS: [(i64, f64); 4] = [(4, 100.0), (14, 70.0), (31, 50.0), (90, 30.0)];
+/// Weight of launches older than the last bucket.
+const OLD_LAUNCH_WEIGHT: f64 = 10.0;
+
+/// Frecency at which the boost reaches ~63% of its maximum, roughly a path
+/// launched 5 times in the last few days.
+const FRECENCY_SCALE: f64 = 500.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct LaunchRecord {
+    /// Number of launches, ever.
+    count: u64,
+    /// Timestamps (in milliseconds) of the most recent launches, oldest first.
+    recent: Vec<i64>,
+}
+
+impl LaunchRecord {
+    fn record(&mut self, now: i64) {
+        self.count += 1;
+        self.recent.push(now);
+        if self.recent.len() > MAX_RECENT_LAUNCHES {
+            let excess = self.recent.len() - MAX_RECENT_LAUNCHES;
+            self.recent.drain(..excess);
+        }
+    }
+
+    /// Timestamp (in milliseconds) of the last launch.
+    pub(crate) fn last_launched(&self) -> Option<i64> {
+        self.recent.last().copied()
+    }
+
+    fn frecency(&self, now: i64) -> f64 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+
+        let total_weight: f64 = self
+            .recent
+            .iter()
+            .map(|&launched| launch_weight((now - launched).max(0) / DAY_IN_MILLIS))
+            .sum();
+
+        self.count as f64 * total_weight / self.recent.len() as f64
+    }
+}
+
+fn launch_weight(age_in_days: i64) -> f64 {
+    LAUNCH_WEIGHT_BUCKETS
+        .iter()
+        .find(|(max_age_in_days, _)| age_in_days <= *max_age_in_days)
+        .map(|(_, weight)| *weight)
+        .unwrap_or(OLD_LAUNCH_WEIGHT)
+}
+
+/// How much the launch history matters, relative to the text relevance.
+///
+/// With only a few chars typed, lots of things match equally well, so we rely
+/// more on the history.
+fn boost_weight(query_len: usize) -> f64 {
+    match query_len {
+        0..=2 => 1.0,
+        3..=4 => 0.5,
+        _ => 0.2,
+    }
+}
+
+/// Multiplier applied to the text relevance score, in `[1, 1 + boost_weight)`.
+fn boost(frecency: f64, query_len: usize) -> f64 {
+    let normalized_frecency = 1.0 - (-frecency / FRECENCY_SCALE).exp();
+    1.0 + boost_weight(query_len) * normalized_frecency
+}
+
+fn parse_records(entries: Vec<(String, serde_json::Value)>) -> HashMap<String, LaunchRecord> {
+    entries
+        .into_iter()
+        .filter_map(|(path, json)| {
+            let record = serde_json::from_value::<LaunchRecord>(json).ok()?;
+            Some((path, record))
+        })
+        .collect()
+}
+
+/// The paths of `records` to drop to keep the history bounded: the ones not
+/// launched for [`LAUNCH_RECORD_TTL_DAYS`], then the least frecent ones beyond
+/// [`MAX_LAUNCH_RECORDS`].
+fn paths_to_prune(records: &HashMap<String, LaunchRecord>, now: i64) -> Vec<String> {
+    let mut pruned = Vec::new();
+    let mut kept = Vec::new();
+    for (path, record) in records {
+        let expired = record.last_launched().map_or(true, |launched| {
+            now - launched > LAUNCH_RECORD_TTL_DAYS * DAY_IN_MILLIS
+        });
+        if expired {
+            pruned.push(path.clone());
+        } else {
+            kept.push((path, record.frecency(now)));
+        }
+    }
+
+    if kept.len() > MAX_LAUNCH_RECORDS {
+        kept.sort_by(|(_, frecency_a), (_, frecency_b)| frecency_b.total_cmp(frecency_a));
+        pruned.extend(
+            kept.drain(MAX_LAUNCH_RECORDS..)
+                .map(|(path, _)| path.clone()),
+        );
+    }
+
+    pruned
+}
+
+/// Record a launch of application `path`, and prune the history.
+pub(crate) fn record_launch<R: Runtime>(tauri_app_handle: &AppHandle<R>, path: &str) {
+    let store = match tauri_app_handle.store(TAURI_STORE_LAUNCH_HISTORY) {
+        Ok(store) => store,
+        Err(e) => {
+            warn!(
+                "failed to record the launch of [{}], store [{}] cannot be loaded due to error [{}]",
+                path, TAURI_STORE_LAUNCH_HISTORY, e
+            );
+            return;
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut record = store
+        .get(path)
+        .and_then(|json| serde_json::from_value::<LaunchRecord>(json).ok())
+        .unwrap_or_default();
+    record.record(now);
+
+    store.set(
+        path,
+        serde_json::to_value(record).expect("LaunchRecord should be serializable"),
+    );
+
+    for path in paths_to_prune(&parse_records(store.entries()), now) {
+        store.delete(path);
+    }
+}
+
+/// A snapshot of the launch history.
+pub(crate) struct LaunchHistory {
+    records: HashMap<String, LaunchRecord>,
+    now: i64,
+}
+
+impl LaunchHistory {
+    pub(crate) fn load<R: Runtime>(tauri_app_handle: &AppHandle<R>) -> Self {
+        let records = match tauri_app_handle.store(TAURI_STORE_LAUNCH_HISTORY) {
+            Ok(store) => parse_records(store.entries()),
+            Err(e) => {
+                warn!(
+                    "failed to load store [{}], launch history is ignored, error [{}]",
+                    TAURI_STORE_LAUNCH_HISTORY, e
+                );
+                HashMap::new()
+            }
+        };
+
+        Self {
+            records,
+            now: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    pub(crate) fn get(&self, path: &str) -> Option<&LaunchRecord> {
+        self.records.get(path)
+    }
+
+    /// Apply the launch history of `path` to `score`, the text relevance of
+    /// `path` to a query that is `query_len` chars long.
+    pub(crate) fn boost_score(&self, path: &str, score: f64, query_len: usize) -> f64 {
+        match self.records.get(path) {
+            Some(record) => score * boost(record.frecency(self.now), query_len),
+            None => score,
+        }
+    }
+}
+
+#[test]
+fn test_launch_record_keeps_recent_launches() {
+    let mut record = LaunchRecord::default();
+    for now in 0..(MAX_RECENT_LAUNCHES as i64 + 5) {
+        record.record(now);
+    }
+
+    assert_eq!(record.count, MAX_RECENT_LAUNCHES as u64 + 5);
+    assert_eq!(record.recent.len(), MAX_RECENT_LAUNCHES);
+    assert_eq!(record.last_launched(), Some(MAX_RECENT_LAUNCHES as i64 + 4));
+}
+
+#[test]
+fn test_frecency_prefers_frequent_and_recent() {
+    let now = 365 * DAY_IN_MILLIS;
+    let launched_at = |days_ago: &[i64]| {
+        let mut record = LaunchRecord::default();
+        for days in days_ago {
+            record.record(now - days * DAY_IN_MILLIS);
+        }
+        record
+    };
+
+    let daily = launched_at(&[4, 3, 2, 1, 0]);
+    let once_today = launched_at(&[0]);
+    let long_ago = launched_at(&[200, 199, 198, 197, 196]);
+
+    assert!(daily.frecency(now) > once_today.frecency(now));
+    assert!(daily.frecency(now) > long_ago.frecency(now));
+    assert_eq!(LaunchRecord::default().frecency(now), 0.0);
+}
+
+#[test]
+fn test_boost_matters_more_for_short_queries() {
+    assert_eq!(boost(0.0, 1), 1.0);
+    assert!(boost(500.0, 1) > boost(500.0, 4));
+    assert!(boost(500.0, 4) > boost(500.0, 10));
+    assert!(boost(f64::MAX, 1) <= 2.0);
+}
+
+#[test]
+fn test_paths_to_prune() {
+    let now = 1000 * DAY_IN_MILLIS;
+    let launched_at = |days_ago: i64| {
+        let mut record = LaunchRecord::default();
+        record.record(now - days_ago * DAY_IN_MILLIS);
+        record
+    };
+
+    let mut records = HashMap::new();
+    records.insert(
+        "expired".to_string(),
+        launched_at(LAUNCH_RECORD_TTL_DAYS + 1),
+    );
+    records.insert("never".to_string(), LaunchRecord::default());
+    assert_eq!(paths_to_prune(&records, now).len(), 2);
+
+    let mut records: HashMap<String, LaunchRecord> = (0..MAX_LAUNCH_RECORDS)
+        .map(|i| (i.to_string(), launched_at(0)))
+        .collect();
+    assert!(paths_to_prune(&records, now).is_empty());
+
+    records.insert("least frecent".to_string(), launched_at(100));
+    assert_eq!(
+        paths_to_prune(&records, now),
+        vec!["least frecent".to_string()]
+    );
+}
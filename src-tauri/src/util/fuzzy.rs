@@ -0,0 +1,274 @@
+//! Fuzzy matching for short, user-typed queries against short candidates, e.g.,
+//! application names.
+//!
+//! From the best to the worst, a candidate can match a query by:
+//!
+//! 1. being equal to it
+//! 2. starting with it
+//! 3. containing a word that starts with it
+//! 4. containing it
+//! 5. containing all its characters in order (subsequence), e.g., "vsc" matches "Visual Studio Code"
+//! 6. being within a small edit distance of it (typos)
+
+const SCORE_EXACT: f64 = 100.0;
+const SCORE_PREFIX: f64 = 90.0;
+const SCORE_WORD_PREFIX: f64 = 80.0;
+const SCORE_SUBSTRING: f64 = 70.0;
+const SCORE_SUBSEQUENCE_MIN: f64 = 40.0;
+const SCORE_SUBSEQUENCE_MAX: f64 = 60.0;
+const SCORE_ONE_TYPO: f64 = 30.0;
+/// Every extra typo costs this much.
+const SCORE_TYPO_PENALTY: f64 = 10.0;
+
+/// Score how well `query` matches `candidate`, case-insensitively.
+///
+/// Returns `None` if they do not match at all, otherwise a score in `(0, 100]`,
+/// higher is better.
+pub(crate) fn fuzzy_score(query: &str, candidate: &str) -> Option<f64> {
+    let query: Vec<char> = query.trim().chars().map(to_lowercase).collect();
+    let original_candidate: Vec<char> = candidate.trim().chars().collect();
+    let candidate: Vec<char> = original_candidate
+        .iter()
+        .copied()
+        .map(to_lowercase)
+        .collect();
+
+    if query.is_empty() || candidate.is_empty() {
+        return None;
+    }
+
+    if query == candidate {
+        return Some(SCORE_EXACT);
+    }
+
+    // How much of the candidate is covered by the query, in `(0, 1)`, so that
+    // among the candidates of the same kind, shorter ones win.
+    let coverage = query.len() as f64 / candidate.len().max(query.len()) as f64;
+
+    if candidate.starts_with(&query) {
+        return Some(SCORE_PREFIX + 9.0 * coverage);
+    }
+
+    let word_starts = word_starts(&original_candidate);
+    if word_starts
+        .iter()
+        .any(|&start| candidate[start..].starts_with(&query))
+    {
+        return Some(SCORE_WORD_PREFIX + 9.0 * coverage);
+    }
+
+    if candidate
+        .windows(query.len())
+        .any(|window| window == query.as_slice())
+    {
+        return Some(SCORE_SUBSTRING + 9.0 * coverage);
+    }
+
+    if let Some(quality) = subsequence_quality(&query, &candidate, &word_starts) {
+        return Some(
+            SCORE_SUBSEQUENCE_MIN + (SCORE_SUBSEQUENCE_MAX - SCORE_SUBSEQUENCE_MIN) * quality,
+        );
+    }
+
+    let max_typos = max_typos(query.len());
+    if max_typos == 0 {
+        return None;
+    }
+    let typos = min_typos(&query, &candidate, &word_starts);
+    if typos > max_typos {
+        return None;
+    }
+
+    Some(SCORE_ONE_TYPO - SCORE_TYPO_PENALTY * (typos - 1) as f64 + coverage)
+}
+
+/// Lowercase a char without changing the length of the string, so that indexes
+/// into the original and the lowercased string stay in sync.
+fn to_lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// The more characters are typed, the more typos we tolerate.
+fn max_typos(query_len: usize) -> usize {
+    match query_len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Indexes of the chars that start a word, words are separated by
+/// non-alphanumeric chars or camel case, e.g., "WeChat" and "VS-Code".
+fn word_starts(candidate: &[char]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    for (idx, &c) in candidate.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            continue;
+        }
+
+        let is_start = match idx.checked_sub(1).map(|prev_idx| candidate[prev_idx]) {
+            None => true,
+            Some(prev) => !prev.is_alphanumeric() || (prev.is_lowercase() && c.is_uppercase()),
+        };
+        if is_start {
+            starts.push(idx);
+        }
+    }
+
+    starts
+}
+
+/// If `query` is a subsequence of `candidate`, return how good the match is,
+/// in `[0, 1]`. Matched chars that are consecutive or that start a word make a
+/// better match.
+fn subsequence_quality(query: &[char], candidate: &[char], word_starts: &[usize]) -> Option<f64> {
+    let mut points = 0;
+    let mut candidate_idx = 0;
+    let mut last_matched_idx: Option<usize> = None;
+
+    for query_char in query {
+        let matched_idx = candidate[candidate_idx..]
+            .iter()
+            .position(|c| c == query_char)?
+            + candidate_idx;
+
+        points += 1;
+        if last_matched_idx.is_some_and(|last| last + 1 == matched_idx) {
+            points += 1;
+        }
+        if word_starts.contains(&matched_idx) {
+            points += 1;
+        }
+
+        last_matched_idx = Some(matched_idx);
+        candidate_idx = matched_idx + 1;
+    }
+
+    // The first char cannot be consecutive, so this is slightly pessimistic,
+    // which is fine.
+    Some(points as f64 / (3 * query.len()) as f64)
+}
+
+/// The minimum number of typos needed to turn `query` into the candidate, one
+/// of its words, or a prefix of either (the user may still be typing).
+fn min_typos(query: &[char], candidate: &[char], word_starts: &[usize]) -> usize {
+    let mut min = usize::MAX;
+
+    let starts = std::iter::once(0).chain(word_starts.iter().copied());
+    for start in starts {
+        let word = &candidate[start..];
+        let word_end = word
+            .iter()
+            .position(|c| !c.is_alphanumeric())
+            .unwrap_or(word.len());
+
+        for target in [word, &word[..word_end]] {
+            min = min.min(damerau_levenshtein(query, target));
+
+            // Prefixes that are a bit shorter/longer than the query
+            let prefix_lens = query.len().saturating_sub(1)..=query.len() + 1;
+            for prefix_len in prefix_lens {
+                if prefix_len > 0 && prefix_len < target.len() {
+                    min = min.min(damerau_levenshtein(query, &target[..prefix_len]));
+                }
+            }
+        }
+    }
+
+    min
+}
+
+/// Edit distance where insertions, deletions, substitutions and transpositions
+/// of adjacent chars all count as one edit (the "optimal string alignment" variant).
+fn damerau_levenshtein(a: &[char], b: &[char]) -> usize {
+    let width = b.len() + 1;
+    let mut distances = vec![0; (a.len() + 1) * width];
+    let idx = |i: usize, j: usize| i * width + j;
+
+    for i in 0..=a.len() {
+        distances[idx(i, 0)] = i;
+    }
+    for j in 0..=b.len() {
+        distances[idx(0, j)] = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[idx(i - 1, j)] + 1)
+                .min(distances[idx(i, j - 1)] + 1)
+                .min(distances[idx(i - 1, j - 1)] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[idx(i - 2, j - 2)] + 1);
+            }
+
+            distances[idx(i, j)] = distance;
+        }
+    }
+
+    distances[idx(a.len(), b.len())]
+}
+
+#[test]
+fn test_fuzzy_score_ranks_match_kinds() {
+    let exact = fuzzy_score("safari", "Safari").unwrap();
+    let prefix = fuzzy_score("saf", "Safari").unwrap();
+    let word_prefix = fuzzy_score("studio", "Visual Studio Code").unwrap();
+    let substring = fuzzy_score("fari", "Safari").unwrap();
+    let subsequence = fuzzy_score("vsc", "Visual Studio Code").unwrap();
+    let typo = fuzzy_score("fierfox", "Firefox").unwrap();
+
+    assert_eq!(exact, 100.0);
+    assert!(exact > prefix);
+    assert!(prefix > word_prefix);
+    assert!(word_prefix > substring);
+    assert!(substring > subsequence);
+    assert!(subsequence > typo);
+    assert!(typo > 0.0);
+}
+
+#[test]
+fn test_fuzzy_score_word_boundaries() {
+    // camel case starts a new word
+    assert!(fuzzy_score("chat", "WeChat").unwrap() >= SCORE_WORD_PREFIX);
+    // Matching word starts is better than matching scattered chars
+    assert!(
+        fuzzy_score("vsc", "Visual Studio Code").unwrap()
+            > fuzzy_score("isd", "Visual Studio Code").unwrap()
+    );
+}
+
+#[test]
+fn test_fuzzy_score_typos() {
+    // transposition while still typing
+    assert!(fuzzy_score("fierf", "Firefox").is_some());
+    // missing char
+    assert!(fuzzy_score("termnal", "Terminal").is_some());
+    // short queries do not tolerate typos
+    assert!(fuzzy_score("sfa", "Firefox").is_none());
+    // too many typos
+    assert!(fuzzy_score("qwerty", "Firefox").is_none());
+}
+
+#[test]
+fn test_fuzzy_score_no_match() {
+    assert!(fuzzy_score("", "Firefox").is_none());
+    assert!(fuzzy_score("firefox", "").is_none());
+    assert!(fuzzy_score("xyz", "Safari").is_none());
+}
+
+#[test]
+fn test_damerau_levenshtein() {
+    let distance = |a: &str, b: &str| {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        damerau_levenshtein(&a, &b)
+    };
+
+    assert_eq!(distance("", ""), 0);
+    assert_eq!(distance("abc", ""), 3);
+    assert_eq!(distance("abc", "abc"), 0);
+    assert_eq!(distance("abc", "acb"), 1);
+    assert_eq!(distance("kitten", "sitting"), 3);
+}
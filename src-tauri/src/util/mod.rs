@@ -1,3 +1,6 @@
+pub mod frecency;
+pub mod fuzzy;
+
 use std::{path::Path, process::Command};
 use tauri::{AppHandle, Runtime};
 use tauri_plugin_shell::ShellExt;
@@ -64,13 +67,22 @@ fn get_linux_desktop_environment() -> Option<LinuxDesktopEnvironment> {
 }
 
 /// Homemade open() function to support open Linux applications via the `.desktop` file.
-//
-// tauri_plugin_shell::open() is deprecated, but we still use it.
-#[allow(deprecated)]
+///
+/// Successful launches of applications are recorded in the launch history, see
+/// [`frecency`].
 #[tauri::command]
 pub async fn open<R: Runtime>(app_handle: AppHandle<R>, path: String) -> Result<(), String> {
+    launch(&app_handle, &path)?;
+    crate::local::application::record_app_launch(&app_handle, &path).await;
+
+    Ok(())
+}
+
+// tauri_plugin_shell::open() is deprecated, but we still use it.
+#[allow(deprecated)]
+fn launch<R: Runtime>(app_handle: &AppHandle<R>, path: &str) -> Result<(), String> {
     if cfg!(target_os = "linux") {
-        let borrowed_path = Path::new(&path);
+        let borrowed_path = Path::new(path);
         if let Some(file_extension) = borrowed_path.extension() {
             if file_extension == "desktop" {
                 let desktop_environment = get_linux_desktop_environment().expect("The Linux OS is running without a desktop, Coco could never run in such a environment");
@@ -14,9 +14,22 @@ import {
   TranscriptionPayload,
   TranscriptionResponse,
   MultiSourceQueryResponse,
+  CocoError,
+  SsoLogin,
+  TlsConfig,
+  HttpConfig,
 } from "@/types/commands";
 import { useAppStore } from "@/stores/appStore";
 
+export function isCocoError(error: unknown): error is CocoError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    "kind" in error &&
+    "message" in error
+  );
+}
+
 async function invokeWithErrorHandler<T>(
   command: string,
   args?: Record<string, any>
@@ -45,7 +58,8 @@ async function invokeWithErrorHandler<T>(
 
     return result;
   } catch (error: any) {
-    const errorMessage = error || "Command execution failed";
+    const errorMessage =
+      (isCocoError(error) ? error.message : error) || "Command execution failed";
     addError(command + ":" + errorMessage, "error");
     throw error;
   }
@@ -59,8 +73,26 @@ export function list_coco_servers(): Promise<Server[]> {
   return invokeWithErrorHandler(`list_coco_servers`);
 }
 
-export function add_coco_server(endpoint: string): Promise<Server> {
-  return invokeWithErrorHandler(`add_coco_server`, { endpoint });
+export function add_coco_server(
+  endpoint: string,
+  http?: HttpConfig,
+  tls?: TlsConfig
+): Promise<Server> {
+  return invokeWithErrorHandler(`add_coco_server`, { endpoint, http, tls });
+}
+
+export function set_server_tls_config(
+  id: string,
+  tls: TlsConfig
+): Promise<Server> {
+  return invokeWithErrorHandler(`set_server_tls_config`, { id, tls });
+}
+
+export function set_server_http_config(
+  id: string,
+  http: HttpConfig
+): Promise<Server> {
+  return invokeWithErrorHandler(`set_server_http_config`, { id, http });
 }
 
 export function enable_server(id: string): Promise<void> {
@@ -83,6 +115,10 @@ export function refresh_coco_server_info(id: string): Promise<Server> {
   return invokeWithErrorHandler(`refresh_coco_server_info`, { id });
 }
 
+export function start_sso_login(serverId: string): Promise<SsoLogin> {
+  return invokeWithErrorHandler(`start_sso_login`, { serverId });
+}
+
 export function handle_sso_callback({
   serverId,
   requestId,
@@ -99,6 +135,32 @@ export function handle_sso_callback({
   });
 }
 
+export function login_with_token({
+  serverId,
+  token,
+}: {
+  serverId: string;
+  token: string;
+}): Promise<Server> {
+  return invokeWithErrorHandler(`login_with_token`, { serverId, token });
+}
+
+export function login_with_password({
+  serverId,
+  username,
+  password,
+}: {
+  serverId: string;
+  username: string;
+  password: string;
+}): Promise<Server> {
+  return invokeWithErrorHandler(`login_with_password`, {
+    serverId,
+    username,
+    password,
+  });
+}
+
 export function get_connectors_by_server(id: string): Promise<Connector[]> {
   return invokeWithErrorHandler(`get_connectors_by_server`, { id });
 }
@@ -157,6 +219,29 @@ export function session_chat_history({
   });
 }
 
+/**
+ * Export all the messages of a session, with the metadata of its attachments,
+ * to `path`, e.g., one picked with the save dialog.
+ */
+export function export_session_chat({
+  serverId,
+  sessionId,
+  format,
+  path,
+}: {
+  serverId: string;
+  sessionId: string;
+  format: "markdown" | "json" | "html";
+  path: string;
+}): Promise<void> {
+  return invokeWithErrorHandler(`export_session_chat`, {
+    serverId,
+    sessionId,
+    format,
+    path,
+  });
+}
+
 export function close_session_chat({
   serverId,
   sessionId,
@@ -291,6 +376,7 @@ export const transcription = (payload: TranscriptionPayload) => {
 };
 
 export const query_coco_fusion = (payload: {
+  queryId?: string;
   from: number;
   size: number;
   queryStrings: Record<string, string>;
@@ -300,3 +386,52 @@ export const query_coco_fusion = (payload: {
     ...payload,
   });
 };
+
+/**
+ * Streaming version of `query_coco_fusion`, results are delivered via events:
+ * `query-hits-${queryId}` (a `PartialQueryResponse` per source) and
+ * `query-done-${queryId}` (the final `MultiSourceQueryResponse`).
+ */
+export const query_coco_fusion_stream = (payload: {
+  queryId: string;
+  from: number;
+  size: number;
+  queryStrings: Record<string, string>;
+  queryTimeout: number;
+}) => {
+  return invokeWithErrorHandler<void>("query_coco_fusion_stream", {
+    ...payload,
+  });
+};
+
+export const cancel_query = (queryId: string) => {
+  return invokeWithErrorHandler<void>("cancel_query", { queryId });
+};
+
+/**
+ * Record that `document` has been opened from the results of `query`, the
+ * queries themselves are recorded when executed. Nothing is recorded in
+ * incognito mode.
+ */
+export const add_search_history = (query: string, document?: any) => {
+  return invokeWithErrorHandler<void>("add_search_history", {
+    query,
+    document,
+  });
+};
+
+/**
+ * Remove the cached search results of server `serverId`, or of all the servers
+ * if it is not specified. Cached hits have `metadata.cached` set to `true`.
+ */
+export const purge_search_cache = (serverId?: string) => {
+  return invokeWithErrorHandler<void>("purge_search_cache", { serverId });
+};
+
+/**
+ * Send a text message to the server over the WebSocket connection of
+ * `clientId`, e.g., to cancel or acknowledge a reply.
+ */
+export const ws_send = (clientId: string, message: string) => {
+  return invokeWithErrorHandler<void>("ws_send", { clientId, message });
+};
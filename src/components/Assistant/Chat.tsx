@@ -19,7 +19,7 @@ import { ChatSidebar } from "./ChatSidebar";
 import { ChatHeader } from "./ChatHeader";
 import { ChatContent } from "./ChatContent";
 import ConnectPrompt from "./ConnectPrompt";
-import type { Chat } from "@/types/chat";
+import type { Chat, IChunkData } from "@/types/chat";
 import PrevSuggestion from "@/components/ChatMessage/PrevSuggestion";
 import { useAppStore } from "@/stores/appStore";
 // import ReadAloud from "./ReadAloud";
@@ -127,7 +127,7 @@ const ChatAI = memo(
         response: false,
       });
 
-      const dealMsgRef = useRef<((msg: string) => void) | null>(null);
+      const dealMsgRef = useRef<((chunk: IChunkData) => void) | null>(null);
 
       const clientId = isChatPage ? "standalone" : "popup";
       const { reconnect, updateDealMsg } = useWebSocket({
@@ -1,8 +1,7 @@
 import { memo, useCallback, useEffect, useState } from "react";
 import { Copy } from "lucide-react";
 import { useTranslation } from "react-i18next";
-import { v4 as uuidv4 } from "uuid";
-import { emit } from "@tauri-apps/api/event";
+import { emit, listen } from "@tauri-apps/api/event";
 import {
   getCurrent as getCurrentDeepLinkUrls,
   onOpenUrl,
@@ -13,7 +12,11 @@ import { UserProfile } from "./UserProfile";
 import { OpenURLWithBrowser } from "@/utils";
 import { useConnectStore } from "@/stores/connectStore";
 import { useAppStore } from "@/stores/appStore";
-import { logout_coco_server, handle_sso_callback } from "@/commands";
+import {
+  logout_coco_server,
+  handle_sso_callback,
+  start_sso_login,
+} from "@/commands";
 
 interface ServiceAuthProps {
   setRefreshLoading: (loading: boolean) => void;
@@ -33,23 +36,21 @@ const ServiceAuth = memo(
 
     const [loading, setLoading] = useState(false);
 
-    const LoginClick = useCallback(() => {
-      if (loading) return; // Prevent multiple clicks if already loading
-
-      let requestID = uuidv4();
-      setSSORequestID(requestID);
+    const LoginClick = useCallback(async () => {
+      if (loading || !currentService?.id) return; // Prevent multiple clicks if already loading
 
-      // Generate the login URL with the current appUid
-      const url = `${currentService?.auth_provider?.sso?.url}/?provider=${currentService?.id}&product=coco&request_id=${requestID}`;
+      // The login URL, with its request ID and PKCE code challenge, is generated by the backend
+      const { request_id, url } = await start_sso_login(currentService.id);
+      setSSORequestID(request_id);
 
-      console.log("Open SSO link, requestID:", ssoRequestID, url);
+      console.log("Open SSO link, requestID:", request_id, url);
 
       // Open the URL in a browser
       OpenURLWithBrowser(url);
 
       // Start loading state
       setLoading(true);
-    }, [ssoRequestID, loading, currentService]);
+    }, [loading, currentService]);
 
     const onLogout = useCallback(
       (id: string) => {
@@ -164,6 +165,18 @@ const ServiceAuth = memo(
       };
     }, [ssoRequestID]);
 
+    // The backend forgets expired tokens, ask the user to log in again
+    useEffect(() => {
+      const unlisten = listen<string>("server-auth-expired", ({ payload }) => {
+        addError(t("cloud.loginExpired"), "warning");
+        refreshClick(payload);
+      });
+
+      return () => {
+        unlisten.then((fn) => fn());
+      };
+    }, [refreshClick]);
+
     if (!currentService?.auth_provider?.sso?.url) {
       return null;
     }
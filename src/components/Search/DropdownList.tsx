@@ -18,7 +18,7 @@ import IconWrapper from "@/components/Common/Icons/IconWrapper";
 import CommonIcon from "@/components/Common/Icons/CommonIcon";
 import SearchListItem from "./SearchListItem";
 import { metaOrCtrlKey, isMetaOrCtrlKey } from "@/utils/keyboardUtils";
-import { copyToClipboard, OpenURLWithBrowser } from "@/utils/index";
+import { copyToClipboard, OpenSearchResult } from "@/utils/index";
 import VisibleKey from "@/components/Common/VisibleKey";
 import Calculator from "./Calculator";
 import { useShortcutsStore } from "@/stores/shortcutsStore";
@@ -31,6 +31,7 @@ import { useThemeStore } from "@/stores/themeStore";
 type ISearchData = Record<string, any[]>;
 
 interface DropdownListProps {
+  input: string;
   suggests: any[];
   searchData: ISearchData;
   isError: any[];
@@ -39,6 +40,7 @@ interface DropdownListProps {
 }
 
 function DropdownList({
+  input,
   suggests,
   searchData,
   isError,
@@ -139,7 +141,7 @@ function DropdownList({
         // console.log("Enter key pressed", selectedItem);
         const item = globalItemIndexMap[selectedItem];
         if (item?.url) {
-          OpenURLWithBrowser(item?.url);
+          OpenSearchResult(input, item);
         } else {
           copyToClipboard(item?.payload?.result?.value);
         }
@@ -153,11 +155,11 @@ function DropdownList({
         const item = globalItemIndexMap[index];
 
         if (item?.url) {
-          OpenURLWithBrowser(item?.url);
+          OpenSearchResult(input, item);
         }
       }
     },
-    [suggests, selectedItem, showIndex, globalItemIndexMap, openPopover]
+    [input, suggests, selectedItem, showIndex, globalItemIndexMap, openPopover]
   );
 
   const handleKeyUp = useCallback((e: KeyboardEvent) => {
@@ -207,14 +209,10 @@ function DropdownList({
   const memoizedCallbacks = useMemo(() => {
     return {
       onMouseEnter: (index: number) => () => setSelectedItem(index),
-      onItemClick: (item: any) => () => {
-        if (item?.url) {
-          OpenURLWithBrowser(item.url);
-        }
-      },
+      onItemClick: (item: any) => () => OpenSearchResult(input, item),
       goToTwoPage: (item: any) => () => setSourceData(item),
     };
-  }, []);
+  }, [input]);
 
   const showHeader = useMemo(
     () => Object.entries(searchData).length < 5,
@@ -303,11 +301,7 @@ function DropdownList({
                       onMouseEnter={memoizedCallbacks.onMouseEnter(
                         currentIndex
                       )}
-                      onItemClick={() => {
-                        if (item?.url) {
-                          OpenURLWithBrowser(item?.url);
-                        }
-                      }}
+                      onItemClick={() => OpenSearchResult(input, item)}
                       goToTwoPage={() => goToTwoPage(item)}
                       itemRef={(el) => (itemRefs.current[currentIndex] = el)}
                     />
@@ -141,6 +141,7 @@ function Search({
           <SearchResults input={input} isChatMode={isChatMode} />
         ) : (
           <DropdownList
+            input={input}
             suggests={suggests}
             searchData={searchData}
             isError={isError}
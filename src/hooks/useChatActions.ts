@@ -342,6 +342,23 @@ export function useChatActions(
     showChatHistory && connected && getChatHistory();
   }, [showChatHistory, connected, getChatHistory]);
 
+  // The mirror of the chat history has been synced in the background
+  useEffect(() => {
+    if (!isTauri || !showChatHistory) return;
+
+    const unlisten = platformAdapter.listenEvent(
+      "chat-history-synced",
+      ({ payload }) => {
+        if (payload.server_id !== currentServiceId) return;
+        getChatHistory();
+      }
+    );
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [isTauri, showChatHistory, currentServiceId, getChatHistory]);
+
   const createChatWindow = useCallback(async (createWin: any) => {
     if (isTauri) {
       createWin &&
@@ -27,23 +27,18 @@ export function useMessageHandler(
   const connectionTimeout = useConnectStore((state) => state.connectionTimeout);
 
   const dealMsg = useCallback(
-    (msg: string) => {
+    (chunkData: IChunkData) => {
       if (messageTimeoutRef.current) {
         clearTimeout(messageTimeoutRef.current);
       }
 
-      if (!msg.includes("PRIVATE")) return;
-
       messageTimeoutRef.current = setTimeout(() => {
         console.log("AI response timeout");
         setTimedoutShow(true);
         onCancel();
       }, (connectionTimeout ?? 120) * 1000);
 
-      const cleanedData = msg.replace(/^PRIVATE /, "");
       try {
-        const chunkData = JSON.parse(cleanedData);
-
         if (chunkData.reply_to_message !== curIdRef.current) return;
 
         setLoadingStep(() => ({
@@ -81,7 +76,7 @@ export function useMessageHandler(
         }
       } catch (error) {
         setCurChatEnd(true);
-        console.error("parse error:", error);
+        console.error("failed to handle chunk:", error);
       }
     },
     [
@@ -4,6 +4,7 @@ import { useWebSocket as useWebSocketAHook } from "ahooks";
 import { useAppStore } from "@/stores/appStore";
 import platformAdapter from "@/utils/platformAdapter";
 import { Server } from "@/types/server";
+import { IChunkData } from "@/types/chat";
 
 enum ReadyState {
   Connecting = 0,
@@ -17,7 +18,7 @@ interface WebSocketProps {
   connected: boolean;
   setConnected: (connected: boolean) => void;
   currentService: Server | null;
-  dealMsgRef: React.MutableRefObject<((msg: string) => void) | null>;
+  dealMsgRef: React.MutableRefObject<((chunk: IChunkData) => void) | null>;
   onWebsocketSessionId?: (sessionId: string) => void;
 }
 
@@ -67,8 +68,9 @@ export default function useWebSocket({
           setConnected(true); // web connected
           console.log("setConnected:", sessionId);
           onWebsocketSessionId?.(sessionId);
-        } else {
-          dealMsgRef.current?.(msg);
+        } else if (msg.startsWith("PRIVATE ")) {
+          // In Tauri, chunks are parsed by the backend
+          dealMsgRef.current?.(JSON.parse(msg.replace(/^PRIVATE /, "")));
         }
       } catch (error) {
         console.error("Error processing message:", error, msg);
@@ -135,7 +137,7 @@ export default function useWebSocket({
   }, [connected]);
 
   const updateDealMsg = useCallback(
-    (newDealMsg: (msg: string) => void) => {
+    (newDealMsg: (chunk: IChunkData) => void) => {
       dealMsgRef.current = newDealMsg;
     },
     [dealMsgRef]
@@ -144,7 +146,10 @@ export default function useWebSocket({
     if (!currentService?.id) return;
 
     let unlisten_error = null;
-    let unlisten_message = null;
+    let unlisten_session = null;
+    let unlisten_chunk = null;
+    let unlisten_tool_call = null;
+    let unlisten_status = null;
 
     if (!isTauri) return;
 
@@ -156,25 +161,33 @@ export default function useWebSocket({
       setConnected(false); // error
     });
 
-    unlisten_message = platformAdapter.listenEvent(`ws-message-${clientId}`, (event) => {
-      const msg = event.payload as string;
-      // console.log(`ws-message-${clientId}`, msg);
-      if (msg.includes("websocket-session-id")) {
-        const sessionId = msg.split(":")[1].trim();
-        websocketIdRef.current = sessionId;
-        console.log("setConnected sessionId:", sessionId);
-        setConnected(true); // Tauri connected
-        if (onWebsocketSessionId) {
-          onWebsocketSessionId(sessionId);
-        }
-        return;
-      }
-      dealMsgRef.current && dealMsgRef.current(msg);
+    unlisten_status = platformAdapter.listenEvent(`ws-status-${clientId}`, (event) => {
+      // The connection is re-established in the background
+      setConnected(event.payload.state === "reconnected");
+    });
+
+    unlisten_session = platformAdapter.listenEvent(`ws-session-${clientId}`, (event) => {
+      const sessionId = event.payload;
+      websocketIdRef.current = sessionId;
+      console.log("setConnected sessionId:", sessionId);
+      setConnected(true); // Tauri connected
+      onWebsocketSessionId?.(sessionId);
+    });
+
+    unlisten_chunk = platformAdapter.listenEvent(`ws-chat-chunk-${clientId}`, (event) => {
+      dealMsgRef.current?.(event.payload);
+    });
+
+    unlisten_tool_call = platformAdapter.listenEvent(`ws-tool-call-${clientId}`, (event) => {
+      dealMsgRef.current?.(event.payload);
     });
 
     return () => {
       unlisten_error?.then((fn: any) => fn());
-      unlisten_message?.then((fn: any) => fn());
+      unlisten_session?.then((fn: any) => fn());
+      unlisten_chunk?.then((fn: any) => fn());
+      unlisten_tool_call?.then((fn: any) => fn());
+      unlisten_status?.then((fn: any) => fn());
     };
   }, [dealMsgRef]);
 
@@ -84,6 +84,56 @@ export default function Chat({}: ChatProps) {
     }
   };
 
+  const activeChatRef = useRef<typeChat>();
+  activeChatRef.current = activeChat;
+
+  // The mirror of the chat history has been synced in the background
+  useEffect(() => {
+    const unlisten = platformAdapter.listenEvent(
+      "chat-history-synced",
+      ({ payload }) => {
+        if (payload.server_id !== currentService?.id) return;
+        refreshChatHistory(payload.session_ids, payload.removed_session_ids);
+      }
+    );
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [currentService?.id, keyword]);
+
+  const refreshChatHistory = async (
+    sessionIds: string[],
+    removedSessionIds: string[]
+  ) => {
+    try {
+      let response: any = await chat_history({
+        serverId: currentService?.id,
+        from: 0,
+        size: 100,
+        query: keyword,
+      });
+      response = response ? JSON.parse(response) : null;
+      const hits: typeChat[] = response?.hits?.hits || [];
+      setChats(hits);
+
+      const active = activeChatRef.current;
+      if (!active?._id) return;
+      if (removedSessionIds.includes(active._id)) {
+        if (hits[0]) {
+          onSelectChat(hits[0]);
+        } else {
+          setActiveChat(undefined);
+          chatAIRef.current?.init("");
+        }
+      } else if (sessionIds.includes(active._id)) {
+        chatHistory(active);
+      }
+    } catch (error) {
+      console.error("chat_history:", error);
+    }
+  };
+
   const deleteChat = (chatId: string) => {
     handleDelete(chatId);
 
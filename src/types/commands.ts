@@ -47,6 +47,29 @@ interface UserProfile {
   preferences: Preferences;
 }
 
+export interface TlsConfig {
+  ca_cert_pem?: string;
+  client_cert_pem?: string;
+  // Write only, it is never sent back by the backend
+  client_key_pem?: string;
+  pinned_cert_sha256?: string;
+}
+
+export type ProxyConfig =
+  | { mode: "system" }
+  | { mode: "none" }
+  | { mode: "custom"; url: string; no_proxy?: string };
+
+// Timeouts are in seconds
+export interface HttpConfig {
+  connect_timeout: number;
+  read_timeout: number;
+  request_timeout: number;
+  upload_timeout: number;
+  proxy: ProxyConfig;
+  default_headers: Record<string, string>;
+}
+
 export interface Server {
   id: string;
   builtin: boolean;
@@ -63,6 +86,10 @@ export interface Server {
   profile?: UserProfile;
   auth_provider: AuthProvider;
   priority: number;
+  tls: TlsConfig;
+  http: HttpConfig;
+  // Advertised by the server, overrides the one derived from `endpoint`
+  websocket_endpoint?: string;
 }
 
 interface ConnectorAssets {
@@ -182,6 +209,12 @@ export interface MultiSourceQueryResponse {
   total_hits: number;
 }
 
+export interface PartialQueryResponse {
+  source: QuerySource;
+  hits: QueryHits[];
+  total_hits: number;
+}
+
 export interface FailedRequest {
   source: QuerySource;
   status: number;
@@ -200,3 +233,25 @@ export interface QueryHits {
   score: number;
   document: any;
 }
+
+export type CocoErrorKind =
+  | "network"
+  | "tls"
+  | "auth_expired"
+  | "server_error"
+  | "not_found"
+  | "parse"
+  | "timeout"
+  | "internal";
+
+export interface CocoError {
+  kind: CocoErrorKind;
+  message: string;
+  status?: number;
+  reason?: string;
+}
+
+export interface SsoLogin {
+  request_id: string;
+  url: string;
+}
@@ -3,6 +3,7 @@ import { IConnectStore } from "@/stores/connectStore";
 import { IShortcutsStore } from "@/stores/shortcutsStore";
 import { IStartupStore } from "@/stores/startupStore";
 import { AppTheme } from "@/types/index";
+import { IChunkData } from "@/types/chat";
 
 export interface EventPayloads {
   "language-changed": {
@@ -36,6 +37,19 @@ export interface EventPayloads {
     status: number;
   };
   [key: `ws-message-${string}`]: string;
+  [key: `ws-session-${string}`]: string;
+  [key: `ws-chat-chunk-${string}`]: IChunkData;
+  [key: `ws-tool-call-${string}`]: IChunkData;
+  [key: `ws-notification-${string}`]: Record<string, unknown>;
+  [key: `ws-heartbeat-${string}`]: void;
+  "chat-history-synced": {
+    server_id: string;
+    session_ids: string[];
+    removed_session_ids: string[];
+  };
+  [key: `ws-status-${string}`]:
+    | { state: "reconnecting"; attempt: number; delay_ms: number }
+    | { state: "reconnected"; session_id?: string; resumed: boolean };
   "change-startup-store": IStartupStore;
   "change-shortcuts-store": IShortcutsStore;
   "change-connect-store": IConnectStore;
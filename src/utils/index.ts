@@ -83,6 +83,19 @@ export const OpenURLWithBrowser = async (url?: string) => {
   }
 };
 
+/**
+ * Open `document`, a hit of `query`, and record it in the search history.
+ */
+export const OpenSearchResult = async (query: string, document?: any) => {
+  if (!document?.url) return;
+  if (IsTauri()) {
+    platformAdapter
+      .commands("add_search_history", query, document)
+      .catch((error) => console.error("Failed to record search history:", error));
+  }
+  await OpenURLWithBrowser(document.url);
+};
+
 const unitArr = ["B", "KB", "MB", "GB", "TB", "PB", "EB", "ZB", "YB"] as const;
 
 export const formatter = {